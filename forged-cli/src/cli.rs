@@ -1,5 +1,15 @@
-use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+use clap::{ArgEnum, Args, Parser, Subcommand};
+use forged::{config::ResetBehavior, logs::parser::Preset, runs::RunRef};
+use probe_rs::{DebugProbeSelector, WireProtocol};
 use serde_json::Value;
+use uuid::Uuid;
+
+use crate::{
+    functions::{device::ListFormat, report::ReportFormat, start::IdentifierFormat},
+    output::OutputFormat,
+};
 
 /// Doc comment
 #[derive(Parser)]
@@ -15,6 +25,19 @@ pub struct Cli {
     #[clap(long)]
     pub api_instance_url: Option<String>,
 
+    /// The configuration profile to use, e.g. the station name.
+    #[clap(long, global = true)]
+    pub profile: Option<String>,
+
+    /// The format of the command results printed to STDOUT. Progress messages go to STDERR.
+    #[clap(long, global = true, arg_enum, default_value = "text")]
+    pub output: OutputFormat,
+
+    /// The format of logged lines, overriding the `log` section of the profile. One of `default`,
+    /// `env-logger`, `syslog`, `defmt-print`, `pytest` or `zephyr`.
+    #[clap(long, global = true)]
+    pub log_preset: Option<Preset>,
+
     #[clap(subcommand)]
     pub command: Command,
 }
@@ -22,30 +45,545 @@ pub struct Cli {
 /// Doc comment
 #[derive(Subcommand)]
 pub enum Command {
-    /// Creates a new device.
-    Start,
+    /// Creates a new device, or resumes an existing device by its identifier.
+    Start {
+        /// The identifier of an existing device whose history should be continued.
+        #[clap(
+            long,
+            requires = "station",
+            conflicts_with_all = &["identifier-from-stdin", "identifier-from-device"]
+        )]
+        identifier: Option<String>,
+
+        /// Read the device identifier as a single line from STDIN (e.g. a barcode scanner).
+        #[clap(long, requires = "station", conflicts_with = "identifier-from-device")]
+        identifier_from_stdin: bool,
+
+        /// Read the device identifier from the memory of the connected target.
+        #[clap(long, requires = "station")]
+        identifier_from_device: bool,
+
+        /// How the identifier memory is decoded. Defaults to the type named by the contract of the
+        /// identifier schema, e.g. `u32` or `string`.
+        #[clap(long, arg_enum, requires = "identifier-from-device")]
+        identifier_format: Option<IdentifierFormat>,
+
+        /// Read the device identifier from a raw memory dump file instead of the target.
+        #[clap(long, requires = "identifier-from-device")]
+        memory_dump: Option<String>,
+
+        /// The target address of the first byte in the memory dump file.
+        #[clap(long, requires = "memory-dump", default_value = "0", parse(try_from_str = parse_address))]
+        memory_dump_address: u64,
+
+        /// The station the new run of an existing device is executed on.
+        #[clap(long)]
+        station: Option<String>,
+
+        #[clap(flatten)]
+        probe: ProbeOptions,
+    },
 
     /// Downloads the binary and device data to the target.
     Download {
+        /// The name or id of the chip. May be omitted if the project has a single chip.
         chip: Option<String>,
+        /// The version to flash, either exact (`1.2.3`) or a requirement (`^1.4`, `>=1.2, <2`).
+        /// The newest matching release is used. Defaults to the newest release.
         version: Option<String>,
+        /// Flash the binary the server considers the newest instead of selecting one locally.
+        #[clap(long, conflicts_with = "version")]
+        server_newest: bool,
+        /// Flash local files instead of a release from the server. Directories include all ELF,
+        /// HEX and BIN files in them. Raw binaries take their load address as `fw.bin@0x08000000`.
+        #[clap(
+            long,
+            multiple_occurrences = true,
+            requires = "chip-part",
+            conflicts_with_all = &["chip", "version", "server-newest"]
+        )]
+        file: Vec<String>,
+        /// The part number of the chip the local files are flashed onto, as known by probe-rs.
+        #[clap(long, requires = "file")]
+        chip_part: Option<String>,
+        /// Record the flashed files and their hashes in the logs of the current run.
+        #[clap(long, requires = "file", conflicts_with_all = &["all-probes", "probe-serials"])]
+        record: bool,
+
+        /// Flash all connected probes' targets concurrently.
+        #[clap(long, conflicts_with_all = &["probe", "probe-serials"])]
+        all_probes: bool,
+
+        /// Flash the targets of the probes with these serial numbers concurrently.
+        #[clap(
+            long,
+            multiple_values = true,
+            use_value_delimiter = true,
+            conflicts_with = "probe"
+        )]
+        probe_serials: Vec<String>,
+
+        /// When flashing several targets, do not create a new device with its own run for each
+        /// target, e.g. to flash them offline.
+        #[clap(long)]
+        no_runs: bool,
+
+        #[clap(flatten)]
+        flash: FlashOptions,
+
+        #[clap(flatten)]
+        probe: ProbeOptions,
+
+        /// Capture the RTT output of the target into logs after flashing.
+        #[clap(long, conflicts_with_all = &["verify-only", "dry-run", "all-probes", "probe-serials"])]
+        rtt: bool,
+
+        #[clap(flatten)]
+        rtt_options: RttOptions,
+    },
+
+    /// Captures the RTT output of the running target into logs.
+    Rtt {
+        /// The name or id of the chip. May be omitted if the project has a single chip.
+        #[clap(long)]
+        chip: Option<String>,
+
+        /// The version requirement of the running binary. Its RTT header location is used to find
+        /// the RTT control block. Defaults to the newest release.
+        #[clap(long)]
+        version: Option<String>,
+
+        /// Attach to a chip with this part number instead of looking up a release on the server.
+        #[clap(long, conflicts_with_all = &["chip", "version"])]
+        chip_part: Option<String>,
+
+        /// The address of the RTT control block. The RAM is scanned for it if neither this nor a
+        /// release with an RTT header location is given.
+        #[clap(long, parse(try_from_str = parse_address))]
+        address: Option<u64>,
+
+        #[clap(flatten)]
+        rtt_options: RttOptions,
+
+        #[clap(flatten)]
+        probe: ProbeOptions,
     },
 
     /// Creates a new log entry on the device in the current session.
     #[clap(subcommand)]
     Log(LogOption),
 
-    /// Attaches a file to the current device event log.
+    /// Attaches files to the current device event log.
     Attach {
-        /// The path to the file to be attached to the test report.
-        file_path: String,
+        /// The paths of the files to be attached to the test report. Glob patterns like
+        /// `logs/*.txt` are expanded.
+        #[clap(required = true)]
+        paths: Vec<String>,
+        /// The name of the attachment instead of the file name. Only valid for a single file.
+        #[clap(long)]
+        name: Option<String>,
+        /// Attach directories as zip archives of their contents.
+        #[clap(long)]
+        zip: bool,
+        /// The largest attachment, in bytes or with a `K`, `M` or `G` suffix.
+        #[clap(long, default_value = "25M", parse(try_from_str = parse_size))]
+        max_size: u64,
     },
 
     /// Adds a new block to the current device.
     Block { schema_name: String, data: Value },
 
     /// Finishes the current device procurment procedure.
-    End,
+    End {
+        /// Write a report of the finished run to this file.
+        #[clap(long)]
+        report: Option<PathBuf>,
+        /// The format of the report.
+        #[clap(long, arg_enum, default_value = "junit", requires = "report")]
+        report_format: ReportFormat,
+    },
+
+    /// Writes a report of the steps, requirement checks and errors of a run as test cases.
+    Report {
+        #[clap(flatten)]
+        run: RunSelection,
+        /// The format of the report.
+        #[clap(long, arg_enum, default_value = "junit")]
+        format: ReportFormat,
+        /// The path of the report. Defaults to `report-<id>.xml`, `.html` or `.json`.
+        #[clap(short = 'o', long)]
+        out_file: Option<PathBuf>,
+    },
+
+    /// Shows the current device and run of the provisioner.
+    ///
+    /// Exits with a non-zero code if the current run has failed.
+    Status,
+
+    /// Shows or changes the configuration file.
+    #[clap(subcommand)]
+    Config(ConfigOption),
+
+    /// Signs in as a user to administer projects.
+    Login {
+        /// The email address of the user. Prompted for if neither this nor `--oauth` is given.
+        #[clap(long, conflicts_with = "oauth")]
+        email: Option<String>,
+
+        /// Read the password from STDIN instead of prompting for it.
+        #[clap(long, conflicts_with = "oauth")]
+        password_stdin: bool,
+
+        /// Sign in with an OAuth provider, e.g. `google` or `github`.
+        #[clap(long)]
+        oauth: Option<String>,
+    },
+
+    /// Signs out the current user.
+    Logout,
+
+    /// Shows the signed-in user.
+    Whoami,
+
+    /// Administers the provisioners of a project. Requires a signed-in user.
+    #[clap(subcommand)]
+    Provisioner(ProvisionerOption),
+
+    /// Manages the connected debug probes.
+    #[clap(subcommand)]
+    Probe(ProbeOption),
+
+    /// Manages the firmware binaries of the project's chips.
+    #[clap(subcommand)]
+    Binary(BinaryOption),
+
+    /// Lists the devices of a project.
+    #[clap(subcommand)]
+    Device(DeviceOption),
+
+    /// Retrieves the attachments of a run.
+    #[clap(subcommand)]
+    Attachments(AttachmentsOption),
+
+    /// Retrieves the records of a run.
+    #[clap(subcommand)]
+    Run(RunOption),
+}
+
+#[derive(Subcommand)]
+pub enum ProbeOption {
+    /// Lists the connected debug probes and the `--probe` argument that selects them.
+    List,
+}
+
+/// Which run a command acts on.
+#[derive(Args, Debug, Default)]
+pub struct RunSelection {
+    /// The id of the run. Defaults to the current run, or the most recent run of `--device`.
+    #[clap(long)]
+    pub run: Option<Uuid>,
+
+    /// The id of the device the run belongs to. Defaults to the current device.
+    #[clap(long)]
+    pub device: Option<Uuid>,
+}
+
+impl RunSelection {
+    pub fn run_ref(&self) -> RunRef {
+        match (self.device, self.run) {
+            (Some(device_id), run_id) => RunRef::Device { device_id, run_id },
+            (None, Some(run_id)) => RunRef::Id(run_id),
+            (None, None) => RunRef::Current,
+        }
+    }
+}
+
+/// Which debug probe to use and how to connect to the target.
+#[derive(Args, Debug, Default, Clone)]
+pub struct ProbeOptions {
+    /// The debug probe to use as `VID:PID[:SERIAL]`, see `forged-cli probe list`. Required if more
+    /// than one probe is connected.
+    #[clap(long)]
+    pub probe: Option<DebugProbeSelector>,
+
+    /// The protocol speed in kHz.
+    #[clap(long)]
+    pub speed: Option<u32>,
+
+    /// The protocol used to talk to the target, `swd` or `jtag`.
+    #[clap(long)]
+    pub protocol: Option<WireProtocol>,
+
+    /// Hold the target in reset while connecting.
+    #[clap(long)]
+    pub connect_under_reset: bool,
+}
+
+/// How targets are flashed. Unset flags fall back to the `flash` section of the profile.
+#[derive(Args, Debug, Default)]
+pub struct FlashOptions {
+    /// Erase the whole chip instead of only the sectors that are written.
+    #[clap(long)]
+    pub chip_erase: bool,
+
+    /// Do not erase before programming, e.g. if the chip was erased already.
+    #[clap(long, conflicts_with = "chip-erase")]
+    pub skip_erase: bool,
+
+    /// Do not read back the written data to verify it.
+    #[clap(long, conflicts_with = "verify-only")]
+    pub no_verify: bool,
+
+    /// Only compare the flash contents to the binary without programming it.
+    #[clap(long)]
+    pub verify_only: bool,
+
+    /// Prepare flashing without writing anything.
+    #[clap(long, conflicts_with = "verify-only")]
+    pub dry_run: bool,
+
+    /// Erase the unwritten bytes of partially written sectors instead of restoring them.
+    #[clap(long)]
+    pub erase_unwritten: bool,
+
+    /// What happens to the target after programming: `none`, `run` or `halt`.
+    #[clap(long)]
+    pub reset_after: Option<ResetBehavior>,
+}
+
+/// Which RTT channel to capture and when to stop.
+#[derive(Args, Debug)]
+pub struct RttOptions {
+    /// The RTT up channel to capture.
+    #[clap(long, default_value = "0")]
+    pub rtt_channel: usize,
+
+    /// Stop capturing after a line containing this text.
+    #[clap(long)]
+    pub rtt_until: Option<String>,
+
+    /// Stop capturing after this many seconds.
+    #[clap(long)]
+    pub rtt_timeout: Option<u64>,
+}
+
+#[derive(Subcommand)]
+pub enum BinaryOption {
+    /// Uploads a new firmware release for a chip.
+    Upload {
+        /// The name or id of the chip.
+        #[clap(long)]
+        chip: String,
+        /// The semantic version of the release, e.g. `1.2.3`.
+        #[clap(long)]
+        version: String,
+        /// The files of the release. The format is inferred from the extension or content.
+        ///
+        /// Raw binaries take the address they are loaded at as `file@0x08000000`.
+        #[clap(required = true)]
+        parts: Vec<String>,
+    },
+
+    /// Lists the binaries of a chip.
+    List {
+        /// The name or id of the chip. May be omitted if the project has a single chip.
+        #[clap(long)]
+        chip: Option<String>,
+    },
+
+    /// Shows the parts of a binary.
+    Show {
+        /// The version of the binary.
+        version: String,
+        /// The name or id of the chip. May be omitted if the project has a single chip.
+        #[clap(long)]
+        chip: Option<String>,
+    },
+
+    /// Deletes a binary.
+    Delete {
+        /// The version of the binary.
+        version: String,
+        /// The name or id of the chip. May be omitted if the project has a single chip.
+        #[clap(long)]
+        chip: Option<String>,
+    },
+
+    /// Compares the parts, sizes and hashes of two binaries.
+    Diff {
+        /// The version of the older binary.
+        from: String,
+        /// The version of the newer binary.
+        to: String,
+        /// The name or id of the chip. May be omitted if the project has a single chip.
+        #[clap(long)]
+        chip: Option<String>,
+    },
+
+    /// Saves the parts of a binary to a directory.
+    Pull {
+        /// The version of the binary.
+        version: String,
+        /// The name or id of the chip. May be omitted if the project has a single chip.
+        #[clap(long)]
+        chip: Option<String>,
+        /// The directory the parts are saved to.
+        #[clap(short = 'o', long, default_value = ".")]
+        out_dir: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum DeviceOption {
+    /// Lists the devices of a project.
+    List {
+        /// Only list devices matching this filter, e.g. a device name.
+        #[clap(long)]
+        filter: Option<String>,
+        /// The maximum number of devices to list. `0` lists all devices.
+        #[clap(long, default_value = "100")]
+        limit: usize,
+        /// The id of the project. Defaults to the project of the provisioner.
+        #[clap(long)]
+        project: Option<Uuid>,
+        /// How the devices are printed.
+        #[clap(long, arg_enum, default_value = "table")]
+        format: ListFormat,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum AttachmentsOption {
+    /// Saves the attachments of a run to a directory.
+    Pull {
+        #[clap(flatten)]
+        run: RunSelection,
+        /// The directory the attachments are saved to.
+        #[clap(short = 'o', long, default_value = ".")]
+        out_dir: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum RunOption {
+    /// Bundles the logs, blocks, steps, requirement checks and attachments of a run into a zip
+    /// archive for failure analysis.
+    Export {
+        #[clap(flatten)]
+        run: RunSelection,
+        /// The path of the archive. Defaults to `run-<id>.zip`.
+        #[clap(short = 'o', long)]
+        out_file: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ProvisionerOption {
+    /// Creates a new provisioner.
+    Create {
+        /// The name or id of the project.
+        #[clap(long)]
+        project: String,
+        /// The name of the provisioner.
+        #[clap(long)]
+        name: String,
+        /// The station the provisioner is assigned to.
+        #[clap(long)]
+        station: String,
+        /// Store the provisioner token in this configuration profile instead of printing it.
+        #[clap(long)]
+        save_profile: Option<String>,
+    },
+
+    /// Lists the provisioners of a project.
+    List {
+        /// The name or id of the project.
+        #[clap(long)]
+        project: String,
+    },
+
+    /// Renames a provisioner.
+    Rename {
+        /// The id of the provisioner.
+        id: Uuid,
+        /// The new name of the provisioner.
+        name: String,
+    },
+
+    /// Assigns a provisioner to a different station.
+    AssignStation {
+        /// The id of the provisioner.
+        id: Uuid,
+        /// The station the provisioner is assigned to.
+        station: String,
+    },
+
+    /// Generates a new token for a provisioner, invalidating the old one.
+    RegenerateToken {
+        /// The id of the provisioner.
+        id: Uuid,
+        /// Store the provisioner token in this configuration profile instead of printing it.
+        #[clap(long)]
+        save_profile: Option<String>,
+    },
+
+    /// Deletes a provisioner.
+    Delete {
+        /// The id of the provisioner.
+        id: Uuid,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ConfigOption {
+    /// Shows the resolved configuration and where each setting is taken from.
+    Show,
+
+    /// Sets a setting of the selected profile.
+    Set {
+        /// The setting to change.
+        #[clap(arg_enum)]
+        key: ConfigKey,
+        /// The new value of the setting.
+        value: String,
+    },
+
+    /// Selects the profile that is used if `--profile` is not given.
+    UseProfile {
+        /// The name of the profile.
+        name: String,
+    },
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug)]
+pub enum ConfigKey {
+    /// The provisioner token used to authenticate with forged.dev.
+    Token,
+    /// The HTTP URL of the forged API endpoint.
+    ApiUrl,
+}
+
+/// Parse a memory address given either in decimal or as `0x` prefixed hexadecimal.
+pub fn parse_address(address: &str) -> Result<u64, std::num::ParseIntError> {
+    match address.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => address.parse(),
+    }
+}
+
+/// Parse a size in bytes with an optional binary `K`, `M` or `G` suffix, e.g. `512K`.
+pub fn parse_size(size: &str) -> Result<u64, String> {
+    let (number, shift) = match size.char_indices().last() {
+        Some((index, 'K' | 'k')) => (&size[..index], 10),
+        Some((index, 'M' | 'm')) => (&size[..index], 20),
+        Some((index, 'G' | 'g')) => (&size[..index], 30),
+        _ => (size, 0),
+    };
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(1 << shift))
+        .ok_or_else(|| format!("Invalid size `{size}`"))
 }
 
 #[derive(Subcommand)]
@@ -54,6 +592,29 @@ pub enum LogOption {
     Stream {
         /// An optional filename to stream logs from. If unspecified, STDIN is used.
         filename: Option<String>,
+        /// Decode the input as defmt frames with the interned strings of this ELF file.
+        #[clap(long, value_name = "ELF")]
+        defmt: Option<String>,
+    },
+
+    /// Generate logs from the lines of a serial port.
+    Serial {
+        /// The serial port to read, e.g. `/dev/ttyUSB0` or `COM3`.
+        #[clap(long)]
+        port: String,
+        /// The baud rate of the serial port.
+        #[clap(long, default_value = "115200")]
+        baud: u32,
+        /// Stop reading after a line matching this regular expression.
+        #[clap(long)]
+        until: Option<String>,
+        /// Stop reading after this many seconds.
+        #[clap(long)]
+        timeout: Option<u64>,
+        /// Create a block for every `KEY=VALUE` line, with the key as the schema name. Values are
+        /// parsed as JSON, or used as a string otherwise.
+        #[clap(long)]
+        blocks: bool,
     },
 
     /// Generate a single log entry.
@@ -64,3 +625,25 @@ pub enum LogOption {
         message: String,
     },
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("0"), Ok(0));
+        assert_eq!(parse_size("1500"), Ok(1500));
+        assert_eq!(parse_size("512K"), Ok(512 << 10));
+        assert_eq!(parse_size("512k"), Ok(512 << 10));
+        assert_eq!(parse_size("25M"), Ok(25 << 20));
+        assert_eq!(parse_size("2g"), Ok(2 << 30));
+    }
+
+    #[test]
+    fn invalid_sizes() {
+        for size in ["", "K", "-1", "1.5M", "10T", "12 M", "99999999999G"] {
+            assert_eq!(parse_size(size), Err(format!("Invalid size `{size}`")));
+        }
+    }
+}
@@ -0,0 +1,904 @@
+//! Decoding of defmt log frames with the interned strings of the firmware's ELF file.
+//!
+//! Only wire format version 4 is supported. Firmware of any other version is rejected by
+//! [`Table::parse`] with an error, so a new wire format never decodes into wrong messages. The API
+//! of `defmt-decoder` is documented as unstable and meant for `probe-rs` only, so the format
+//! strings are parsed with `defmt-parser` and only the log frames are decoded here.
+use std::collections::BTreeMap;
+
+use anyhow::anyhow;
+use defmt_parser::{DisplayHint, Fragment, Level, ParserMode, TimePrecision, Type};
+use object::{Object, ObjectSection, ObjectSymbol};
+use serde::Deserialize;
+
+use crate::{progress, queries::CreateLogArguments, Result};
+
+/// The defmt wire format version this decoder understands.
+const SUPPORTED_VERSION: &str = "4";
+
+/// The largest frame that is decoded. Longer length prefixes and unterminated data are corrupt.
+const MAX_FRAME_SIZE: usize = 64 * 1024;
+
+/// How the frames of a defmt stream are delimited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    /// Frames are written back to back.
+    Raw,
+    /// Frames are rzCOBS encoded and separated by zero bytes.
+    Rzcobs,
+}
+
+/// A string the firmware interned into the `.defmt` section of its ELF file.
+#[derive(Debug, Deserialize)]
+struct Entry {
+    tag: String,
+    data: String,
+}
+
+/// The interned strings of a firmware that logs with defmt.
+pub struct Table {
+    entries: BTreeMap<u16, Entry>,
+    timestamp: Option<String>,
+    encoding: Encoding,
+}
+
+impl Table {
+    /// Read the defmt table of an ELF file.
+    ///
+    /// # Returns
+    /// The table, or `None` if the firmware does not log with defmt.
+    pub fn parse(elf: &[u8]) -> Result<Option<Self>> {
+        let file =
+            object::File::parse(elf).map_err(|e| anyhow!("Failed to parse the ELF file: {e}"))?;
+        let Some(section) = file.section_by_name(".defmt") else {
+            return Ok(None);
+        };
+
+        let mut version = None;
+        let mut encoding = Encoding::Raw;
+        let mut entries = BTreeMap::new();
+        let mut timestamp = None;
+        for symbol in file.symbols() {
+            let Ok(name) = symbol.name() else {
+                continue;
+            };
+            if let Some(value) = name.strip_prefix("_defmt_version_ = ") {
+                version = Some(value.to_string());
+            } else if let Some(value) = name.strip_prefix("_defmt_encoding_ = ") {
+                encoding = match value {
+                    "raw" => Encoding::Raw,
+                    "rzcobs" => Encoding::Rzcobs,
+                    _ => return Err(anyhow!("Unsupported defmt encoding `{value}`").into()),
+                };
+            } else if symbol.section_index() == Some(section.index()) {
+                let Ok(entry) = serde_json::from_str::<Entry>(name) else {
+                    continue;
+                };
+                if entry.tag == "defmt_timestamp" {
+                    timestamp = Some(entry.data);
+                } else {
+                    let index = u16::try_from(symbol.address()).map_err(|_| {
+                        anyhow!("Invalid defmt symbol address {}", symbol.address())
+                    })?;
+                    entries.insert(index, entry);
+                }
+            }
+        }
+
+        match version.as_deref() {
+            None => Ok(None),
+            Some(SUPPORTED_VERSION) => Ok(Some(Self {
+                entries,
+                timestamp,
+                encoding,
+            })),
+            Some(version) => Err(anyhow!(
+                "Unsupported defmt version {version}, expected {SUPPORTED_VERSION}"
+            )
+            .into()),
+        }
+    }
+
+    /// Create a decoder for a stream of frames logged by the firmware.
+    pub fn decoder(&self) -> StreamDecoder<'_> {
+        StreamDecoder {
+            table: self,
+            pending: Vec::new(),
+        }
+    }
+
+    fn entry(&self, index: u16) -> std::result::Result<&Entry, DecodeError> {
+        self.entries
+            .get(&index)
+            .ok_or_else(|| DecodeError::Invalid(format!("unknown string index {index}")))
+    }
+
+    /// Decode a single, complete frame.
+    fn decode_frame(&self, bytes: &mut Reader) -> std::result::Result<Frame, DecodeError> {
+        let entry = self.entry(bytes.u16()?)?;
+        let level = match entry.tag.as_str() {
+            "defmt_trace" => Some(Level::Trace),
+            "defmt_debug" => Some(Level::Debug),
+            "defmt_info" => Some(Level::Info),
+            "defmt_warn" => Some(Level::Warn),
+            "defmt_error" => Some(Level::Error),
+            "defmt_println" => None,
+            tag => {
+                return Err(DecodeError::Invalid(format!(
+                    "`{tag}` is not a log statement"
+                )))
+            }
+        };
+        let timestamp = match &self.timestamp {
+            Some(format) => Some(self.format(format, bytes)?),
+            None => None,
+        };
+        let message = self.format(&entry.data, bytes)?;
+
+        Ok(Frame {
+            level,
+            timestamp,
+            message,
+        })
+    }
+
+    /// Decode the arguments of a format string and format them.
+    fn format(&self, format: &str, bytes: &mut Reader) -> std::result::Result<String, DecodeError> {
+        let fragments =
+            defmt_parser::parse(format, ParserMode::ForwardsCompatible).map_err(|e| {
+                DecodeError::Invalid(format!("malformed format string {format:?}: {e}"))
+            })?;
+        let args = self.decode_args(&fragments, bytes)?;
+
+        let mut formatted = String::new();
+        for fragment in fragments {
+            match fragment {
+                Fragment::Literal(literal) => formatted.push_str(&literal),
+                Fragment::Parameter(parameter) => {
+                    let arg = args.get(&parameter.index).ok_or_else(|| {
+                        DecodeError::Invalid(format!("missing argument {}", parameter.index))
+                    })?;
+                    let arg = match (&parameter.ty, arg) {
+                        (Type::BitField(range), Arg::Uint(value)) => {
+                            Arg::Uint(bits(*value, range.start, range.end))
+                        }
+                        _ => arg.clone(),
+                    };
+                    formatted.push_str(&arg.display(parameter.hint.as_ref()));
+                }
+            }
+        }
+        Ok(formatted)
+    }
+
+    /// Decode the arguments of a format string in the order of their index.
+    fn decode_args(
+        &self,
+        fragments: &[Fragment],
+        bytes: &mut Reader,
+    ) -> std::result::Result<BTreeMap<usize, Arg>, DecodeError> {
+        let mut types = BTreeMap::new();
+        for fragment in fragments {
+            if let Fragment::Parameter(parameter) = fragment {
+                let ty = match (&parameter.ty, types.remove(&parameter.index)) {
+                    (Type::BitField(range), Some(Type::BitField(other))) => {
+                        Type::BitField(range.start.min(other.start)..range.end.max(other.end))
+                    }
+                    (ty, _) => ty.clone(),
+                };
+                types.insert(parameter.index, ty);
+            }
+        }
+
+        let mut args = BTreeMap::new();
+        for (index, ty) in types {
+            args.insert(index, self.decode_arg(&ty, bytes)?);
+        }
+        Ok(args)
+    }
+
+    fn decode_arg(&self, ty: &Type, bytes: &mut Reader) -> std::result::Result<Arg, DecodeError> {
+        Ok(match ty {
+            Type::Bool => Arg::Bool(bytes.u8()? != 0),
+            Type::U8 => Arg::Uint(bytes.u8()?.into()),
+            Type::U16 => Arg::Uint(bytes.u16()?.into()),
+            Type::U32 | Type::Usize => Arg::Uint(bytes.u32()?.into()),
+            Type::U64 => Arg::Uint(bytes.u64()?.into()),
+            Type::U128 => Arg::Uint(bytes.u128()?),
+            Type::I8 => Arg::Int((bytes.u8()? as i8).into()),
+            Type::I16 => Arg::Int((bytes.u16()? as i16).into()),
+            Type::I32 | Type::Isize => Arg::Int((bytes.u32()? as i32).into()),
+            Type::I64 => Arg::Int((bytes.u64()? as i64).into()),
+            Type::I128 => Arg::Int(bytes.u128()? as i128),
+            Type::F32 => Arg::F32(f32::from_bits(bytes.u32()?)),
+            Type::F64 => Arg::F64(f64::from_bits(bytes.u64()?)),
+            Type::BitField(range) => Arg::Uint(match range.end {
+                0..=8 => bytes.u8()?.into(),
+                9..=16 => bytes.u16()?.into(),
+                17..=32 => bytes.u32()?.into(),
+                33..=64 => bytes.u64()?.into(),
+                _ => bytes.u128()?,
+            }),
+            Type::Char => {
+                let value = bytes.u32()?;
+                Arg::Char(
+                    char::from_u32(value).ok_or_else(|| {
+                        DecodeError::Invalid(format!("invalid character {value:#x}"))
+                    })?,
+                )
+            }
+            Type::Str => {
+                let length = bytes.length()?;
+                Arg::Str(String::from_utf8_lossy(bytes.take(length)?).into_owned())
+            }
+            Type::IStr => Arg::Str(self.entry(bytes.u16()?)?.data.clone()),
+            Type::Debug | Type::Display => {
+                let mut text = Vec::new();
+                loop {
+                    match bytes.u8()? {
+                        0xff => break,
+                        byte => text.push(byte),
+                    }
+                }
+                Arg::Formatted(String::from_utf8_lossy(&text).into_owned())
+            }
+            Type::U8Slice => {
+                let length = bytes.length()?;
+                Arg::Bytes(bytes.take(length)?.to_vec())
+            }
+            Type::U8Array(length) => Arg::Bytes(bytes.take(*length)?.to_vec()),
+            Type::Format => {
+                let index = bytes.u16()?;
+                Arg::Formatted(self.format_value(index, bytes)?)
+            }
+            Type::FormatSlice => {
+                let length = bytes.length()?;
+                self.decode_values(length, bytes)?
+            }
+            Type::FormatArray(length) => self.decode_values(*length, bytes)?,
+            Type::FormatSequence => {
+                let mut formatted = String::new();
+                loop {
+                    match bytes.u16()? {
+                        0 => break,
+                        index => formatted.push_str(&self.format_value(index, bytes)?),
+                    }
+                }
+                Arg::Formatted(formatted)
+            }
+        })
+    }
+
+    /// Decode `length` values that share the format string index written before them.
+    fn decode_values(
+        &self,
+        length: usize,
+        bytes: &mut Reader,
+    ) -> std::result::Result<Arg, DecodeError> {
+        let index = bytes.u16()?;
+        let values = (0..length)
+            .map(|_| self.format_value(index, bytes))
+            .collect::<std::result::Result<_, _>>()?;
+        Ok(Arg::Values(values))
+    }
+
+    /// Format a value of a type implementing `defmt::Format`.
+    fn format_value(
+        &self,
+        index: u16,
+        bytes: &mut Reader,
+    ) -> std::result::Result<String, DecodeError> {
+        let entry = self.entry(index)?;
+        if entry.tag != "defmt_derived" || !entry.data.contains('|') {
+            return self.format(&entry.data, bytes);
+        }
+
+        // Derived enums list the format strings of their variants, prefixed by the discriminant.
+        let variants: Vec<&str> = entry.data.split('|').collect();
+        let discriminant = match variants.len() {
+            0..=255 => bytes.u8()? as usize,
+            256..=65535 => bytes.u16()? as usize,
+            _ => bytes.u32()? as usize,
+        };
+        let variant = variants.get(discriminant).ok_or_else(|| {
+            DecodeError::Invalid(format!("invalid enum discriminant {discriminant}"))
+        })?;
+        self.format(variant, bytes)
+    }
+}
+
+/// A decoded log frame.
+#[derive(Debug)]
+pub struct Frame {
+    /// The level of the log statement. `None` for `println!`.
+    pub level: Option<Level>,
+    /// The formatted timestamp, if the firmware defines one.
+    pub timestamp: Option<String>,
+    pub message: String,
+}
+
+impl Frame {
+    /// Convert the frame into a log entry.
+    ///
+    /// # Note
+    /// Logs have no separate timestamp, so the device timestamp prefixes the message.
+    pub fn into_log(self) -> CreateLogArguments {
+        let level = self
+            .level
+            .map(|level| level.as_str().to_ascii_uppercase())
+            .unwrap_or_else(|| "INFO".to_string());
+        let message = match self.timestamp {
+            Some(timestamp) => format!("{timestamp} {}", self.message),
+            None => self.message,
+        };
+
+        CreateLogArguments { level, message }
+    }
+}
+
+/// Splits a defmt byte stream into decoded frames.
+pub struct StreamDecoder<'a> {
+    table: &'a Table,
+    pending: Vec<u8>,
+}
+
+impl StreamDecoder<'_> {
+    /// Add data to the decoder.
+    ///
+    /// # Returns
+    /// The frames completed by the data. Malformed frames are skipped.
+    pub fn push(&mut self, data: &[u8]) -> Vec<Frame> {
+        self.pending.extend_from_slice(data);
+
+        let mut frames = Vec::new();
+        match self.table.encoding {
+            Encoding::Rzcobs => {
+                while let Some(end) = self.pending.iter().position(|&byte| byte == 0) {
+                    let frame: Vec<u8> = self.pending.drain(..=end).collect();
+                    let frame = &frame[..end];
+                    if frame.is_empty() {
+                        continue;
+                    }
+                    let decoded = rzcobs_decode(frame)
+                        .and_then(|frame| self.table.decode_frame(&mut Reader::new(&frame)));
+                    match decoded {
+                        Ok(frame) => frames.push(frame),
+                        Err(error) => progress!("⚠️  Skipping a malformed defmt frame: {error}"),
+                    }
+                }
+            }
+            Encoding::Raw => {
+                while !self.pending.is_empty() {
+                    let mut reader = Reader::new(&self.pending);
+                    match self.table.decode_frame(&mut reader) {
+                        Ok(frame) => {
+                            let consumed = reader.position;
+                            self.pending.drain(..consumed);
+                            frames.push(frame);
+                        }
+                        Err(DecodeError::Incomplete) => break,
+                        Err(error) => {
+                            // Raw frames cannot be resynchronized, drop the rest of the data.
+                            progress!("⚠️  Skipping malformed defmt data: {error}");
+                            self.pending.clear();
+                        }
+                    }
+                }
+            }
+        }
+
+        // Without a frame end in sight, the data is corrupt rather than incomplete.
+        if self.pending.len() > MAX_FRAME_SIZE {
+            progress!(
+                "⚠️  Skipping {} bytes of defmt data without a complete frame",
+                self.pending.len()
+            );
+            self.pending.clear();
+        }
+        frames
+    }
+
+    /// Whether data of an incomplete frame is left over.
+    pub fn has_pending(&self) -> bool {
+        self.pending.iter().any(|&byte| byte != 0)
+    }
+}
+
+/// Reasons a frame could not be decoded.
+#[derive(Debug)]
+enum DecodeError {
+    /// The frame ended before all of its data was read.
+    Incomplete,
+    Invalid(String),
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::Incomplete => write!(f, "the frame is incomplete"),
+            DecodeError::Invalid(reason) => write!(f, "{reason}"),
+        }
+    }
+}
+
+/// Reads little endian values from the data of a frame.
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn take(&mut self, length: usize) -> std::result::Result<&'a [u8], DecodeError> {
+        let data = self
+            .data
+            .get(self.position..self.position + length)
+            .ok_or(DecodeError::Incomplete)?;
+        self.position += length;
+        Ok(data)
+    }
+
+    fn array<const N: usize>(&mut self) -> std::result::Result<[u8; N], DecodeError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    fn u8(&mut self) -> std::result::Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> std::result::Result<u16, DecodeError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> std::result::Result<u32, DecodeError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> std::result::Result<u64, DecodeError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    fn u128(&mut self) -> std::result::Result<u128, DecodeError> {
+        Ok(u128::from_le_bytes(self.array()?))
+    }
+
+    /// Read the length prefix of a slice or string.
+    ///
+    /// # Returns
+    /// An invalid data error if no frame can hold the length, e.g. after corrupt data, so the
+    /// stream is not waited on for data that never arrives.
+    fn length(&mut self) -> std::result::Result<usize, DecodeError> {
+        let length = self.u32()? as usize;
+        if length > MAX_FRAME_SIZE {
+            return Err(DecodeError::Invalid(format!(
+                "length {length} exceeds the largest frame of {MAX_FRAME_SIZE} bytes"
+            )));
+        }
+        Ok(length)
+    }
+}
+
+/// Decode a frame encoded with rzCOBS, without its zero byte separator.
+///
+/// rzCOBS is decoded from the end of the frame:
+/// * `0xxxxxxx` - For each bit from the MSB, a zero byte if set, a byte of the stream otherwise.
+/// * `1nnnnnnn` - `n + 7` bytes of the stream and a zero byte.
+/// * `11111111` - 134 bytes of the stream.
+fn rzcobs_decode(frame: &[u8]) -> std::result::Result<Vec<u8>, DecodeError> {
+    let malformed = || DecodeError::Invalid("malformed rzCOBS data".to_string());
+
+    let mut decoded = Vec::new();
+    let mut data = frame.iter().rev().copied();
+    while let Some(code) = data.next() {
+        match code {
+            0x00 => return Err(malformed()),
+            0x01..=0x7f => {
+                for bit in (0..7).rev() {
+                    if code & (1 << bit) == 0 {
+                        decoded.push(data.next().ok_or_else(malformed)?);
+                    } else {
+                        decoded.push(0);
+                    }
+                }
+            }
+            0x80..=0xfe => {
+                decoded.push(0);
+                for _ in 0..(code & 0x7f) + 7 {
+                    decoded.push(data.next().ok_or_else(malformed)?);
+                }
+            }
+            0xff => {
+                for _ in 0..134 {
+                    decoded.push(data.next().ok_or_else(malformed)?);
+                }
+            }
+        }
+    }
+    decoded.reverse();
+    Ok(decoded)
+}
+
+/// Extract the bits `start..end` of a value.
+fn bits(value: u128, start: u8, end: u8) -> u128 {
+    let width = end.saturating_sub(start) as u32;
+    let value = value.checked_shr(start as u32).unwrap_or(0);
+    if width >= 128 {
+        value
+    } else {
+        value & ((1 << width) - 1)
+    }
+}
+
+/// A decoded argument of a log statement.
+#[derive(Debug, Clone)]
+enum Arg {
+    Bool(bool),
+    Uint(u128),
+    Int(i128),
+    F32(f32),
+    F64(f64),
+    Char(char),
+    Str(String),
+    Bytes(Vec<u8>),
+    /// Text that was formatted by the firmware or a nested format string.
+    Formatted(String),
+    /// The formatted values of a slice or array.
+    Values(Vec<String>),
+}
+
+impl Arg {
+    fn display(&self, hint: Option<&DisplayHint>) -> String {
+        match self {
+            Arg::Bool(value) => value.to_string(),
+            Arg::Uint(value) => format_integer(*value, false, hint),
+            Arg::Int(value) => format_integer(value.unsigned_abs(), *value < 0, hint),
+            Arg::F32(value) => value.to_string(),
+            Arg::F64(value) => value.to_string(),
+            Arg::Char(value) => match hint {
+                Some(DisplayHint::Debug) => format!("{value:?}"),
+                _ => value.to_string(),
+            },
+            Arg::Str(value) => match hint {
+                Some(DisplayHint::Debug) => format!("{value:?}"),
+                _ => value.clone(),
+            },
+            Arg::Bytes(bytes) => match hint {
+                Some(DisplayHint::Ascii) => format!(
+                    "b\"{}\"",
+                    bytes
+                        .iter()
+                        .flat_map(|&byte| std::ascii::escape_default(byte))
+                        .map(char::from)
+                        .collect::<String>()
+                ),
+                _ => format!(
+                    "[{}]",
+                    bytes
+                        .iter()
+                        .map(|&byte| format_integer(byte.into(), false, hint))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            },
+            Arg::Formatted(value) => value.clone(),
+            Arg::Values(values) => format!("[{}]", values.join(", ")),
+        }
+    }
+}
+
+/// Format an integer given by its magnitude and sign with a display hint.
+fn format_integer(value: u128, negative: bool, hint: Option<&DisplayHint>) -> String {
+    let sign = if negative { "-" } else { "" };
+    match hint {
+        Some(DisplayHint::NoHint { zero_pad }) => {
+            format!(
+                "{sign}{value:0width$}",
+                width = zero_pad.saturating_sub(sign.len())
+            )
+        }
+        Some(DisplayHint::Hexadecimal {
+            alternate,
+            uppercase,
+            zero_pad,
+        }) => {
+            let digits = if *uppercase {
+                format!("{value:0zero_pad$X}")
+            } else {
+                format!("{value:0zero_pad$x}")
+            };
+            let prefix = if *alternate { "0x" } else { "" };
+            format!("{sign}{prefix}{digits}")
+        }
+        Some(DisplayHint::Binary {
+            alternate,
+            zero_pad,
+        }) => {
+            let prefix = if *alternate { "0b" } else { "" };
+            format!("{sign}{prefix}{value:0zero_pad$b}")
+        }
+        Some(DisplayHint::Ascii) if !negative && value < 0x80 => {
+            format!("{:?}", char::from(value as u8))
+        }
+        Some(DisplayHint::Seconds(precision)) | Some(DisplayHint::Time(precision)) => {
+            let (seconds, fraction) = match precision {
+                TimePrecision::Micros => {
+                    (value / 1_000_000, Some(format!("{:06}", value % 1_000_000)))
+                }
+                TimePrecision::Millis => (value / 1_000, Some(format!("{:03}", value % 1_000))),
+                TimePrecision::Seconds => (value, None),
+            };
+            let whole = match hint {
+                Some(DisplayHint::Time(_)) => format!(
+                    "{:02}:{:02}:{:02}",
+                    seconds / 3600,
+                    seconds / 60 % 60,
+                    seconds % 60
+                ),
+                _ => seconds.to_string(),
+            };
+            match fraction {
+                Some(fraction) => format!("{sign}{whole}.{fraction}"),
+                None => format!("{sign}{whole}"),
+            }
+        }
+        _ => format!("{sign}{value}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A table of the strings a firmware interned, with a microsecond timestamp.
+    fn table(encoding: Encoding) -> Table {
+        let entries = [
+            (1, "defmt_info", "count={=u8} temp={=i16}"),
+            (2, "defmt_warn", "flags={0=0..4} mode={0=4..8}"),
+            (3, "defmt_error", "{=str}: {=[u8]}"),
+            (4, "defmt_derived", "Off|On({=u8})"),
+            (5, "defmt_debug", "state={}"),
+            (6, "defmt_write", "not a log statement"),
+        ];
+        Table {
+            entries: entries
+                .into_iter()
+                .map(|(index, tag, data)| {
+                    let entry = Entry {
+                        tag: tag.to_string(),
+                        data: data.to_string(),
+                    };
+                    (index, entry)
+                })
+                .collect(),
+            timestamp: Some("{=u32:us}".to_string()),
+            encoding,
+        }
+    }
+
+    /// A frame of a log statement at 1.234567 seconds.
+    fn frame(index: u16, args: &[u8]) -> Vec<u8> {
+        let mut frame = index.to_le_bytes().to_vec();
+        frame.extend_from_slice(&1_234_567u32.to_le_bytes());
+        frame.extend_from_slice(args);
+        frame
+    }
+
+    fn decode(index: u16, args: &[u8]) -> (Option<Level>, String) {
+        let frame = table(Encoding::Raw)
+            .decode_frame(&mut Reader::new(&frame(index, args)))
+            .unwrap();
+        assert_eq!(frame.timestamp.as_deref(), Some("1.234567"));
+        (frame.level, frame.message)
+    }
+
+    /// Encode data with rzCOBS, the reverse of [`rzcobs_decode`].
+    fn rzcobs_encode(data: &[u8]) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        let (mut run, mut zeros) = (0u8, 0u8);
+        for &byte in data {
+            if run < 7 {
+                if byte == 0 {
+                    zeros |= 1 << run;
+                } else {
+                    encoded.push(byte);
+                }
+                run += 1;
+                if run == 7 && zeros != 0 {
+                    encoded.push(zeros);
+                    (run, zeros) = (0, 0);
+                }
+            } else if byte == 0 {
+                encoded.push((run - 7) | 0x80);
+                (run, zeros) = (0, 0);
+            } else {
+                encoded.push(byte);
+                run += 1;
+                if run == 134 {
+                    encoded.push(0xff);
+                    (run, zeros) = (0, 0);
+                }
+            }
+        }
+        match run {
+            0 => {}
+            1..=6 => encoded.push((zeros | (0xff << run)) & 0x7f),
+            _ => encoded.push((run - 7) | 0x80),
+        }
+        encoded
+    }
+
+    #[test]
+    fn rzcobs_vectors() {
+        // A short group, padded with zeros.
+        assert_eq!(
+            rzcobs_decode(&[0x11, 0x22, 0x7a]).unwrap(),
+            [0x11, 0x00, 0x22, 0x00, 0x00, 0x00, 0x00]
+        );
+        // Seven bytes without zeros.
+        assert_eq!(
+            rzcobs_decode(&[1, 2, 3, 4, 5, 6, 7, 0x80]).unwrap(),
+            [1, 2, 3, 4, 5, 6, 7, 0]
+        );
+        // A longer run of bytes, ended by a zero.
+        assert_eq!(
+            rzcobs_decode(&[1, 2, 3, 4, 5, 6, 7, 8, 0x81]).unwrap(),
+            [1, 2, 3, 4, 5, 6, 7, 8, 0]
+        );
+        // The longest run of bytes without a zero.
+        let mut run = vec![0x55; 134];
+        run.push(0xff);
+        assert_eq!(rzcobs_decode(&run).unwrap(), [0x55; 134]);
+
+        assert!(rzcobs_decode(&[0x11, 0x00]).is_err());
+        assert!(rzcobs_decode(&[0x7a]).is_err());
+        assert!(rzcobs_decode(&[1, 2, 0x80]).is_err());
+    }
+
+    #[test]
+    fn rzcobs_round_trip() {
+        let data: Vec<u8> = (0..400).map(|index| (index % 9 * 31) as u8).collect();
+        let decoded = rzcobs_decode(&rzcobs_encode(&data)).unwrap();
+        assert_eq!(&decoded[..data.len()], data);
+        assert!(decoded[data.len()..].iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn integers() {
+        assert_eq!(
+            decode(1, &[7, 0xd8, 0xff]),
+            (Some(Level::Info), "count=7 temp=-40".to_string())
+        );
+    }
+
+    #[test]
+    fn bitfields() {
+        assert_eq!(
+            decode(2, &[0xa5]),
+            (Some(Level::Warn), "flags=5 mode=10".to_string())
+        );
+    }
+
+    #[test]
+    fn strings_and_byte_slices() {
+        let mut args = 5u32.to_le_bytes().to_vec();
+        args.extend_from_slice(b"radio");
+        args.extend_from_slice(&3u32.to_le_bytes());
+        args.extend_from_slice(&[1, 2, 255]);
+        assert_eq!(
+            decode(3, &args),
+            (Some(Level::Error), "radio: [1, 2, 255]".to_string())
+        );
+    }
+
+    #[test]
+    fn derived_enums() {
+        assert_eq!(
+            decode(5, &[4, 0, 1, 3]),
+            (Some(Level::Debug), "state=On(3)".to_string())
+        );
+        assert_eq!(decode(5, &[4, 0, 0]).1, "state=Off");
+    }
+
+    #[test]
+    fn invalid_frames() {
+        let table = table(Encoding::Raw);
+        let decode = |frame: &[u8]| table.decode_frame(&mut Reader::new(frame));
+        assert!(matches!(
+            decode(&frame(1, &[7])),
+            Err(DecodeError::Incomplete)
+        ));
+        assert!(matches!(
+            decode(&frame(9, &[])),
+            Err(DecodeError::Invalid(_))
+        ));
+        assert!(matches!(
+            decode(&frame(6, &[])),
+            Err(DecodeError::Invalid(_))
+        ));
+        assert!(matches!(
+            decode(&frame(5, &[4, 0, 2])),
+            Err(DecodeError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn corrupt_lengths_are_invalid() {
+        let table = table(Encoding::Raw);
+        let mut args = u32::MAX.to_le_bytes().to_vec();
+        args.extend_from_slice(b"radio");
+        assert!(matches!(
+            table.decode_frame(&mut Reader::new(&frame(3, &args))),
+            Err(DecodeError::Invalid(_))
+        ));
+
+        // The raw decoder drops the corrupt data and decodes the following frames.
+        let mut decoder = table.decoder();
+        assert!(decoder.push(&frame(3, &args)).is_empty());
+        assert!(!decoder.has_pending());
+        let frames = decoder.push(&frame(2, &[0xa5]));
+        assert_eq!(frames[0].message, "flags=5 mode=10");
+    }
+
+    #[test]
+    fn unterminated_data_is_dropped() {
+        // A string of the largest length, whose data never ends.
+        let mut args = (MAX_FRAME_SIZE as u32).to_le_bytes().to_vec();
+        args.extend(vec![0x55; MAX_FRAME_SIZE / 2]);
+        let raw = frame(3, &args);
+        // rzCOBS data without a zero byte that ends the frame.
+        let rzcobs = vec![0x55; MAX_FRAME_SIZE / 2 + 1];
+
+        for (encoding, stream) in [(Encoding::Raw, raw), (Encoding::Rzcobs, rzcobs)] {
+            let table = table(encoding);
+            let mut decoder = table.decoder();
+            assert!(decoder.push(&stream).is_empty());
+            assert!(decoder.has_pending());
+            assert!(decoder.push(&stream).is_empty());
+            assert!(!decoder.has_pending(), "{encoding:?}");
+        }
+    }
+
+    #[test]
+    fn raw_stream_with_incomplete_frames() {
+        let table = table(Encoding::Raw);
+        let mut decoder = table.decoder();
+        let mut stream = frame(1, &[7, 0xd8, 0xff]);
+        stream.extend(frame(2, &[0xa5]));
+
+        assert!(decoder.push(&stream[..5]).is_empty());
+        assert!(decoder.has_pending());
+        let frames = decoder.push(&stream[5..]);
+        let messages: Vec<_> = frames.into_iter().map(|frame| frame.message).collect();
+        assert_eq!(messages, ["count=7 temp=-40", "flags=5 mode=10"]);
+        assert!(!decoder.has_pending());
+    }
+
+    #[test]
+    fn rzcobs_stream_with_incomplete_frames() {
+        let table = table(Encoding::Rzcobs);
+        let mut decoder = table.decoder();
+        let mut stream = vec![0];
+        stream.extend(rzcobs_encode(&frame(1, &[7, 0xd8, 0xff])));
+        stream.push(0);
+        let second = rzcobs_encode(&frame(2, &[0xa5]));
+
+        let frames = decoder.push(&stream);
+        assert_eq!(frames.len(), 1);
+        let log = frames.into_iter().next().unwrap().into_log();
+        assert_eq!(
+            (log.level.as_str(), log.message.as_str()),
+            ("INFO", "1.234567 count=7 temp=-40")
+        );
+
+        assert!(decoder.push(&second).is_empty());
+        assert!(decoder.has_pending());
+        let frames = decoder.push(&[0]);
+        assert_eq!(frames[0].message, "flags=5 mode=10");
+        assert!(!decoder.has_pending());
+    }
+}
@@ -1,6 +1,16 @@
 pub mod attach;
+pub mod attachments;
+pub mod binary;
 pub mod block;
+pub mod config;
+pub mod device;
 pub mod download;
 pub mod end;
 pub mod log;
+pub mod login;
+pub mod provisioner;
+pub mod report;
+pub mod rtt;
+pub mod run;
 pub mod start;
+pub mod status;
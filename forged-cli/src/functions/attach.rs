@@ -1,22 +1,379 @@
-use crate::Result;
+use std::{
+    fmt::Display,
+    io::{Cursor, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{progress, Result};
+use anyhow::anyhow;
 use cynic::MutationBuilder;
+use serde::Serialize;
+use uuid::Uuid;
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
 
 use crate::queries::{CreateAttachment, CreateAttachmentArguments};
 
-pub async fn attach(client: &mut forged::Client, file_path: String) -> Result<()> {
-    println!("📎  Attaching file: {file_path}");
+/// The attachments that were created.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Attached {
+    pub attachments: Vec<AttachedFile>,
+}
+
+/// An attachment that was created from a local file.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AttachedFile {
+    pub attachment_id: Uuid,
+    pub name: String,
+    pub mime_type: String,
+    pub size: usize,
+}
+
+impl Display for Attached {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for attachment in &self.attachments {
+            writeln!(
+                f,
+                "attachment: {} ({}, {}, {} bytes)",
+                attachment.attachment_id, attachment.name, attachment.mime_type, attachment.size
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// A local file read for attaching.
+struct Pending {
+    path: PathBuf,
+    name: String,
+    mime_type: String,
+    data: Vec<u8>,
+}
+
+/// Attach files to the current run.
+///
+/// All files are read and checked before the first one is uploaded.
+///
+/// # Args
+/// * `paths` - The files to attach, or glob patterns matching them.
+/// * `name` - The name of the attachment instead of the file name, for a single file.
+/// * `zip` - Whether directories are attached as zip archives. They are rejected otherwise.
+/// * `max_size` - The size of the largest attachment in bytes.
+pub async fn attach(
+    client: &mut forged::Client,
+    paths: &[String],
+    name: Option<String>,
+    zip: bool,
+    max_size: u64,
+) -> Result<Attached> {
+    let files = expand_paths(paths)?;
+    if name.is_some() && files.len() > 1 {
+        return Err(anyhow!(
+            "--name can only be used with a single file, but {} files were given",
+            files.len()
+        )
+        .into());
+    }
+
+    let mut pending = files
+        .iter()
+        .map(|path| read_file(path, zip, max_size))
+        .collect::<Result<Vec<_>>>()?;
+    if let (Some(name), [file]) = (name, pending.as_mut_slice()) {
+        file.name = name;
+    }
+
+    let mut attachments = Vec::with_capacity(pending.len());
+    for file in pending {
+        progress!("📎  Attaching file: {}", file.path.display());
+
+        let size = file.data.len();
+        let upload = forged::Upload::new(file.name.clone(), file.data)
+            .with_mime_type(file.mime_type.clone());
+        let attachment = client
+            .run_query_with_file_upload(
+                CreateAttachment::build(CreateAttachmentArguments {
+                    data: upload.clone(),
+                }),
+                vec![upload],
+            )
+            .await?
+            .attachment_create;
+
+        // Report what the server stored, which may differ from the local guess.
+        attachments.push(AttachedFile {
+            attachment_id: attachment.id,
+            name: attachment.name,
+            mime_type: attachment.mime_type,
+            size,
+        });
+    }
 
-    let data = std::fs::read(&file_path).unwrap();
+    Ok(Attached { attachments })
+}
+
+/// Resolve paths and glob patterns to the files they name, in the order they were given.
+///
+/// # Note
+/// Directories matched by a glob pattern are skipped, only directories given explicitly are
+/// attached or rejected.
+fn expand_paths(paths: &[String]) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for path in paths {
+        if Path::new(path).exists() {
+            files.push(PathBuf::from(path));
+            continue;
+        }
+        if !path.contains(['*', '?', '[']) {
+            return Err(anyhow!("File {path} does not exist").into());
+        }
 
-    let upload = forged::Upload::new(file_path.clone(), data);
-    client
-        .run_query_with_file_upload(
-            CreateAttachment::build(CreateAttachmentArguments {
-                data: upload.clone(),
-            }),
-            vec![upload],
+        let matches = glob::glob(path)
+            .map_err(|e| anyhow!("Invalid glob pattern {path}: {e}"))?
+            .filter(|entry| !matches!(entry, Ok(path) if path.is_dir()))
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| anyhow!("Failed to expand {path}: {e}"))?;
+        if matches.is_empty() {
+            return Err(anyhow!("No files match {path}").into());
+        }
+        files.extend(matches);
+    }
+
+    Ok(files)
+}
+
+/// Read a file or zip a directory, checking it against the size limit.
+fn read_file(path: &Path, zip: bool, max_size: u64) -> Result<Pending> {
+    let base_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .ok_or_else(|| anyhow!("{} has no file name", path.display()))?;
+
+    let (name, mime_type, data) = if path.is_dir() {
+        if !zip {
+            return Err(anyhow!(
+                "{} is a directory. Use --zip to attach it as a zip archive",
+                path.display()
+            )
+            .into());
+        }
+        let data = zip_directory(path)?;
+        check_size(path, data.len() as u64, max_size)?;
+        (
+            format!("{base_name}.zip"),
+            "application/zip".to_string(),
+            data,
         )
-        .await?;
+    } else {
+        let metadata = std::fs::metadata(path)
+            .map_err(|e| anyhow!("Failed to read {}: {e}", path.display()))?;
+        // Check before reading, so huge files are not loaded just to be rejected.
+        check_size(path, metadata.len(), max_size)?;
+        let data =
+            std::fs::read(path).map_err(|e| anyhow!("Failed to read {}: {e}", path.display()))?;
+        (base_name, mime_type(path, &data), data)
+    };
+
+    Ok(Pending {
+        path: path.to_path_buf(),
+        name,
+        mime_type,
+        data,
+    })
+}
 
+fn check_size(path: &Path, size: u64, max_size: u64) -> Result<()> {
+    if size > max_size {
+        return Err(anyhow!(
+            "{} is {size} bytes, larger than the limit of {max_size} bytes. Raise it with --max-size",
+            path.display()
+        )
+        .into());
+    }
     Ok(())
 }
+
+/// The MIME type of a file from its extension. Files without a known extension are plain text if
+/// they are valid UTF-8.
+fn mime_type(path: &Path, data: &[u8]) -> String {
+    match mime_guess::from_path(path).first() {
+        Some(mime) => mime.to_string(),
+        None if std::str::from_utf8(data).is_ok() => "text/plain".to_string(),
+        None => "application/octet-stream".to_string(),
+    }
+}
+
+/// Zip the files in a directory and its subdirectories, with paths relative to it.
+fn zip_directory(dir: &Path) -> Result<Vec<u8>> {
+    let mut files = Vec::new();
+    collect_files(dir, &mut files)?;
+    files.sort();
+
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    for file in files {
+        let relative = file
+            .strip_prefix(dir)
+            .expect("Collected files are inside the directory");
+        let name = relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let data =
+            std::fs::read(&file).map_err(|e| anyhow!("Failed to read {}: {e}", file.display()))?;
+
+        writer
+            .start_file(name, options)
+            .and_then(|_| Ok(writer.write_all(&data)?))
+            .map_err(|e| anyhow!("Failed to zip {}: {e}", file.display()))?;
+    }
+
+    let archive = writer
+        .finish()
+        .map_err(|e| anyhow!("Failed to zip {}: {e}", dir.display()))?;
+    Ok(archive.into_inner())
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let entries =
+        std::fs::read_dir(dir).map_err(|e| anyhow!("Failed to read {}: {e}", dir.display()))?;
+    for entry in entries {
+        let path = entry
+            .map_err(|e| anyhow!("Failed to read {}: {e}", dir.display()))?
+            .path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use zip::ZipArchive;
+
+    use super::*;
+
+    fn error<T>(result: Result<T>) -> String {
+        match result {
+            Ok(_) => panic!("Expected an error"),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    fn write(path: &Path, content: &[u8]) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn globs_skip_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        write(&dir.path().join("b.log"), b"b");
+        write(&dir.path().join("a.log"), b"a");
+        write(&dir.path().join("notes.txt"), b"notes");
+        write(&dir.path().join("old.log").join("c.log"), b"c");
+
+        let pattern = dir.path().join("*.log").to_string_lossy().into_owned();
+        let notes = dir.path().join("notes.txt").to_string_lossy().into_owned();
+        assert_eq!(
+            expand_paths(&[notes, pattern]).unwrap(),
+            [
+                dir.path().join("notes.txt"),
+                dir.path().join("a.log"),
+                dir.path().join("b.log")
+            ]
+        );
+
+        // A directory given explicitly is kept, to be zipped or rejected.
+        let old = dir.path().join("old.log").to_string_lossy().into_owned();
+        assert_eq!(expand_paths(&[old]).unwrap(), [dir.path().join("old.log")]);
+    }
+
+    #[test]
+    fn missing_files_and_patterns() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let missing = dir
+            .path()
+            .join("missing.log")
+            .to_string_lossy()
+            .into_owned();
+        assert!(error(expand_paths(&[missing])).contains("does not exist"));
+
+        let pattern = dir.path().join("*.log").to_string_lossy().into_owned();
+        assert!(error(expand_paths(&[pattern])).contains("No files match"));
+    }
+
+    #[test]
+    fn size_limit_is_checked_before_reading() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("huge.bin");
+        // A sparse file that would take gigabytes of memory if it was read.
+        std::fs::File::create(&path)
+            .unwrap()
+            .set_len(4 << 30)
+            .unwrap();
+
+        let message = error(read_file(&path, false, 1024));
+        assert!(message.contains("4294967296 bytes"), "{message}");
+        assert!(message.contains("limit of 1024 bytes"), "{message}");
+
+        let small = dir.path().join("small.txt");
+        write(&small, b"12345");
+        assert_eq!(read_file(&small, false, 5).unwrap().data, b"12345");
+        assert!(error(read_file(&small, false, 4)).contains("larger than the limit"));
+    }
+
+    #[test]
+    fn mime_types() {
+        assert_eq!(mime_type(Path::new("report.html"), b"<p>"), "text/html");
+        assert_eq!(mime_type(Path::new("trace.png"), b"\x89PNG"), "image/png");
+        // Unknown extensions fall back to the content.
+        assert_eq!(mime_type(Path::new("boot.out"), b"booted\n"), "text/plain");
+        assert_eq!(mime_type(Path::new("LOG"), "µs ✓".as_bytes()), "text/plain");
+        assert_eq!(
+            mime_type(Path::new("dump.out"), &[0xff, 0xfe, 0x00]),
+            "application/octet-stream"
+        );
+    }
+
+    #[test]
+    fn directories_are_zipped_with_relative_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let logs = dir.path().join("logs");
+        write(&logs.join("boot.log"), b"booted");
+        write(
+            &logs.join("nested").join("deep").join("trace.bin"),
+            &[0, 1, 2],
+        );
+
+        assert!(error(read_file(&logs, false, u64::MAX)).contains("--zip"));
+
+        let pending = read_file(&logs, true, u64::MAX).unwrap();
+        assert_eq!(pending.name, "logs.zip");
+        assert_eq!(pending.mime_type, "application/zip");
+
+        let mut archive = ZipArchive::new(Cursor::new(pending.data)).unwrap();
+        let mut names: Vec<_> = archive.file_names().map(str::to_string).collect();
+        names.sort();
+        assert_eq!(names, ["boot.log", "nested/deep/trace.bin"]);
+
+        let mut data = Vec::new();
+        archive
+            .by_name("nested/deep/trace.bin")
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+        assert_eq!(data, [0, 1, 2]);
+
+        // The limit applies to the archive.
+        assert!(error(read_file(&logs, true, 10)).contains("larger than the limit"));
+    }
+}
@@ -0,0 +1,208 @@
+use std::{
+    collections::HashSet,
+    fmt::{self, Display},
+    path::{Path, PathBuf},
+};
+
+use anyhow::anyhow;
+use forged::runs::Attachment;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{cli::AttachmentsOption, progress, Result};
+
+/// The attachments that were saved.
+#[derive(Serialize)]
+pub struct Pulled {
+    pub files: Vec<PulledFile>,
+}
+
+/// An attachment that was saved to disk.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PulledFile {
+    pub attachment_id: Uuid,
+    pub path: PathBuf,
+    pub mime_type: String,
+    pub size: usize,
+}
+
+impl Display for Pulled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for file in &self.files {
+            writeln!(f, "{}", file.path.display())?;
+        }
+        Ok(())
+    }
+}
+
+pub async fn attachments(client: &forged::Client, option: AttachmentsOption) -> Result<Pulled> {
+    match option {
+        AttachmentsOption::Pull { run, out_dir } => {
+            progress!("⬇️  Fetching attachments ...");
+            let attachments = client.attachments(run.run_ref()).await?;
+            Ok(Pulled {
+                files: save(attachments, &out_dir)?,
+            })
+        }
+    }
+}
+
+/// Save attachments as files of a directory, see [`file_names`].
+fn save(attachments: Vec<Attachment>, out_dir: &Path) -> Result<Vec<PulledFile>> {
+    std::fs::create_dir_all(out_dir)
+        .map_err(|e| anyhow!("Failed to create directory {out_dir:?}: {e}"))?;
+
+    let names = file_names(&attachments);
+    let mut files = Vec::with_capacity(attachments.len());
+    for (attachment, name) in attachments.into_iter().zip(names) {
+        let path = out_dir.join(name);
+        progress!("⬇️  Saving {} to {path:?}", attachment.name);
+        std::fs::write(&path, &attachment.data)
+            .map_err(|e| anyhow!("Failed to write {path:?}: {e}"))?;
+
+        files.push(PulledFile {
+            attachment_id: attachment.id,
+            path,
+            mime_type: attachment.mime_type,
+            size: attachment.data.len(),
+        });
+    }
+
+    Ok(files)
+}
+
+/// Unique file names for attachments, without any directories of their names.
+///
+/// Attachments with the same name get a numbered suffix, see [`unique_file_names`].
+pub fn file_names(attachments: &[Attachment]) -> Vec<String> {
+    unique_file_names(attachments.iter().map(|attachment| {
+        // Older versions of the CLI uploaded the full local path as the name.
+        match attachment.name.rsplit(['/', '\\']).next() {
+            Some(name) if !matches!(name, "" | "." | "..") => name.to_string(),
+            _ => format!("attachment-{}", attachment.id),
+        }
+    }))
+}
+
+/// Give repeated file names a numbered suffix, e.g. `trace.log` and `trace-1.log`.
+pub fn unique_file_names(names: impl IntoIterator<Item = String>) -> Vec<String> {
+    let mut taken = HashSet::new();
+    names
+        .into_iter()
+        .map(|name| {
+            let mut unique = name.clone();
+            let mut counter = 1;
+            while !taken.insert(unique.clone()) {
+                unique = match Path::new(&name).extension() {
+                    Some(extension) => format!(
+                        "{}-{counter}.{}",
+                        Path::new(&name)
+                            .file_stem()
+                            .unwrap_or_default()
+                            .to_string_lossy(),
+                        extension.to_string_lossy()
+                    ),
+                    None => format!("{name}-{counter}"),
+                };
+                counter += 1;
+            }
+            unique
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attachment(id: u128, name: &str) -> Attachment {
+        Attachment {
+            id: Uuid::from_u128(id),
+            name: name.to_string(),
+            mime_type: "text/plain".to_string(),
+            data: name.as_bytes().to_vec(),
+        }
+    }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        let attachments: Vec<_> = names
+            .iter()
+            .enumerate()
+            .map(|(id, name)| attachment(id as u128, name))
+            .collect();
+        file_names(&attachments)
+    }
+
+    #[test]
+    fn strips_directories() {
+        assert_eq!(
+            names(&[
+                "../x",
+                "a/b",
+                "/etc/passwd",
+                "C:\\logs\\boot.log",
+                "trace.log"
+            ]),
+            ["x", "b", "passwd", "boot.log", "trace.log"]
+        );
+    }
+
+    #[test]
+    fn names_without_a_file_name() {
+        assert_eq!(
+            names(&["..", "logs/", ".", ""]),
+            [
+                format!("attachment-{}", Uuid::from_u128(0)),
+                format!("attachment-{}", Uuid::from_u128(1)),
+                format!("attachment-{}", Uuid::from_u128(2)),
+                format!("attachment-{}", Uuid::from_u128(3)),
+            ]
+        );
+    }
+
+    #[test]
+    fn numbers_duplicates() {
+        assert_eq!(
+            names(&[
+                "trace.log",
+                "trace.log",
+                "a/trace.log",
+                "dump",
+                "dump",
+                "trace-1.log"
+            ]),
+            [
+                "trace.log",
+                "trace-1.log",
+                "trace-2.log",
+                "dump",
+                "dump-1",
+                "trace-1-1.log"
+            ]
+        );
+    }
+
+    #[test]
+    fn saves_inside_the_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let out_dir = dir.path().join("pulled");
+        let attachments = vec![
+            attachment(0, "../x"),
+            attachment(1, "a/b"),
+            attachment(2, "b"),
+        ];
+
+        let files = save(attachments, &out_dir).unwrap();
+
+        let paths: Vec<_> = files.iter().map(|file| file.path.clone()).collect();
+        assert_eq!(
+            paths,
+            [out_dir.join("x"), out_dir.join("b"), out_dir.join("b-1")]
+        );
+        assert_eq!(std::fs::read(out_dir.join("b")).unwrap(), b"a/b");
+        assert_eq!(std::fs::read(out_dir.join("b-1")).unwrap(), b"b");
+        assert!(!dir.path().join("x").exists());
+        assert_eq!(std::fs::read_dir(&out_dir).unwrap().count(), 3);
+    }
+}
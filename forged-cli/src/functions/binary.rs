@@ -0,0 +1,727 @@
+use std::{
+    fmt::{self, Display},
+    path::{Path, PathBuf},
+};
+
+use anyhow::anyhow;
+use cynic::{MutationBuilder, QueryBuilder};
+use forged::{BinaryKind, BinaryPartUpload};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{
+    cli::{parse_address, BinaryOption},
+    functions::attachments::unique_file_names,
+    output::write_table,
+    progress,
+    queries::{self, Binary, BinaryPart, Chip, Chips, DeleteBinary, DeleteBinaryArguments},
+    Result,
+};
+
+/// The outcome of a `binary` command.
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum BinaryResult {
+    Uploaded(Uploaded),
+    List(Vec<Binary>),
+    Shown(Binary),
+    #[serde(rename_all = "camelCase")]
+    Deleted {
+        deleted_id: Uuid,
+    },
+    Pulled {
+        files: Vec<PathBuf>,
+    },
+    Diff(BinaryDiff),
+}
+
+/// The binary that was uploaded.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Uploaded {
+    pub binary_id: Uuid,
+    pub chip_id: Uuid,
+    pub version: String,
+}
+
+/// The differences between two binaries of a chip.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BinaryDiff {
+    pub chip: String,
+    pub from: Binary,
+    pub to: Binary,
+    pub parts: Vec<PartDiff>,
+}
+
+/// A part of either binary, matched by its name or, if it has none, its position.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PartDiff {
+    pub part: String,
+    pub change: PartChange,
+    pub from: Option<BinaryPart>,
+    pub to: Option<BinaryPart>,
+}
+
+/// How a part changed between two binaries.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PartChange {
+    Added,
+    Removed,
+    /// The kind, offset or image of the part differs.
+    Changed,
+    Unchanged,
+}
+
+impl Display for BinaryResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BinaryResult::Uploaded(uploaded) => writeln!(f, "binary: {}", uploaded.binary_id),
+            BinaryResult::List(binaries) => write_table(
+                f,
+                ["VERSION", "ID", "ACTIVE", "CREATED", "NVM SIZE"],
+                binaries.iter().map(|binary| {
+                    [
+                        binary.version.clone(),
+                        binary.id.to_string(),
+                        if binary.deletion_date.is_some() {
+                            "deleted"
+                        } else if binary.active {
+                            "yes"
+                        } else {
+                            "no"
+                        }
+                        .to_string(),
+                        binary.creation_date.date().to_string(),
+                        binary
+                            .analysis
+                            .as_ref()
+                            .map(|analysis| analysis.nvm_size.to_string())
+                            .unwrap_or_else(|| "-".to_string()),
+                    ]
+                }),
+            ),
+            BinaryResult::Shown(binary) => write_binary(f, binary),
+            BinaryResult::Deleted { deleted_id } => writeln!(f, "deleted: {deleted_id}"),
+            BinaryResult::Pulled { files } => {
+                for file in files {
+                    writeln!(f, "{}", file.display())?;
+                }
+                Ok(())
+            }
+            BinaryResult::Diff(diff) => write_diff(f, diff),
+        }
+    }
+}
+
+fn write_binary(f: &mut fmt::Formatter<'_>, binary: &Binary) -> fmt::Result {
+    writeln!(f, "Binary:   {}", binary.id)?;
+    writeln!(f, "Version:  {}", binary.version)?;
+    writeln!(f, "Created:  {}", binary.creation_date.0)?;
+    if let Some(deleted) = &binary.deletion_date {
+        writeln!(f, "Deleted:  {}", deleted.0)?;
+    }
+    writeln!(f, "Active:   {}", if binary.active { "yes" } else { "no" })?;
+    if let Some(analysis) = &binary.analysis {
+        writeln!(f, "NVM size: {} bytes", analysis.nvm_size)?;
+    }
+
+    writeln!(f)?;
+    write_table(
+        f,
+        [
+            "PART",
+            "KIND",
+            "OFFSET",
+            "NVM SIZE",
+            "RTT HEADER",
+            "SHA-256",
+        ],
+        binary.parts.iter().enumerate().map(|(index, part)| {
+            let analysis = part.analysis.as_ref();
+            [
+                part_label(index, part),
+                format!("{:?}", part.kind).to_uppercase(),
+                part.memory_offset
+                    .map(|offset| format!("{:#010x}", offset as u32))
+                    .unwrap_or_else(|| "-".to_string()),
+                analysis
+                    .map(|analysis| analysis.nvm_size.to_string())
+                    .unwrap_or_else(|| "-".to_string()),
+                analysis
+                    .and_then(|analysis| analysis.rtt.header_location)
+                    .map(|location| format!("{:#010x}", location as u32))
+                    .unwrap_or_else(|| "-".to_string()),
+                part.hash(),
+            ]
+        }),
+    )
+}
+
+fn write_diff(f: &mut fmt::Formatter<'_>, diff: &BinaryDiff) -> fmt::Result {
+    writeln!(
+        f,
+        "Binary:   v{} → v{} of {}",
+        diff.from.version, diff.to.version, diff.chip
+    )?;
+    let nvm_size = |binary: &Binary| binary.analysis.as_ref().map(|analysis| analysis.nvm_size);
+    writeln!(
+        f,
+        "NVM size: {}",
+        size_change(nvm_size(&diff.from), nvm_size(&diff.to))
+    )?;
+
+    writeln!(f)?;
+    write_table(
+        f,
+        ["PART", "CHANGE", "KIND", "OFFSET", "NVM SIZE", "SHA-256"],
+        diff.parts.iter().map(|part| {
+            let (from, to) = (part.from.as_ref(), part.to.as_ref());
+            let compare = |value: fn(&BinaryPart) -> String| match (from.map(value), to.map(value))
+            {
+                (Some(from), Some(to)) if from != to => format!("{from} → {to}"),
+                (from, to) => from.or(to).unwrap_or_else(|| "-".to_string()),
+            };
+            let nvm_size = |part: Option<&BinaryPart>| {
+                part.and_then(|part| part.analysis.as_ref())
+                    .map(|analysis| analysis.nvm_size)
+            };
+
+            [
+                part.part.clone(),
+                format!("{:?}", part.change).to_lowercase(),
+                compare(|part| format!("{:?}", part.kind).to_uppercase()),
+                compare(|part| {
+                    part.memory_offset
+                        .map(|offset| format!("{:#010x}", offset as u32))
+                        .unwrap_or_else(|| "-".to_string())
+                }),
+                size_change(nvm_size(from), nvm_size(to)),
+                compare(|part| part.hash().chars().take(12).collect()),
+            ]
+        }),
+    )
+}
+
+/// Format a size that may have changed, e.g. `1024 → 1536 (+512)`.
+fn size_change(from: Option<i32>, to: Option<i32>) -> String {
+    match (from, to) {
+        (Some(from), Some(to)) if from != to => format!("{from} → {to} ({:+})", to - from),
+        (from, to) => from
+            .or(to)
+            .map(|size| size.to_string())
+            .unwrap_or_else(|| "-".to_string()),
+    }
+}
+
+/// Manage the firmware binaries of the project's chips.
+pub async fn binary(client: &mut forged::Client, option: BinaryOption) -> Result<BinaryResult> {
+    let chips = client
+        .run_query(Chips::build(()))
+        .await?
+        .current_provisioner
+        .project
+        .chips;
+
+    match option {
+        BinaryOption::Upload {
+            chip,
+            version,
+            parts,
+        } => {
+            let chip = find_chip(&chips, Some(&chip))?;
+            Ok(BinaryResult::Uploaded(
+                upload(client, chip, version, parts).await?,
+            ))
+        }
+
+        BinaryOption::List { chip } => {
+            let chip = find_chip(&chips, chip.as_deref())?;
+            let mut binaries = chip.binaries.clone();
+            binaries.sort_by_key(|binary| std::cmp::Reverse(binary.version()));
+            Ok(BinaryResult::List(binaries))
+        }
+
+        BinaryOption::Show { version, chip } => {
+            let chip = find_chip(&chips, chip.as_deref())?;
+            Ok(BinaryResult::Shown(find_binary(chip, &version)?.clone()))
+        }
+
+        BinaryOption::Delete { version, chip } => {
+            let chip = find_chip(&chips, chip.as_deref())?;
+            let binary = find_binary(chip, &version)?;
+            progress!("🗑️  Deleting v{} of {} ...", binary.version, chip.name);
+            let deleted_id = client
+                .run_query(DeleteBinary::build(DeleteBinaryArguments { id: binary.id }))
+                .await?
+                .binary_delete;
+
+            Ok(BinaryResult::Deleted { deleted_id })
+        }
+
+        BinaryOption::Diff { from, to, chip } => {
+            let chip = find_chip(&chips, chip.as_deref())?;
+            let (from, to) = (find_binary(chip, &from)?, find_binary(chip, &to)?);
+            Ok(BinaryResult::Diff(BinaryDiff {
+                chip: chip.name.clone(),
+                parts: diff_parts(from, to),
+                from: from.clone(),
+                to: to.clone(),
+            }))
+        }
+
+        BinaryOption::Pull {
+            version,
+            chip,
+            out_dir,
+        } => {
+            let chip = find_chip(&chips, chip.as_deref())?;
+            let binary = find_binary(chip, &version)?;
+            std::fs::create_dir_all(&out_dir)
+                .map_err(|e| anyhow!("Failed to create directory {out_dir:?}: {e}"))?;
+
+            let mut files = Vec::with_capacity(binary.parts.len());
+            let names = part_file_names(&binary.parts);
+            for (index, (part, name)) in binary.parts.iter().zip(names).enumerate() {
+                let path = out_dir.join(name);
+                progress!(
+                    "⬇️  Pulling part {}/{} to {path:?}",
+                    index + 1,
+                    binary.parts.len()
+                );
+
+                let data = client
+                    .binary_part(chip.id, binary.id, part.id, None)
+                    .await?;
+                std::fs::write(&path, data)
+                    .map_err(|e| anyhow!("Failed to write {path:?}: {e}"))?;
+                files.push(path);
+            }
+
+            Ok(BinaryResult::Pulled { files })
+        }
+    }
+}
+
+async fn upload(
+    client: &forged::Client,
+    chip: &Chip,
+    version: String,
+    parts: Vec<String>,
+) -> Result<Uploaded> {
+    semver::Version::parse(&version).map_err(|e| anyhow!("Invalid version `{version}`: {e}"))?;
+
+    let parts = parts
+        .iter()
+        .map(|part| read_part(part))
+        .collect::<Result<Vec<_>>>()?;
+    for part in &parts {
+        progress!(
+            "📦 {} ({:?}, {} bytes)",
+            part.name,
+            part.kind,
+            part.image.len()
+        );
+    }
+
+    progress!("⬆️  Uploading v{version} for {} ...", chip.name);
+    let binary_id = client.upload_binary(chip.id, &version, parts).await?;
+
+    Ok(Uploaded {
+        binary_id,
+        chip_id: chip.id,
+        version,
+    })
+}
+
+/// Find a chip of the project by its name or id. Without a name, the project must have exactly
+/// one chip.
+pub fn find_chip<'a>(chips: &'a [Chip], chip: Option<&str>) -> Result<&'a Chip> {
+    let available = || {
+        chips
+            .iter()
+            .map(|chip| chip.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    let Some(chip) = chip else {
+        return match chips {
+            [chip] => Ok(chip),
+            [] => Err(anyhow!("No chips have been configured for this project.").into()),
+            _ => Err(anyhow!(
+                "Multiple chips found for this project. Please specify one with `--chip`. \
+                Available chips: [ {} ]",
+                available()
+            )
+            .into()),
+        };
+    };
+
+    let id = Uuid::parse_str(chip).ok();
+    chips
+        .iter()
+        .find(|candidate| Some(candidate.id) == id || candidate.name == chip)
+        .ok_or_else(|| {
+            anyhow!(
+                "Chip `{chip}` not found. Available chips: [ {} ]",
+                available()
+            )
+            .into()
+        })
+}
+
+/// Find a binary of a chip by its version. Deleted binaries are only found if no other binary has
+/// the version.
+fn find_binary<'a>(chip: &'a Chip, version: &str) -> Result<&'a Binary> {
+    let wanted = semver::Version::parse(version).ok();
+    let matches = |binary: &&Binary| match &wanted {
+        Some(wanted) => binary.version() == *wanted,
+        None => binary.version == version,
+    };
+
+    chip.binaries
+        .iter()
+        .filter(matches)
+        .min_by_key(|binary| binary.deletion_date.is_some())
+        .ok_or_else(|| {
+            anyhow!(
+                "Binary version `{version}` not found for chip `{}`. Available versions: [ {} ]",
+                chip.name,
+                chip.binaries
+                    .iter()
+                    .map(|binary| binary.version.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+            .into()
+        })
+}
+
+/// The name of a part, or its position if it has none.
+fn part_label(index: usize, part: &BinaryPart) -> String {
+    part.name.clone().unwrap_or_else(|| format!("#{index}"))
+}
+
+/// Match the parts of two binaries by their label and compare them. Parts of `to` come first in
+/// their order, followed by the parts that were removed.
+fn diff_parts(from: &Binary, to: &Binary) -> Vec<PartDiff> {
+    let label = |(index, part): (usize, &BinaryPart)| (part_label(index, part), part.clone());
+    let mut removed: Vec<_> = from.parts.iter().enumerate().map(label).collect();
+
+    let mut parts: Vec<_> = to
+        .parts
+        .iter()
+        .enumerate()
+        .map(label)
+        .map(|(name, new)| {
+            let old = removed
+                .iter()
+                .position(|(old, _)| *old == name)
+                .map(|position| removed.remove(position).1);
+            let change = match &old {
+                None => PartChange::Added,
+                Some(old)
+                    if old.kind == new.kind
+                        && old.memory_offset == new.memory_offset
+                        && old.image_hash == new.image_hash =>
+                {
+                    PartChange::Unchanged
+                }
+                Some(_) => PartChange::Changed,
+            };
+            PartDiff {
+                part: name,
+                change,
+                from: old,
+                to: Some(new),
+            }
+        })
+        .collect();
+
+    parts.extend(removed.into_iter().map(|(name, old)| PartDiff {
+        part: name,
+        change: PartChange::Removed,
+        from: Some(old),
+        to: None,
+    }));
+    parts
+}
+
+/// The unique file names the parts of a binary are pulled to, see [`part_file_name`].
+fn part_file_names(parts: &[BinaryPart]) -> Vec<String> {
+    unique_file_names(
+        parts
+            .iter()
+            .enumerate()
+            .map(|(index, part)| part_file_name(index, part)),
+    )
+}
+
+/// The file name a part is pulled to. Unnamed parts are named after their position and kind.
+fn part_file_name(index: usize, part: &BinaryPart) -> String {
+    part.name
+        .as_deref()
+        .and_then(|name| Path::new(name).file_name())
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| {
+            let extension = match part.kind {
+                queries::BinaryKind::Elf => "elf",
+                queries::BinaryKind::Bin => "bin",
+                queries::BinaryKind::Hex => "hex",
+            };
+            format!("part{index}.{extension}")
+        })
+}
+
+/// Split a part given as `path` or `path@address`.
+///
+/// The suffix after the last `@` is only taken as the address if it is one, so paths containing
+/// an `@` can be given without an address.
+fn split_part(part: &str) -> (&str, Option<u64>) {
+    part.rsplit_once('@')
+        .and_then(|(path, address)| Some((path, Some(parse_address(address).ok()?))))
+        .unwrap_or((part, None))
+}
+
+/// Read a part given as `path` or `path@address`.
+pub fn read_part(part: &str) -> Result<BinaryPartUpload> {
+    let (path, memory_offset) = split_part(part);
+    let memory_offset = memory_offset
+        .map(|address| {
+            u32::try_from(address)
+                .map_err(|_| anyhow!("Address {address:#x} of `{path}` is out of range"))
+        })
+        .transpose()?;
+
+    let image = std::fs::read(path).map_err(|e| anyhow!("Failed to read `{path}`: {e}"))?;
+    let name = Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.to_string());
+
+    let part = BinaryPartUpload::new(name, image, memory_offset);
+    if part.kind != BinaryKind::Bin && memory_offset.is_some() {
+        return Err(anyhow!(
+            "`{path}` is not a raw binary, its load address is taken from the file itself"
+        )
+        .into());
+    }
+
+    Ok(part)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_part_address() {
+        assert_eq!(split_part("app.bin"), ("app.bin", None));
+        assert_eq!(
+            split_part("app.bin@0x08000000"),
+            ("app.bin", Some(0x0800_0000))
+        );
+        assert_eq!(split_part("app.bin@4096"), ("app.bin", Some(4096)));
+        assert_eq!(
+            split_part("build@v2/app.bin@0x1000"),
+            ("build@v2/app.bin", Some(0x1000))
+        );
+    }
+
+    #[test]
+    fn split_part_without_address() {
+        assert_eq!(split_part("build@v2/app.bin"), ("build@v2/app.bin", None));
+        assert_eq!(split_part("user@host.elf"), ("user@host.elf", None));
+        assert_eq!(split_part("app.bin@"), ("app.bin@", None));
+    }
+
+    #[test]
+    fn read_part_address_out_of_range() {
+        let error = read_part("app.bin@0x100000000").unwrap_err();
+        assert!(error.to_string().contains("out of range"));
+    }
+
+    fn chip(id: u128, name: &str) -> Chip {
+        Chip {
+            id: Uuid::from_u128(id),
+            name: name.to_string(),
+            part_number: "STM32F401".to_string(),
+            binary_newest: None,
+            binaries: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn find_chip_by_name_or_id() {
+        let chips = [chip(1, "main"), chip(2, "radio")];
+        let found = |name: &str| find_chip(&chips, Some(name)).map(|chip| chip.id);
+
+        assert_eq!(found("radio").unwrap(), Uuid::from_u128(2));
+        assert_eq!(
+            found(&Uuid::from_u128(1).to_string()).unwrap(),
+            Uuid::from_u128(1)
+        );
+        let error = found("bootloader").unwrap_err().to_string();
+        assert!(
+            error.contains("Available chips: [ main, radio ]"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn find_chip_without_name() {
+        let single = [chip(1, "main")];
+        assert_eq!(find_chip(&single, None).unwrap().name, "main");
+
+        assert!(find_chip(&[], None).is_err());
+        let error = find_chip(&[chip(1, "main"), chip(2, "radio")], None).unwrap_err();
+        assert!(error.to_string().contains("Please specify one"));
+    }
+
+    fn part(name: Option<&str>, offset: Option<i32>, hash: i32, nvm_size: i32) -> BinaryPart {
+        BinaryPart {
+            id: Uuid::nil(),
+            name: name.map(str::to_string),
+            kind: queries::BinaryKind::Elf,
+            memory_offset: offset,
+            image_hash: vec![hash; 32],
+            analysis: Some(queries::BinaryPartAnalysis {
+                nvm_size,
+                rtt: queries::RttAnalysis {
+                    header_location: None,
+                },
+            }),
+        }
+    }
+
+    fn binary(version: &str, parts: Vec<BinaryPart>) -> Binary {
+        Binary {
+            id: Uuid::nil(),
+            version: version.to_string(),
+            version_major: 0,
+            version_minor: 0,
+            version_patch: 0,
+            creation_date: queries::DateTime("2024-01-01T00:00:00Z".to_string()),
+            deletion_date: None,
+            active: true,
+            analysis: Some(queries::BinaryAnalysis {
+                nvm_size: parts
+                    .iter()
+                    .filter_map(|part| part.analysis.as_ref())
+                    .map(|analysis| analysis.nvm_size)
+                    .sum(),
+            }),
+            parts,
+        }
+    }
+
+    #[test]
+    fn diff_parts_by_name_or_position() {
+        let from = binary(
+            "1.0.0",
+            vec![
+                part(Some("app.elf"), None, 1, 1024),
+                part(Some("bootloader.bin"), Some(0x0800_0000), 2, 256),
+                part(Some("config.bin"), Some(0x0807_0000), 3, 64),
+                part(None, None, 4, 16),
+            ],
+        );
+        let to = binary(
+            "1.1.0",
+            vec![
+                part(Some("bootloader.bin"), Some(0x0800_0000), 2, 256),
+                part(Some("app.elf"), None, 5, 1536),
+                part(Some("calibration.bin"), Some(0x0806_0000), 6, 32),
+                part(None, Some(0x0808_0000), 4, 16),
+            ],
+        );
+
+        let parts: Vec<_> = diff_parts(&from, &to)
+            .into_iter()
+            .map(|part| (part.part, part.change))
+            .collect();
+        assert_eq!(
+            parts,
+            [
+                ("bootloader.bin".to_string(), PartChange::Unchanged),
+                ("app.elf".to_string(), PartChange::Changed),
+                ("calibration.bin".to_string(), PartChange::Added),
+                ("#3".to_string(), PartChange::Changed),
+                ("config.bin".to_string(), PartChange::Removed),
+            ]
+        );
+    }
+
+    #[test]
+    fn diff_of_identical_binaries() {
+        let parts = vec![
+            part(Some("app.elf"), None, 1, 1024),
+            part(None, None, 2, 16),
+        ];
+        let diff = diff_parts(&binary("1.0.0", parts.clone()), &binary("1.0.1", parts));
+
+        assert!(diff
+            .iter()
+            .all(|part| part.change == PartChange::Unchanged && part.from.is_some()));
+    }
+
+    #[test]
+    fn diff_table() {
+        let from = binary("1.0.0", vec![part(Some("app.elf"), None, 0xab, 1024)]);
+        let to = binary("1.1.0", vec![part(Some("app.elf"), None, 0xcd, 1536)]);
+        let diff = BinaryResult::Diff(BinaryDiff {
+            chip: "main".to_string(),
+            parts: diff_parts(&from, &to),
+            from,
+            to,
+        });
+
+        let text = diff.to_string();
+        assert!(
+            text.starts_with("Binary:   v1.0.0 → v1.1.0 of main\nNVM size: 1024 → 1536 (+512)\n")
+        );
+        assert!(
+            text.contains("1024 → 1536 (+512)  abababababab → cdcdcdcdcdcd"),
+            "{text}"
+        );
+    }
+
+    #[test]
+    fn size_changes() {
+        assert_eq!(size_change(Some(1024), Some(1024)), "1024");
+        assert_eq!(size_change(Some(1024), Some(512)), "1024 → 512 (-512)");
+        assert_eq!(size_change(None, Some(64)), "64");
+        assert_eq!(size_change(Some(64), None), "64");
+        assert_eq!(size_change(None, None), "-");
+    }
+
+    #[test]
+    fn part_file_names_are_unique() {
+        let mut unnamed = part(None, None, 3, 16);
+        unnamed.kind = queries::BinaryKind::Bin;
+        let parts = [
+            part(Some("a/app.bin"), None, 1, 16),
+            part(Some("b/app.bin"), None, 2, 16),
+            part(Some("part2.bin"), None, 4, 16),
+            unnamed,
+        ];
+
+        assert_eq!(
+            part_file_names(&parts),
+            ["app.bin", "app-1.bin", "part2.bin", "part3.bin"]
+        );
+
+        let same = [
+            part(Some("fw/app.elf"), None, 1, 16),
+            part(Some("app.elf"), None, 2, 16),
+            part(Some("../app.elf"), None, 3, 16),
+        ];
+        assert_eq!(
+            part_file_names(&same),
+            ["app.elf", "app-1.elf", "app-2.elf"]
+        );
+    }
+}
@@ -1,21 +1,105 @@
+use std::fmt::Display;
+
 use cynic::MutationBuilder;
+use forged::requirements::Requirement;
+use serde::Serialize;
 use serde_json::Value;
+use uuid::Uuid;
 
 use crate::{
+    progress,
     queries::{CreateBlock, CreateBlockArguments},
     Result,
 };
 
-pub async fn block(client: &mut forged::Client, schema_name: String, data: Value) -> Result<()> {
-    println!("📎  Creating block:");
-    println!("{data:#}");
+/// The block that was created and the local requirement evaluations of its value.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockCreated {
+    pub block_id: Uuid,
+    pub requirements: Vec<RequirementResult>,
+}
+
+/// The local evaluation of a block value against a requirement.
+#[derive(Serialize)]
+pub struct RequirementResult {
+    pub requirement: String,
+    pub value: f64,
+    pub passed: bool,
+}
+
+impl Display for BlockCreated {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "block: {}", self.block_id)
+    }
+}
+
+pub async fn block(
+    client: &mut forged::Client,
+    schema_name: String,
+    data: Value,
+) -> Result<BlockCreated> {
+    let requirements = preview_requirements(client).await;
+    create_block(client, &requirements, schema_name, data).await
+}
+
+/// Fetch the requirements that block values are previewed against.
+///
+/// # Note
+/// The preview is informational, so blocks are created without it if it cannot be fetched.
+pub async fn preview_requirements(client: &forged::Client) -> Vec<Requirement> {
+    client.requirements().await.unwrap_or_else(|error| {
+        progress!("⚠️  Skipping the requirement preview: {error}");
+        Vec::new()
+    })
+}
+
+/// Create a block, previewing its value against `requirements` first.
+///
+/// # Args
+/// * `requirements` - The requirements of the run, see [`preview_requirements`].
+/// * `schema_name` - The name of the block schema.
+/// * `data` - The value of the block.
+pub async fn create_block(
+    client: &forged::Client,
+    requirements: &[Requirement],
+    schema_name: String,
+    data: Value,
+) -> Result<BlockCreated> {
+    progress!("📎  Creating block:");
+    progress!("{data:#}");
+
+    let evaluations = forged::requirements::evaluate(requirements, &schema_name, &data);
+    for evaluation in &evaluations {
+        if evaluation.passed {
+            progress!("✅ {} passes {}", evaluation.value, evaluation.requirement);
+        } else {
+            progress!(
+                "❌ {} violates {}",
+                evaluation.value,
+                evaluation.requirement
+            );
+        }
+    }
+    let requirements = evaluations
+        .into_iter()
+        .map(|evaluation| RequirementResult {
+            requirement: evaluation.requirement.name.clone(),
+            value: evaluation.value,
+            passed: evaluation.passed,
+        })
+        .collect();
 
-    client
+    let block = client
         .run_query(CreateBlock::build(CreateBlockArguments {
             schema_name,
             data,
         }))
-        .await?;
+        .await?
+        .block_create;
 
-    Ok(())
+    Ok(BlockCreated {
+        block_id: block.id,
+        requirements,
+    })
 }
@@ -0,0 +1,155 @@
+use std::{fmt::Display, path::PathBuf};
+
+use anyhow::anyhow;
+use forged::config::{Config, ConfigFile, Overrides, Setting};
+use serde::Serialize;
+
+use crate::{
+    cli::{ConfigKey, ConfigOption},
+    progress, Result,
+};
+
+/// The outcome of a `config` command.
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum ConfigResult {
+    Shown(ShownConfig),
+    Changed(ChangedConfig),
+}
+
+impl Display for ConfigResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigResult::Shown(config) => config.fmt(f),
+            ConfigResult::Changed(config) => config.fmt(f),
+        }
+    }
+}
+
+/// The resolved configuration.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShownConfig {
+    pub path: Option<PathBuf>,
+    pub profiles: Vec<String>,
+    pub profile: ShownSetting,
+    pub api_url: ShownSetting,
+    pub token: Option<ShownSetting>,
+}
+
+/// A resolved setting and a description of where it was taken from.
+#[derive(Serialize)]
+pub struct ShownSetting {
+    pub value: String,
+    pub source: String,
+}
+
+impl From<Setting<String>> for ShownSetting {
+    fn from(setting: Setting<String>) -> Self {
+        Self {
+            value: setting.value,
+            source: setting.source.to_string(),
+        }
+    }
+}
+
+impl Display for ShownConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.path {
+            Some(path) => writeln!(f, "Config file: {}", path.display())?,
+            None => writeln!(f, "Config file: -")?,
+        }
+        writeln!(f, "Profiles:    {}", self.profiles.join(", "))?;
+        writeln!(f)?;
+
+        let mut write_setting = |name: &str, setting: Option<&ShownSetting>| match setting {
+            Some(setting) => writeln!(f, "{name:<8} {} ({})", setting.value, setting.source),
+            None => writeln!(f, "{name:<8} -"),
+        };
+        write_setting("profile", Some(&self.profile))?;
+        write_setting("api-url", Some(&self.api_url))?;
+        write_setting("token", self.token.as_ref())
+    }
+}
+
+/// The configuration file after a change.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangedConfig {
+    pub path: PathBuf,
+    pub profile: String,
+}
+
+impl Display for ChangedConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "profile: {}", self.profile)
+    }
+}
+
+/// Show or change the configuration file.
+///
+/// # Args
+/// * `overrides` - The settings given on the command line.
+/// * `option` - The configuration command to run.
+pub fn config(overrides: &Overrides, option: ConfigOption) -> Result<ConfigResult> {
+    let mut file = ConfigFile::load()?;
+
+    match option {
+        ConfigOption::Show => {
+            let config = Config::resolve(&file, overrides)?;
+
+            Ok(ConfigResult::Shown(ShownConfig {
+                path: ConfigFile::path(),
+                profiles: file.profiles.keys().cloned().collect(),
+                profile: config.profile.into(),
+                api_url: config.api_url.into(),
+                token: config.token.map(|token| {
+                    let mut token = ShownSetting::from(token);
+                    token.value = mask(&token.value);
+                    token
+                }),
+            }))
+        }
+
+        ConfigOption::Set { key, value } => {
+            // Setting a key of a new profile creates it.
+            let profile = Config::select_profile(&file, overrides).value;
+            let settings = file.profile_mut(&profile);
+            match key {
+                ConfigKey::Token => settings.token = Some(value),
+                ConfigKey::ApiUrl => settings.api_url = Some(value),
+            }
+
+            let path = file.save()?;
+            progress!("⚙️  Updated {key:?} of profile `{profile}` in {path:?}");
+            Ok(ConfigResult::Changed(ChangedConfig { path, profile }))
+        }
+
+        ConfigOption::UseProfile { name } => {
+            if !file.profiles.contains_key(&name) {
+                return Err(anyhow!(
+                    "Profile `{name}` does not exist. Available profiles: [ {} ]",
+                    file.profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+                )
+                .into());
+            }
+
+            file.profile = Some(name.clone());
+            let path = file.save()?;
+            progress!("⚙️  Using profile `{name}` by default");
+            Ok(ConfigResult::Changed(ChangedConfig {
+                path,
+                profile: name,
+            }))
+        }
+    }
+}
+
+/// Hide all but the last four characters of a secret.
+fn mask(secret: &str) -> String {
+    let visible = secret.len().saturating_sub(4);
+    match secret.get(visible..) {
+        Some(tail) if visible > 0 => format!("****{tail}"),
+        _ => "****".to_string(),
+    }
+}
@@ -0,0 +1,75 @@
+use std::fmt::{self, Display};
+
+use clap::ArgEnum;
+use forged::devices::Device;
+use futures_util::{StreamExt, TryStreamExt};
+use serde::Serialize;
+
+use crate::{
+    cli::DeviceOption,
+    output::{write_csv, write_table},
+    progress, Result,
+};
+
+/// How a list is printed in text output.
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ListFormat {
+    /// A table with aligned columns.
+    Table,
+    /// Comma separated values, e.g. for spreadsheets.
+    Csv,
+    /// The entries as JSON.
+    Json,
+}
+
+/// The outcome of a `device` command.
+#[derive(Serialize)]
+pub struct Devices {
+    pub devices: Vec<Device>,
+    #[serde(skip)]
+    format: ListFormat,
+}
+
+impl Display for Devices {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let header = ["ID", "NAME", "ACTIVE", "CREATED"];
+        let rows = self.devices.iter().map(|device| {
+            [
+                device.id.to_string(),
+                device.name.clone().unwrap_or_default(),
+                if device.active { "yes" } else { "no" }.to_string(),
+                device.creation_date.to_string(),
+            ]
+        });
+
+        match self.format {
+            ListFormat::Table => write_table(f, header, rows),
+            ListFormat::Csv => write_csv(f, header, rows),
+            ListFormat::Json => {
+                let json = serde_json::to_string_pretty(&self.devices).map_err(|_| fmt::Error)?;
+                writeln!(f, "{json}")
+            }
+        }
+    }
+}
+
+pub async fn device(client: &forged::Client, option: DeviceOption) -> Result<Devices> {
+    match option {
+        DeviceOption::List {
+            filter,
+            limit,
+            project,
+            format,
+        } => {
+            progress!("⬇️  Fetching devices ...");
+            let devices = client.devices(project, filter);
+            let devices = if limit == 0 {
+                devices.try_collect().await?
+            } else {
+                devices.take(limit).try_collect().await?
+            };
+
+            Ok(Devices { devices, format })
+        }
+    }
+}
@@ -1,192 +1,864 @@
 use indicatif::ProgressBar;
-use std::io::Cursor;
+use std::{fmt::Display, io::Cursor, sync::Arc};
 
 use anyhow::anyhow;
-use cynic::QueryBuilder;
-use probe_rs::flashing::{BinOptions, FlashLoader};
+use cynic::{MutationBuilder, QueryBuilder};
+use forged::{
+    config::{FlashSettings, ResetBehavior},
+    logs::{LogEntry, SinkOptions},
+    BinaryKind, BinaryPartUpload,
+};
+use futures_util::future::join_all;
+use probe_rs::{
+    flashing::{BinOptions, FlashLoader},
+    DebugProbeSelector, Session,
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
 
-use crate::Error;
 use crate::{
-    queries::{Binary, Chip, Chips},
+    cli::{FlashOptions, ProbeOptions, RttOptions},
+    functions::{
+        binary::{find_chip, read_part},
+        log::finish_sink,
+        rtt::{capture_logs, defmt_table, header_location},
+    },
+    memory::find_mismatch,
+    output::write_table,
+    progress,
+    queries::{Binary, Chip, Chips, CreateDevice, CreateLog, CreateLogArguments, FinishRun},
     Result,
 };
 
+/// The binary that was flashed onto the target.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Flashed {
+    pub chip_id: Uuid,
+    pub chip: String,
+    pub part_number: String,
+    pub binary_id: Uuid,
+    pub version: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub log_ids: Vec<Uuid>,
+}
+
+impl Display for Flashed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "flashed: v{} onto {} ({})",
+            self.version, self.chip, self.part_number
+        )
+    }
+}
+
+/// The local files that were flashed onto the target.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FlashedFiles {
+    pub part_number: String,
+    pub files: Vec<FlashedFile>,
+    pub log_ids: Vec<Uuid>,
+}
+
+/// A local file that was flashed onto the target.
+#[derive(Serialize)]
+pub struct FlashedFile {
+    pub name: String,
+    pub sha256: String,
+}
+
+impl Display for FlashedFiles {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for file in &self.files {
+            writeln!(
+                f,
+                "flashed: {} ({}) onto {}",
+                file.name, file.sha256, self.part_number
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The outcome of a `download` command.
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum Downloaded {
+    Flashed(Flashed),
+    Files(FlashedFiles),
+    Gang(GangFlashed),
+}
+
+impl Downloaded {
+    /// Check if flashing any of the targets or recording its run failed.
+    pub fn failed(&self) -> bool {
+        match self {
+            Downloaded::Gang(gang) => gang
+                .probes
+                .iter()
+                .any(|probe| !probe.passed || probe.record_error.is_some()),
+            _ => false,
+        }
+    }
+}
+
+impl Display for Downloaded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Downloaded::Flashed(flashed) => flashed.fmt(f),
+            Downloaded::Files(files) => files.fmt(f),
+            Downloaded::Gang(gang) => gang.fmt(f),
+        }
+    }
+}
+
+/// The outcome of flashing the targets of several probes at once.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GangFlashed {
+    pub release: String,
+    pub part_number: String,
+    pub probes: Vec<ProbeOutcome>,
+}
+
+/// The outcome of flashing the target of a single probe.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProbeOutcome {
+    pub probe: String,
+    pub selector: String,
+    pub passed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device_id: Option<Uuid>,
+    /// Why the device and its run could not be recorded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub record_error: Option<String>,
+}
+
+impl Display for GangFlashed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "flashed: {} onto {}", self.release, self.part_number)?;
+        writeln!(f)?;
+        write_table(
+            f,
+            ["PROBE", "SELECTOR", "RESULT", "DEVICE"],
+            self.probes.iter().map(|probe| {
+                [
+                    probe.probe.clone(),
+                    probe.selector.clone(),
+                    match &probe.error {
+                        None => "✅ PASS".to_string(),
+                        Some(error) => format!("❌ FAIL: {error}"),
+                    },
+                    match (&probe.device_id, &probe.record_error) {
+                        (Some(id), _) => id.to_string(),
+                        (None, Some(error)) => format!("❌ NOT RECORDED: {error}"),
+                        (None, None) => "-".to_string(),
+                    },
+                ]
+            }),
+        )
+    }
+}
+
+/// Which release of the server to flash.
+pub struct Release {
+    /// The name or id of the chip. May be omitted if the project has a single chip.
+    pub chip: Option<String>,
+    /// The version requirement of the binary, see [`parse_version_req`].
+    pub version: Option<String>,
+    /// Use the binary the server considers the newest.
+    pub server_newest: bool,
+}
+
+/// Which local files to flash, see [`download_local`].
+pub struct LocalFiles {
+    /// The part number of the chip, as known by probe-rs.
+    pub part_number: String,
+    /// The files or directories to flash, see [`read_part`] for the format.
+    pub files: Vec<String>,
+    /// Log the flashed files and their hashes on the current run. Requires a client.
+    pub record: bool,
+}
+
 pub async fn download(
     client: &mut forged::Client,
-    chip: Option<String>,
-    version: Option<String>,
-) -> Result<()> {
+    release: Release,
+    probe: &ProbeOptions,
+    mode: FlashMode,
+    gang: Option<Gang>,
+    rtt: Option<&RttOptions>,
+) -> Result<Downloaded> {
+    let (chip, binary) = find_release(client, release).await?;
+
+    progress!(
+        " -> Flashing firmware v{} onto {} ({})",
+        binary.version(),
+        chip.name,
+        chip.part_number
+    );
+    progress!("⛅ Grabbing binaries from the server ...");
+    let parts = fetch_parts(client, &chip, &binary).await?;
+
+    if let Some(gang) = gang {
+        let release = format!("v{}", binary.version());
+        return Ok(Downloaded::Gang(
+            flash_gang(
+                Some(client),
+                &chip.part_number,
+                release,
+                &parts,
+                probe,
+                mode,
+                gang,
+            )
+            .await?,
+        ));
+    }
+
+    let mut session = crate::probe::attach(&chip.part_number, probe)?;
+    if let Err(error) = flash_parts(&mut session, &parts, mode, &progress_bars(), "") {
+        progress!("❌ Flashing procedure failed.");
+        return Err(error);
+    }
+
+    let log_ids = match rtt {
+        Some(options) => {
+            let defmt = defmt_table(&parts)?;
+            let header = header_location(&binary);
+            let sink = client.log_sink(SinkOptions::default());
+            capture_logs(sink, &mut session, header, defmt.as_ref(), options).await?
+        }
+        None => Vec::new(),
+    };
+
+    Ok(Downloaded::Flashed(Flashed {
+        chip_id: chip.id,
+        chip: chip.name.clone(),
+        part_number: chip.part_number.clone(),
+        binary_id: binary.id,
+        version: binary.version().to_string(),
+        log_ids,
+    }))
+}
+
+/// Find the chip and binary of a release on the server.
+pub async fn find_release(client: &forged::Client, release: Release) -> Result<(Chip, Binary)> {
+    let Release {
+        chip,
+        version,
+        server_newest,
+    } = release;
     let query = client.run_query(Chips::build(())).await?;
     let chips = query.current_provisioner.project.chips;
 
-    let chips_string =
-        chips
-            .iter()
-            .map(|chip| chip.name.clone())
-            .fold(String::new(), |acc, chip| {
-                if acc.is_empty() {
-                    chip.to_string()
-                } else {
-                    format!("{acc}, {chip}")
-                }
-            });
+    let chip = find_chip(&chips, chip.as_deref())?;
 
-    let chip = if let Some(chip_name) = chip {
-        chips
-            .iter()
-            .find(|chip| chip.name == chip_name)
-            .ok_or_else(|| {
-                anyhow!("Chip `{chip_name}` not found. Available chips: [ {chips_string} ]")
-            })?
+    let binary = if server_newest {
+        newest_binary(chip)?
     } else {
-        match chips.len() {
-            0 => {
-                return Err(Error::Other(anyhow!(
-                    "No chips have been configured for this project. Add one to the project first."
-                )))
-            }
-            1 => chips.first().unwrap(),
-            _ => {
-                return Err(Error::Other(anyhow!(
-                "Multiple chips found for this project. Please specify one. Available chips: [ {chips_string} ]"
-            )))
-            }
-        }
-    };
+        let requirement = version.as_deref().map(parse_version_req).transpose()?;
+        select_binary(&chip.binaries, requirement.as_ref()).ok_or_else(|| {
+            let mut versions: Vec<semver::Version> = chip
+                .binaries
+                .iter()
+                .filter(|binary| binary.is_available())
+                .map(|binary| binary.version())
+                .collect();
+            versions.sort();
+            versions.reverse();
 
-    let binaries = &chip.binaries;
-    let binary = if let Some(version) = version {
-        let version = semver::Version::parse(&version)?;
-        binaries
-            .iter()
-            .find(|bin| bin.version() == version)
-            .ok_or_else(|| {
-                let mut versions: Vec<semver::Version> = binaries.iter().map(|bin| bin.version()).collect();
-                versions.sort();
-                versions.reverse();
-
-                anyhow!(
-                    "Binary version `{version}` not found for chip `{}`. Available versions: [ {} ]",
+            match &requirement {
+                Some(requirement) => anyhow!(
+                    "No binary matching `{requirement}` found for chip `{}`. Available versions: [ {} ]",
                     chip.name,
-                    versions.iter().fold(String::new(), |acc, version| {
-                        if acc.is_empty() {
-                            version.to_string()
-                        } else {
-                            format!("{acc}, {version}")
-                        }
-                    }
-                ))
-            })?
-    } else {
-        // Otherwise, find the newest binary.
-        let Some(binary) = binaries.iter().max_by_key(|x| x.version()) else {
-            return Err(Error::Other(anyhow!(
-                "No binaries have been uploaded for chip {}",
-                chip.name
-            )));
-        };
-        binary
+                    versions
+                        .iter()
+                        .map(|version| version.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+                None => anyhow!("No binaries have been uploaded for chip {}", chip.name),
+            }
+        })?
     };
 
-    println!(
-        " -> Flashing firmware v{} onto {} ({})",
-        binary.version(),
-        chip.name,
-        chip.part_number
-    );
-    println!("⛅ Grabbing binaries from the server ...");
+    Ok((chip.clone(), binary.clone()))
+}
+
+/// Find the binary the server considers the newest for `chip`.
+///
+/// # Returns
+/// An error if the newest binary is inactive or deleted, since it cannot be flashed.
+fn newest_binary(chip: &Chip) -> Result<&Binary> {
+    let newest = chip
+        .binary_newest
+        .as_ref()
+        .ok_or_else(|| anyhow!("No binaries have been uploaded for chip {}", chip.name))?;
+    let binary = chip
+        .binaries
+        .iter()
+        .find(|binary| binary.id == newest.id)
+        .ok_or_else(|| anyhow!("The newest binary {} was not found", newest.id))?;
+    if !binary.is_available() {
+        return Err(anyhow!(
+            "The newest binary v{} of chip `{}` is inactive or deleted. Select an available \
+            release with `--version` instead.",
+            binary.version,
+            chip.name
+        )
+        .into());
+    }
 
-    let result = run_flash_download(client, &chip, binary).await;
+    Ok(binary)
+}
 
-    if result.is_err() {
-        println!("❌ Flashing procedure failed.");
-        return result;
+/// Parse a version requirement such as `^1.4`, `~2.0` or `>=1.2, <2`.
+///
+/// A plain version like `1.2.3` only matches exactly that version instead of the compatible
+/// versions `^1.2.3` would match.
+fn parse_version_req(version: &str) -> Result<semver::VersionReq> {
+    if let Ok(version) = semver::Version::parse(version) {
+        return Ok(semver::VersionReq {
+            comparators: vec![semver::Comparator {
+                op: semver::Op::Exact,
+                major: version.major,
+                minor: Some(version.minor),
+                patch: Some(version.patch),
+                pre: version.pre,
+            }],
+        });
     }
 
-    Ok(())
+    Ok(semver::VersionReq::parse(version)
+        .map_err(|e| anyhow!("Invalid version requirement `{version}`: {e}"))?)
 }
 
-async fn run_flash_download(
-    client: &mut forged::Client,
-    chip: &Chip,
-    binary: &Binary,
-) -> Result<()> {
-    let lister = probe_rs::Lister::new();
-    let probe = lister
-        .list_all()
-        .get(0)
-        .ok_or_else(|| anyhow!("No probe found"))?
-        .open(&lister)
-        .map_err(probe_rs::Error::Probe)?;
-    {
-        let protocol_speed = probe.speed_khz();
+/// Select the newest available binary matching a version requirement.
+///
+/// Inactive and deleted binaries are skipped. Pre-releases only match requirements that name a
+/// pre-release of the same version, see [`semver::VersionReq::matches`].
+fn select_binary<'a>(
+    binaries: &'a [Binary],
+    requirement: Option<&semver::VersionReq>,
+) -> Option<&'a Binary> {
+    binaries
+        .iter()
+        .filter(|binary| binary.is_available())
+        .filter(|binary| match requirement {
+            Some(requirement) => requirement.matches(&binary.version()),
+            None => binary.version().pre.is_empty(),
+        })
+        .max_by_key(|binary| binary.version())
+}
 
-        log::info!("Protocol speed {} kHz", protocol_speed);
+/// Flash local files onto the target without fetching a release from the server.
+///
+/// # Args
+/// * `client` - The client to create logs and runs with.
+/// * `local` - The files to flash.
+/// * `probe` - Which debug probe to flash the target with.
+/// * `mode` - How to flash the target.
+/// * `gang` - Flash the targets of several probes at once instead of a single target.
+/// * `rtt` - Capture the RTT output of the target into logs after flashing. Requires a client.
+pub async fn download_local(
+    client: Option<&forged::Client>,
+    local: LocalFiles,
+    probe: &ProbeOptions,
+    mode: FlashMode,
+    gang: Option<Gang>,
+    rtt: Option<&RttOptions>,
+) -> Result<Downloaded> {
+    let part_number = local.part_number.as_str();
+    let parts = local_parts(&local.files)?;
+    let flashed: Vec<FlashedFile> = parts
+        .iter()
+        .map(|part| FlashedFile {
+            name: part.name.clone(),
+            sha256: Sha256::digest(&part.image)
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect(),
+        })
+        .collect();
+
+    progress!(
+        " -> Flashing {} local file(s) onto {part_number}",
+        parts.len()
+    );
+
+    if let Some(gang) = gang {
+        let release = flashed
+            .iter()
+            .map(|file| file.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        return Ok(Downloaded::Gang(
+            flash_gang(client, part_number, release, &parts, probe, mode, gang).await?,
+        ));
     }
 
-    // Create a new session
-    let mut session = probe.attach(&chip.part_number, probe_rs::Permissions::default())?;
+    let mut session = crate::probe::attach(part_number, probe)?;
+    if let Err(error) = flash_parts(&mut session, &parts, mode, &progress_bars(), "") {
+        progress!("❌ Flashing procedure failed.");
+        return Err(error);
+    }
 
-    let target = session.target();
+    let record = local.record && mode.programs();
+    let mut log_ids = Vec::new();
+    if let Some(client) = client.filter(|_| record || rtt.is_some()) {
+        let defmt = rtt.map(|_| defmt_table(&parts)).transpose()?.flatten();
+        let mut sink = client.log_sink(SinkOptions::default());
+        if record {
+            for file in &flashed {
+                sink.push(LogEntry::new(
+                    "INFO",
+                    format!(
+                        "Flashed local file {} (sha256 {}) onto {part_number}",
+                        file.name, file.sha256
+                    ),
+                ))
+                .await?;
+            }
+        }
+        log_ids = match rtt {
+            Some(options) => {
+                capture_logs(sink, &mut session, None, defmt.as_ref(), options).await?
+            }
+            None => finish_sink(sink).await?,
+        };
+    }
 
-    // Create the flash loader
-    let mut loader = FlashLoader::new(target.memory_map.to_vec(), target.source().clone());
+    Ok(Downloaded::Files(FlashedFiles {
+        part_number: part_number.to_string(),
+        files: flashed,
+        log_ids,
+    }))
+}
+
+/// Read the local parts to flash. Directories are expanded to the ELF, HEX and BIN files in them.
+fn local_parts(files: &[String]) -> Result<Vec<BinaryPartUpload>> {
+    let mut parts = Vec::new();
+    for file in files {
+        let path = std::path::Path::new(file);
+        if !path.is_dir() {
+            parts.push(read_part(file)?);
+            continue;
+        }
+
+        let mut entries = std::fs::read_dir(path)
+            .map_err(|e| anyhow!("Failed to read directory {path:?}: {e}"))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file() && is_firmware_file(path))
+            .collect::<Vec<_>>();
+        entries.sort();
 
+        if entries.is_empty() {
+            return Err(anyhow!("No ELF, HEX or BIN files found in {path:?}").into());
+        }
+        for entry in entries {
+            parts.push(read_part(&entry.to_string_lossy())?);
+        }
+    }
+
+    Ok(parts)
+}
+
+fn is_firmware_file(path: &std::path::Path) -> bool {
+    matches!(
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase())
+            .as_deref(),
+        Some("elf" | "axf" | "out" | "hex" | "ihex" | "ihx" | "bin")
+    )
+}
+
+/// Download the parts of a binary from the server.
+async fn fetch_parts(
+    client: &forged::Client,
+    chip: &Chip,
+    binary: &Binary,
+) -> Result<Vec<BinaryPartUpload>> {
     let n_parts = binary.parts.len();
-    for (index, part) in binary.parts.clone().into_iter().enumerate() {
-        println!(
+    let mut parts = Vec::with_capacity(n_parts);
+    for (index, part) in binary.parts.iter().enumerate() {
+        progress!(
             "📦 Downloading part {}/{n_parts}{}",
             index + 1,
             part.analysis
+                .as_ref()
                 .map(|analysis| format!(" ({} bytes)", analysis.nvm_size))
                 .unwrap_or_default()
         );
 
-        let binary = client
+        let data = client
             .binary_part(chip.id, binary.id, part.id, None)
             .await?;
 
-        match part.kind {
-            crate::queries::BinaryKind::Elf => loader
-                .load_elf_data(&mut Cursor::new(binary))
-                .map_err(|_| anyhow!("Failed to flash."))?,
-            crate::queries::BinaryKind::Bin => loader
-                .load_bin_data(
-                    &mut Cursor::new(binary),
-                    BinOptions {
-                        base_address: part.memory_offset.map(|o| o as u64),
-                        skip: 0,
-                    },
-                )
-                .map_err(|_| anyhow!("Failed to flash."))?,
-            crate::queries::BinaryKind::Hex => loader
-                .load_hex_data(&mut Cursor::new(binary))
-                .map_err(|_| anyhow!("Failed to flash."))?,
+        parts.push(BinaryPartUpload {
+            name: part.name.clone().unwrap_or_else(|| format!("part{index}")),
+            kind: (&part.kind).into(),
+            memory_offset: part.memory_offset.map(|offset| offset as u32),
+            image: data,
+        });
+    }
+
+    Ok(parts)
+}
+
+/// How the targets are flashed, resolved from the command line and the configuration.
+#[derive(Debug, Clone, Copy)]
+pub struct FlashMode {
+    pub chip_erase: bool,
+    pub skip_erase: bool,
+    pub verify: bool,
+    pub verify_only: bool,
+    pub dry_run: bool,
+    pub keep_unwritten_bytes: bool,
+    pub reset_after: ResetBehavior,
+}
+
+impl FlashMode {
+    /// Resolve the flash mode. Command line flags take precedence over the configuration.
+    ///
+    /// # Args
+    /// * `options` - The flags given on the command line.
+    /// * `settings` - The `flash` section of the selected profile.
+    ///
+    /// # Returns
+    /// An error if both a chip erase and skipping the erase are configured.
+    pub fn resolve(options: &FlashOptions, settings: &FlashSettings) -> Result<Self> {
+        let flag = |flag: bool, setting: Option<bool>, default: bool| {
+            if flag {
+                true
+            } else {
+                setting.unwrap_or(default)
+            }
+        };
+
+        // An erase flag on the command line overrides the opposite setting of the configuration.
+        let chip_erase = flag(
+            options.chip_erase,
+            settings.chip_erase.filter(|_| !options.skip_erase),
+            false,
+        );
+        let skip_erase = flag(
+            options.skip_erase,
+            settings.skip_erase.filter(|_| !options.chip_erase),
+            false,
+        );
+        if chip_erase && skip_erase {
+            return Err(anyhow!(
+                "The `flash` settings enable both `chip_erase` and `skip_erase`. Disable one of them"
+            )
+            .into());
+        }
+
+        let verify_only = flag(options.verify_only, settings.verify_only, false);
+        // The command line only rejects this combination if both are flags.
+        if options.dry_run && verify_only {
+            return Err(anyhow!(
+                "`--dry-run` conflicts with `verify_only` of the `flash` settings. Verifying only \
+                 never programs the target"
+            )
+            .into());
         }
+
+        Ok(Self {
+            chip_erase,
+            skip_erase,
+            verify: !options.no_verify && settings.verify.unwrap_or(true),
+            verify_only,
+            dry_run: !verify_only && flag(options.dry_run, settings.dry_run, false),
+            keep_unwritten_bytes: !options.erase_unwritten
+                && settings.keep_unwritten_bytes.unwrap_or(true),
+            reset_after: options
+                .reset_after
+                .or(settings.reset_after)
+                .unwrap_or(ResetBehavior::None),
+        })
+    }
+
+    /// Check if the target is programmed, instead of only being verified or simulated.
+    pub fn programs(&self) -> bool {
+        !self.verify_only && !self.dry_run
+    }
+
+    /// Run the target after flashing, so its RTT output can be captured.
+    ///
+    /// # Returns
+    /// An error if the target is not programmed, e.g. because of the `flash` settings.
+    pub fn capture_rtt(mut self) -> Result<Self> {
+        if !self.programs() {
+            return Err(anyhow!(
+                "`--rtt` needs the target to be programmed, but the `flash` settings enable \
+                 `verify_only` or `dry_run`"
+            )
+            .into());
+        }
+
+        if self.reset_after == ResetBehavior::None {
+            self.reset_after = ResetBehavior::Run;
+        }
+        Ok(self)
+    }
+}
+
+/// Which probes to flash at once, see [`flash_gang`].
+pub struct Gang {
+    /// The serial numbers of the probes to use. If empty, all connected probes are used.
+    pub serials: Vec<String>,
+    /// Create a new device with its own run for every flashed target.
+    pub run_per_device: bool,
+}
+
+/// Flash the same parts onto the targets of several probes concurrently.
+///
+/// # Args
+/// * `client` - The client to create the runs with if [`Gang::run_per_device`] is set.
+/// * `part_number` - The part number of the chips, as known by probe-rs.
+/// * `release` - A description of the flashed parts for the summary and the run logs.
+/// * `parts` - The parts to flash.
+/// * `options` - How to connect to the targets. The probe selector is ignored.
+/// * `mode` - How to flash the targets.
+/// * `gang` - Which probes to use.
+///
+/// # Returns
+/// The outcome of every probe. Failing probes do not abort the others.
+async fn flash_gang(
+    client: Option<&forged::Client>,
+    part_number: &str,
+    release: String,
+    parts: &[BinaryPartUpload],
+    options: &ProbeOptions,
+    mode: FlashMode,
+    gang: Gang,
+) -> Result<GangFlashed> {
+    let client = match client {
+        Some(client) => Some(client),
+        None if gang.run_per_device => {
+            return Err(anyhow!(
+                "A run per device requires an API token. Pass --no-runs to flash without runs"
+            )
+            .into())
+        }
+        None => None,
+    };
+
+    let probes = probe_rs::Lister::new().list_all();
+    let probes = crate::probe::select_all(&probes, &gang.serials)?;
+    progress!("🔀 Flashing {} targets concurrently ...", probes.len());
+
+    // Flashing blocks, so every target is flashed on a blocking task of its own.
+    let bars = progress_bars();
+    let parts: Arc<[BinaryPartUpload]> = parts.into();
+    let tasks = probes.iter().map(|&probe| {
+        let (probe, part_number, options) =
+            (probe.clone(), part_number.to_string(), options.clone());
+        let (parts, bars) = (parts.clone(), bars.clone());
+        tokio::task::spawn_blocking(move || {
+            let label = DebugProbeSelector::from(&probe).to_string();
+            let mut session = crate::probe::attach_probe(&probe, &part_number, &options)?;
+            flash_parts(&mut session, &parts, mode, &bars, &format!("{label} "))
+        })
+    });
+    let results: Vec<Result<()>> = join_all(tasks)
+        .await
+        .into_iter()
+        .map(|result| result.unwrap_or_else(|_| Err(anyhow!("Flashing panicked").into())))
+        .collect();
+
+    let mut outcomes = Vec::with_capacity(probes.len());
+    for (probe, result) in probes.into_iter().zip(results) {
+        let selector = DebugProbeSelector::from(probe).to_string();
+        let error = result.err().map(|error| error.to_string());
+
+        // A failing request must not hide the outcomes of the targets that were already flashed.
+        let (device_id, record_error) = match client {
+            Some(client) if gang.run_per_device => {
+                let (level, message) = match &error {
+                    None => ("INFO", format!("Flashed {release} onto {part_number} with probe {selector}")),
+                    Some(error) => ("ERROR", format!("Flashing {release} onto {part_number} with probe {selector} failed: {error}")),
+                };
+                match record_device(client, level, message).await {
+                    Ok(device_id) => (Some(device_id), None),
+                    Err(error) => (None, Some(error.to_string())),
+                }
+            }
+            _ => (None, None),
+        };
+
+        outcomes.push(ProbeOutcome {
+            probe: probe.identifier.clone(),
+            selector,
+            passed: error.is_none(),
+            error,
+            device_id,
+            record_error,
+        });
+    }
+
+    Ok(GangFlashed {
+        release,
+        part_number: part_number.to_string(),
+        probes: outcomes,
+    })
+}
+
+/// Create a new device whose run only records the outcome of flashing it.
+async fn record_device(client: &forged::Client, level: &str, message: String) -> Result<Uuid> {
+    let device_id = client
+        .run_query(CreateDevice::build(()))
+        .await?
+        .device_create
+        .id;
+    let logged = client
+        .run_query(CreateLog::build(CreateLogArguments {
+            level: level.to_string(),
+            message,
+        }))
+        .await;
+    // The run is finished even without its log, so the device is not left with a pending run.
+    let finished = client.run_query(FinishRun::build(())).await;
+    logged?;
+    finished?;
+
+    Ok(device_id)
+}
+
+/// Load the parts into a flash loader for the attached target and flash them.
+fn flash_parts(
+    session: &mut Session,
+    parts: &[BinaryPartUpload],
+    mode: FlashMode,
+    bars: &indicatif::MultiProgress,
+    label: &str,
+) -> Result<()> {
+    let target = session.target();
+    let mut loader = FlashLoader::new(target.memory_map.to_vec(), target.source().clone());
+    for part in parts {
+        load_part(
+            &mut loader,
+            part.kind,
+            part.memory_offset.map(u64::from),
+            &part.image,
+        )?;
+    }
+
+    if mode.verify_only {
+        return verify(session, &loader, label);
+    }
+
+    flash(session, loader, mode, bars, label)?;
+    if !mode.dry_run {
+        reset(session, mode.reset_after)?;
+    }
+
+    Ok(())
+}
+
+/// Compare the flash contents of the target to the loaded parts without programming them.
+fn verify(session: &mut Session, loader: &FlashLoader, label: &str) -> Result<()> {
+    let mut core = session.core(0)?;
+    for (address, data) in loader.data() {
+        if let Some(mismatch) = find_mismatch(&mut core, address, data)? {
+            return Err(
+                anyhow!("The flash contents differ from the binary at {mismatch:#010x}").into(),
+            );
+        }
+    }
+
+    progress!("✅ {label}The flash contents match the binary");
+    Ok(())
+}
+
+/// Reset the target after programming.
+fn reset(session: &mut Session, behavior: ResetBehavior) -> Result<()> {
+    match behavior {
+        ResetBehavior::None => {}
+        ResetBehavior::Run => session.core(0)?.reset()?,
+        ResetBehavior::Halt => {
+            session
+                .core(0)?
+                .reset_and_halt(std::time::Duration::from_millis(500))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Add the content of a binary part to the flash loader.
+fn load_part(
+    loader: &mut FlashLoader,
+    kind: BinaryKind,
+    memory_offset: Option<u64>,
+    data: &[u8],
+) -> Result<()> {
+    match kind {
+        BinaryKind::Elf => loader
+            .load_elf_data(&mut Cursor::new(data))
+            .map_err(|_| anyhow!("Failed to flash."))?,
+        BinaryKind::Bin => loader
+            .load_bin_data(
+                &mut Cursor::new(data),
+                BinOptions {
+                    base_address: memory_offset,
+                    skip: 0,
+                },
+            )
+            .map_err(|_| anyhow!("Failed to flash."))?,
+        BinaryKind::Hex => loader
+            .load_hex_data(&mut Cursor::new(data))
+            .map_err(|_| anyhow!("Failed to flash."))?,
+    }
+
+    Ok(())
+}
+
+/// Create the progress bar container, hidden if the output is quiet.
+fn progress_bars() -> indicatif::MultiProgress {
+    let multi_progress = indicatif::MultiProgress::new();
+    if crate::output::is_quiet() {
+        multi_progress.set_draw_target(indicatif::ProgressDrawTarget::hidden());
     }
+    multi_progress
+}
 
+/// Erase and program the loaded parts onto the target, showing progress bars.
+fn flash(
+    session: &mut Session,
+    loader: FlashLoader,
+    mode: FlashMode,
+    multi_progress: &indicatif::MultiProgress,
+    label: &str,
+) -> Result<()> {
     let style = indicatif::ProgressStyle::default_bar()
         .tick_chars("⠁⠁⠉⠙⠚⠒⠂⠂⠒⠲⠴⠤⠄⠄⠤⠠⠠⠤⠦⠖⠒⠐⠐⠒⠓⠋⠉⠈⠈✔")
         .progress_chars("--")
         .template("{msg:.green.bold} {spinner} [{elapsed_precise}] [{wide_bar}] {bytes:>8}/{total_bytes:>8} @ {bytes_per_sec:>10} (eta {eta:3})").expect("Error in progress bar creation. This is a bug, please report it.");
 
-    let multi_progress = indicatif::MultiProgress::new();
     let erase_bar = multi_progress.add(
         ProgressBar::new(0)
             .with_style(style.clone())
-            .with_message("    Erasing"),
+            .with_message(format!("{label}    Erasing")),
     );
     let program_bar = multi_progress.add(
         ProgressBar::new(0)
             .with_style(style.clone())
-            .with_message("Programming"),
+            .with_message(format!("{label}Programming")),
     );
 
     let progress = probe_rs::flashing::FlashProgress::new(move |event| {
         use probe_rs::flashing::ProgressEvent;
         match event {
             ProgressEvent::Initialized { flash_layout } => {
-                erase_bar.set_length(flash_layout.sectors().iter().map(|s| s.size() as u64).sum());
+                erase_bar.set_length(flash_layout.sectors().iter().map(|s| s.size()).sum());
                 program_bar.set_length(flash_layout.pages().iter().map(|s| s.size() as u64).sum());
             }
             ProgressEvent::StartedErasing => {
@@ -220,13 +892,274 @@ async fn run_flash_download(
 
     let mut options = probe_rs::flashing::DownloadOptions::default();
     options.disable_double_buffering = false;
-    options.verify = true;
-    options.keep_unwritten_bytes = true;
-    options.dry_run = false;
+    options.verify = mode.verify;
+    options.keep_unwritten_bytes = mode.keep_unwritten_bytes;
+    options.dry_run = mode.dry_run;
+    options.do_chip_erase = mode.chip_erase;
     options.progress = Some(progress);
-    options.skip_erase = false;
+    options.skip_erase = mode.skip_erase;
 
-    loader.commit(&mut session, options)?;
+    loader.commit(session, options)?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::queries::DateTime;
+
+    use super::*;
+
+    /// A binary whose numeric version fields disagree with its full version string.
+    fn binary(version: &str, active: bool, deleted: bool) -> Binary {
+        Binary {
+            id: Uuid::nil(),
+            version: version.to_string(),
+            version_major: 0,
+            version_minor: 0,
+            version_patch: 0,
+            creation_date: DateTime("2024-01-01T00:00:00Z".to_string()),
+            deletion_date: deleted.then(|| DateTime("2024-02-01T00:00:00Z".to_string())),
+            active,
+            analysis: None,
+            parts: Vec::new(),
+        }
+    }
+
+    fn binaries() -> Vec<Binary> {
+        vec![
+            binary("1.2.0", true, false),
+            binary("1.4.0", true, false),
+            binary("1.4.2", true, false),
+            binary("1.5.0-rc.1", true, false),
+            binary("1.9.0", false, false),
+            binary("2.0.0", true, false),
+            binary("2.0.1", true, false),
+            binary("2.1.0-beta.1", true, false),
+            binary("2.1.0-beta.2", true, false),
+            binary("3.0.0", true, true),
+        ]
+    }
+
+    #[test]
+    fn version_requirements() {
+        let exact = |version: &str| semver::VersionReq {
+            comparators: vec![semver::Comparator {
+                op: semver::Op::Exact,
+                ..semver::Comparator::parse(version).unwrap()
+            }],
+        };
+        let cases = [
+            ("^1.4", semver::VersionReq::parse("^1.4").unwrap()),
+            ("~2.0", semver::VersionReq::parse("~2.0").unwrap()),
+            (">=1.2, <2", semver::VersionReq::parse(">=1.2, <2").unwrap()),
+            ("1.4", semver::VersionReq::parse("^1.4").unwrap()),
+            ("1.2.3", exact("1.2.3")),
+            ("2.1.0-beta.1", exact("2.1.0-beta.1")),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(parse_version_req(input).unwrap(), expected, "{input}");
+        }
+
+        assert!(parse_version_req("latest").is_err());
+        assert!(parse_version_req(">=1.2 <<2").is_err());
+    }
+
+    #[test]
+    fn binary_selection() {
+        let binaries = binaries();
+        let cases = [
+            (None, Some("2.0.1")),
+            (Some("^1.4"), Some("1.4.2")),
+            (Some("~1.4.0"), Some("1.4.2")),
+            (Some("~2.0"), Some("2.0.1")),
+            (Some(">=1.2, <2"), Some("1.4.2")),
+            // An exact version does not match compatible newer versions.
+            (Some("1.4.0"), Some("1.4.0")),
+            (Some("1.2.3"), None),
+            // Pre-releases are only selected if requested.
+            (Some(">=1.5.0-rc.1, <2"), Some("1.5.0-rc.1")),
+            (Some("2.1.0-beta.1"), Some("2.1.0-beta.1")),
+            (Some(">=2.1.0-beta.1"), Some("2.1.0-beta.2")),
+            (Some("^2.1"), None),
+            // Inactive and deleted binaries are skipped.
+            (Some("1.9.0"), None),
+            (Some("^3"), None),
+            (Some(">=1.8, <2"), None),
+        ];
+        for (requirement, expected) in cases {
+            let parsed = requirement.map(|requirement| parse_version_req(requirement).unwrap());
+            let selected = select_binary(&binaries, parsed.as_ref());
+            assert_eq!(
+                selected.map(|binary| binary.version.as_str()),
+                expected,
+                "{requirement:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn binary_selection_without_binaries() {
+        assert!(select_binary(&[], None).is_none());
+        let unavailable = [binary("1.0.0", false, false), binary("1.1.0", true, true)];
+        assert!(select_binary(&unavailable, None).is_none());
+    }
+
+    fn chip(binaries: Vec<Binary>, newest: usize) -> Chip {
+        let binaries: Vec<_> = binaries
+            .into_iter()
+            .enumerate()
+            .map(|(index, binary)| Binary {
+                id: Uuid::from_u128(index as u128 + 1),
+                ..binary
+            })
+            .collect();
+        Chip {
+            id: Uuid::nil(),
+            name: "main".to_string(),
+            part_number: "STM32F401".to_string(),
+            binary_newest: Some(crate::queries::BinaryId {
+                id: binaries[newest].id,
+            }),
+            binaries,
+        }
+    }
+
+    #[test]
+    fn newest_binary_must_be_available() {
+        let available = chip(binaries(), 6);
+        assert_eq!(newest_binary(&available).unwrap().version, "2.0.1");
+
+        for newest in [4, 9] {
+            let error = newest_binary(&chip(binaries(), newest)).unwrap_err();
+            assert!(
+                error.to_string().contains("is inactive or deleted"),
+                "{error}"
+            );
+        }
+
+        let without = Chip {
+            binary_newest: None,
+            ..chip(binaries(), 0)
+        };
+        assert!(newest_binary(&without).is_err());
+    }
+
+    #[test]
+    fn flash_mode_defaults() {
+        let mode = FlashMode::resolve(&FlashOptions::default(), &FlashSettings::default()).unwrap();
+
+        assert!(!mode.chip_erase && !mode.skip_erase);
+        assert!(mode.verify && !mode.verify_only && !mode.dry_run);
+        assert!(mode.keep_unwritten_bytes);
+        assert_eq!(mode.reset_after, ResetBehavior::None);
+        assert!(mode.programs());
+    }
+
+    #[test]
+    fn flash_mode_flags_override_settings() {
+        let settings = FlashSettings {
+            verify: Some(true),
+            dry_run: Some(true),
+            keep_unwritten_bytes: Some(true),
+            reset_after: Some(ResetBehavior::Halt),
+            ..FlashSettings::default()
+        };
+        let mode = FlashMode::resolve(&FlashOptions::default(), &settings).unwrap();
+        assert!(mode.dry_run && !mode.programs());
+        assert_eq!(mode.reset_after, ResetBehavior::Halt);
+
+        let options = FlashOptions {
+            no_verify: true,
+            verify_only: true,
+            erase_unwritten: true,
+            reset_after: Some(ResetBehavior::Run),
+            ..FlashOptions::default()
+        };
+        let mode = FlashMode::resolve(&options, &settings).unwrap();
+        assert!(!mode.verify && !mode.keep_unwritten_bytes);
+        // Verifying only never simulates, even if the configuration enables a dry run.
+        assert!(mode.verify_only && !mode.dry_run);
+        assert_eq!(mode.reset_after, ResetBehavior::Run);
+    }
+
+    #[test]
+    fn flash_mode_erase_flags_override_the_opposite_setting() {
+        let skip_erase = FlashSettings {
+            skip_erase: Some(true),
+            ..FlashSettings::default()
+        };
+        let options = FlashOptions {
+            chip_erase: true,
+            ..FlashOptions::default()
+        };
+        let mode = FlashMode::resolve(&options, &skip_erase).unwrap();
+        assert!(mode.chip_erase && !mode.skip_erase);
+
+        let chip_erase = FlashSettings {
+            chip_erase: Some(true),
+            ..FlashSettings::default()
+        };
+        let options = FlashOptions {
+            skip_erase: true,
+            ..FlashOptions::default()
+        };
+        let mode = FlashMode::resolve(&options, &chip_erase).unwrap();
+        assert!(mode.skip_erase && !mode.chip_erase);
+    }
+
+    #[test]
+    fn flash_mode_rejects_a_dry_run_of_a_verify_only_profile() {
+        let settings = FlashSettings {
+            verify_only: Some(true),
+            ..FlashSettings::default()
+        };
+        let options = FlashOptions {
+            dry_run: true,
+            ..FlashOptions::default()
+        };
+        let error = FlashMode::resolve(&options, &settings).unwrap_err();
+
+        assert!(error.to_string().contains("`--dry-run` conflicts"));
+    }
+
+    #[test]
+    fn capturing_rtt_needs_programming() {
+        let mode = FlashMode::resolve(&FlashOptions::default(), &FlashSettings::default()).unwrap();
+        assert_eq!(mode.capture_rtt().unwrap().reset_after, ResetBehavior::Run);
+
+        let halt = FlashSettings {
+            reset_after: Some(ResetBehavior::Halt),
+            ..FlashSettings::default()
+        };
+        let mode = FlashMode::resolve(&FlashOptions::default(), &halt).unwrap();
+        assert_eq!(mode.capture_rtt().unwrap().reset_after, ResetBehavior::Halt);
+
+        for settings in [
+            FlashSettings {
+                verify_only: Some(true),
+                ..FlashSettings::default()
+            },
+            FlashSettings {
+                dry_run: Some(true),
+                ..FlashSettings::default()
+            },
+        ] {
+            let mode = FlashMode::resolve(&FlashOptions::default(), &settings).unwrap();
+            let error = mode.capture_rtt().unwrap_err();
+            assert!(error.to_string().contains("`--rtt` needs"), "{error}");
+        }
+    }
+
+    #[test]
+    fn flash_mode_rejects_conflicting_erase_settings() {
+        let settings = FlashSettings {
+            chip_erase: Some(true),
+            skip_erase: Some(true),
+            ..FlashSettings::default()
+        };
+        let error = FlashMode::resolve(&FlashOptions::default(), &settings).unwrap_err();
+
+        assert!(error.to_string().contains("`chip_erase` and `skip_erase`"));
+    }
+}
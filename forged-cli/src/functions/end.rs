@@ -1,10 +1,105 @@
-use cynic::MutationBuilder;
+use std::{fmt::Display, path::PathBuf};
 
-use crate::{queries::FinishRun, Result};
+use anyhow::anyhow;
+use cynic::{MutationBuilder, QueryBuilder};
+use forged::runs::RunRef;
+use serde::Serialize;
+use uuid::Uuid;
 
-pub async fn end(client: &mut forged::Client) -> Result<()> {
-    eprintln!("Finishing current device ...");
-    client.run_query(FinishRun::build(())).await?;
+use crate::{
+    functions::report::{write_report, ReportFormat, Reported},
+    progress,
+    queries::{CurrentDevice, FinishRun},
+    Result,
+};
 
-    Ok(())
+/// The run that was finished.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Finished {
+    pub run_id: Uuid,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub report: Option<Reported>,
+}
+
+impl Display for Finished {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "run: {}", self.run_id)?;
+        if let Some(report) = &self.report {
+            write!(f, "{report}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Finish the current run.
+///
+/// # Note
+/// The report is best-effort. If it cannot be written, the run is still finished.
+///
+/// # Args
+/// * `report` - The format and path of a report of the finished run to write.
+pub async fn end(
+    client: &mut forged::Client,
+    report: Option<(ReportFormat, PathBuf)>,
+) -> Result<Finished> {
+    // The device of the run is only known while it is current.
+    let report = match report {
+        Some(report) => match current_device(client).await {
+            Ok(device_id) => Some((report, device_id)),
+            Err(error) => {
+                progress!("⚠️  Skipping the report: {}", error.to_string().trim_end());
+                None
+            }
+        },
+        None => None,
+    };
+
+    progress!("Finishing current device ...");
+    let run_id = client.run_query(FinishRun::build(())).await?.run_finish;
+
+    let report = match report {
+        Some(((format, path), device_id)) => {
+            match run_report(client, device_id, run_id, format, path).await {
+                Ok(reported) => Some(reported),
+                Err(error) => {
+                    progress!(
+                        "⚠️  Failed to write the report: {}",
+                        error.to_string().trim_end()
+                    );
+                    None
+                }
+            }
+        }
+        None => None,
+    };
+
+    Ok(Finished { run_id, report })
+}
+
+async fn current_device(client: &forged::Client) -> Result<Uuid> {
+    Ok(client
+        .run_query(CurrentDevice::build(()))
+        .await?
+        .current_provisioner
+        .current_device
+        .ok_or_else(|| anyhow!("The provisioner has no current device"))?
+        .id)
+}
+
+/// Write the report of a finished run.
+async fn run_report(
+    client: &forged::Client,
+    device_id: Uuid,
+    run_id: Uuid,
+    format: ReportFormat,
+    path: PathBuf,
+) -> Result<Reported> {
+    let details = client
+        .run_details(RunRef::Device {
+            device_id,
+            run_id: Some(run_id),
+        })
+        .await?;
+    write_report(&details, format, Some(path))
 }
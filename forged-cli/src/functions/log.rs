@@ -1,55 +1,133 @@
 use crate::{
     cli::LogOption,
+    defmt::Table,
+    functions::{
+        block::{create_block, preview_requirements},
+        rtt::LineBuffer,
+    },
+    progress,
     queries::{CreateLog, CreateLogArguments},
     Result,
 };
 use anyhow::anyhow;
 use cynic::MutationBuilder;
+use forged::logs::{parser::LineParser, LogEntry, LogSink, SinkHandle, SinkOptions};
+use regex::Regex;
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::mpsc;
+use uuid::Uuid;
 
-use std::io::{prelude::*, BufReader, Read};
+use std::{
+    collections::VecDeque,
+    fmt::Display,
+    io::{prelude::*, BufReader, ErrorKind, Read},
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+/// How long a read of the serial port waits for data.
+const SERIAL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The parser of all logged lines, see [`install_parser`].
+static PARSER: OnceLock<LineParser> = OnceLock::new();
 
-const SUPPORTED_LEVELS: [&str; 6] = ["CRITICAL", "ERROR", "WARN", "INFO", "DEBUG", "TRACE"];
+/// Use a parser for all lines that are logged. Without one, the default preset is used.
+pub fn install_parser(parser: LineParser) {
+    PARSER.set(parser).ok();
+}
 
 /// Parse a line entry into a log entry.
 ///
 /// # Note
-/// Log entries generally have the form `<LEVEL> <MESSAGE>` and are parsed as such. Any data before
-/// `<LEVEL>` is omitted.
+/// The line is parsed with the installed [`LineParser`]. A device timestamp prefixes the message.
 ///
 /// # Returns
 /// The parsed log entry. If no level was found, INFO is used.
-fn parse_log_entry(line: &str) -> CreateLogArguments {
-    for level in SUPPORTED_LEVELS {
-        if let Some(index) = line.to_ascii_uppercase().find(level) {
-            return CreateLogArguments {
-                level: level.to_string(),
-                message: line.split_at(index + level.len()).1.trim().to_string(),
-            };
-        }
-    }
-
+pub fn parse_log_entry(line: &str) -> CreateLogArguments {
+    let entry: LogEntry = PARSER.get_or_init(LineParser::default).parse(line).into();
     CreateLogArguments {
-        level: "INFO".to_string(),
-        message: line.to_string(),
+        level: entry.level,
+        message: entry.message,
     }
 }
 
-async fn generate_log(client: &forged::Client, args: CreateLogArguments) -> Result<()> {
-    println!("🪵  Logging: [{}] {}", args.level, args.message);
-    client.run_query(CreateLog::build(args)).await?;
+pub async fn generate_log(client: &forged::Client, args: CreateLogArguments) -> Result<Uuid> {
+    progress!("🪵  Logging: [{}] {}", args.level, args.message);
+    Ok(client
+        .run_query(CreateLog::build(args))
+        .await?
+        .log_create
+        .id)
+}
+
+/// Buffer a log entry in a sink, which creates it in a batch with the following entries.
+async fn sink_log(sink: &mut LogSink, args: CreateLogArguments) -> Result<()> {
+    progress!("🪵  Logging: [{}] {}", args.level, args.message);
+    sink.push(LogEntry::new(args.level, args.message)).await?;
     Ok(())
 }
 
+/// Buffer a log entry in a sink without waiting, e.g. while polling a target.
+///
+/// # Returns
+/// Whether the entry was buffered, see [`SinkHandle::try_push`].
+pub fn queue_log(handle: &SinkHandle, args: CreateLogArguments) -> bool {
+    progress!("🪵  Logging: [{}] {}", args.level, args.message);
+    handle.try_push(LogEntry::new(args.level, args.message))
+}
+
+/// Send the remaining entries of a sink, warning about entries that did not fit into its queue.
+///
+/// # Returns
+/// The IDs of the created logs.
+pub async fn finish_sink(sink: LogSink) -> Result<Vec<Uuid>> {
+    let dropped = sink.dropped();
+    let log_ids = sink.finish().await?;
+    if dropped > 0 {
+        progress!(
+            "⚠️  Dropped {dropped} log entries that were produced faster than they were sent"
+        );
+    }
+    Ok(log_ids)
+}
+
+/// The log entries that were created.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Logged {
+    pub log_ids: Vec<Uuid>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub block_ids: Vec<Uuid>,
+}
+
+impl Display for Logged {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for id in &self.log_ids {
+            writeln!(f, "log: {id}")?;
+        }
+        for id in &self.block_ids {
+            writeln!(f, "block: {id}")?;
+        }
+        Ok(())
+    }
+}
+
 /// Generate log entries from an input file or STDIN.
 ///
+/// Streamed input is read line by line, or decoded as defmt frames if an ELF file is given. Its
+/// entries are created in batches.
+///
 /// # Args
 /// * `token` - The provisioner authentication token.
 /// * `instance_url` - The URL of the instance to generate logs on.
 /// * `file` - An optional file to generate logs from. If `None`, STDIN will be used.
-pub async fn log(client: &mut forged::Client, options: LogOption) -> Result<()> {
+pub async fn log(client: &mut forged::Client, options: LogOption) -> Result<Logged> {
+    let mut log_ids = Vec::new();
+
     match options {
-        LogOption::Stream { filename } => {
-            let input: Box<dyn Read> = if let Some(filename) = filename {
+        LogOption::Stream { filename, defmt } => {
+            let mut input: Box<dyn Read> = if let Some(filename) = filename {
                 let file = std::fs::File::open(filename)
                     .map_err(|e| anyhow!("Failed to open file: {e}"))?;
                 Box::new(file)
@@ -57,17 +135,324 @@ pub async fn log(client: &mut forged::Client, options: LogOption) -> Result<()>
                 Box::new(std::io::stdin())
             };
 
-            for line in BufReader::new(input).lines() {
-                let parsed_log =
-                    parse_log_entry(&line.map_err(|e| anyhow!("Failed to read line: {e}"))?);
-                generate_log(client, parsed_log).await?;
+            if let Some(elf) = defmt {
+                let elf = std::fs::read(&elf)
+                    .map_err(|e| anyhow!("Failed to read ELF file {elf}: {e}"))?;
+                let table = Table::parse(&elf)?
+                    .ok_or_else(|| anyhow!("The ELF file contains no defmt data"))?;
+
+                let mut decoder = table.decoder();
+                let mut sink = client.log_sink(SinkOptions::default());
+                let mut buffer = [0; 1024];
+                loop {
+                    let count = match input.read(&mut buffer) {
+                        Ok(0) => break,
+                        Ok(count) => count,
+                        Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                        Err(e) => return Err(anyhow!("Failed to read input: {e}").into()),
+                    };
+                    for frame in decoder.push(&buffer[..count]) {
+                        sink_log(&mut sink, frame.into_log()).await?;
+                    }
+                }
+                if decoder.has_pending() {
+                    progress!("⚠️  The input ends with an incomplete defmt frame");
+                }
+                log_ids = sink.finish().await?;
+            } else {
+                let mut sink = client.log_sink(SinkOptions::default());
+                for line in BufReader::new(input).lines() {
+                    let parsed_log =
+                        parse_log_entry(&line.map_err(|e| anyhow!("Failed to read line: {e}"))?);
+                    sink_log(&mut sink, parsed_log).await?;
+                }
+                log_ids = sink.finish().await?;
             }
         }
 
+        LogOption::Serial {
+            port,
+            baud,
+            until,
+            timeout,
+            blocks,
+        } => {
+            let until = until
+                .map(|until| Regex::new(&until))
+                .transpose()
+                .map_err(|e| anyhow!("Invalid exit pattern: {e}"))?;
+            let timeout = timeout.map(Duration::from_secs);
+            return serial(client, &port, baud, until, timeout, blocks).await;
+        }
+
         LogOption::Entry { level, message } => {
-            generate_log(client, CreateLogArguments { level, message }).await?;
+            log_ids.push(generate_log(client, CreateLogArguments { level, message }).await?);
         }
     }
 
-    Ok(())
+    Ok(Logged {
+        log_ids,
+        block_ids: Vec::new(),
+    })
+}
+
+/// Generate log entries from the lines of a serial port.
+///
+/// The lines are created in batches, while blocks are created as soon as their line is read. A
+/// block that cannot be created is skipped with a warning.
+///
+/// # Args
+/// * `port` - The name of the serial port.
+/// * `baud` - The baud rate of the serial port.
+/// * `until` - Stop reading after a line matching this pattern.
+/// * `timeout` - Stop reading after this duration.
+/// * `blocks` - Create a block for every `KEY=VALUE` line, see [`parse_block_line`].
+async fn serial(
+    client: &mut forged::Client,
+    port: &str,
+    baud: u32,
+    until: Option<Regex>,
+    timeout: Option<Duration>,
+    blocks: bool,
+) -> Result<Logged> {
+    let mut capture = SerialCapture::open(port, baud, until, timeout)?;
+    progress!("🔌 Reading {port} at {baud} baud");
+
+    let requirements = if blocks {
+        preview_requirements(client).await
+    } else {
+        Vec::new()
+    };
+    let mut sink = client.log_sink(SinkOptions::default());
+    let mut block_ids = Vec::new();
+    let captured = async {
+        while let Some(line) = capture.next_line().await? {
+            sink_log(&mut sink, parse_log_entry(&line)).await?;
+            let Some((schema_name, data)) = parse_block_line(&line).filter(|_| blocks) else {
+                continue;
+            };
+            match create_block(client, &requirements, schema_name.clone(), data).await {
+                Ok(created) => block_ids.push(created.block_id),
+                Err(error) => progress!("⚠️  Skipping block `{schema_name}`: {error}"),
+            }
+        }
+        Ok::<_, crate::Error>(())
+    }
+    .await;
+
+    // The logs buffered so far are sent even if the capture failed.
+    let log_ids = finish_sink(sink).await?;
+    captured?;
+    Ok(Logged { log_ids, block_ids })
+}
+
+/// The lines of a serial port, read on a separate thread.
+struct SerialCapture {
+    name: String,
+    chunks: mpsc::Receiver<std::io::Result<Vec<u8>>>,
+    lines: LineBuffer,
+    pending: VecDeque<String>,
+    until: Option<Regex>,
+    deadline: Option<Instant>,
+    stopped: bool,
+}
+
+impl SerialCapture {
+    /// Open a serial port and start reading it.
+    ///
+    /// # Args
+    /// * `port` - The name of the serial port.
+    /// * `baud` - The baud rate of the serial port.
+    /// * `until` - Stop after a line matching this pattern.
+    /// * `timeout` - Stop after this duration.
+    fn open(
+        port: &str,
+        baud: u32,
+        until: Option<Regex>,
+        timeout: Option<Duration>,
+    ) -> Result<Self> {
+        let mut serial = serialport::new(port, baud)
+            .timeout(SERIAL_POLL_INTERVAL)
+            .open()
+            .map_err(|e| anyhow!("Failed to open serial port {port}: {e}"))?;
+
+        let (sender, chunks) = mpsc::channel(16);
+        std::thread::spawn(move || {
+            let mut buffer = [0; 1024];
+            while !sender.is_closed() {
+                let chunk = match serial.read(&mut buffer) {
+                    Ok(count) => Ok(buffer[..count].to_vec()),
+                    Err(e) if matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::Interrupted) => {
+                        continue
+                    }
+                    Err(e) => Err(e),
+                };
+                let last = !matches!(&chunk, Ok(data) if !data.is_empty());
+                if sender.blocking_send(chunk).is_err() || last {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self {
+            name: port.to_string(),
+            chunks,
+            lines: LineBuffer::default(),
+            pending: VecDeque::new(),
+            until,
+            deadline: timeout.map(|timeout| Instant::now() + timeout),
+            stopped: false,
+        })
+    }
+
+    /// Wait for the next line.
+    ///
+    /// # Returns
+    /// The line, or `None` after the line matching the exit pattern, after the timeout or when the
+    /// port is closed. An incomplete last line is returned before the port is closed.
+    async fn next_line(&mut self) -> Result<Option<String>> {
+        loop {
+            if self.stopped {
+                return Ok(None);
+            }
+            if let Some(line) = self.pending.pop_front() {
+                if self
+                    .until
+                    .as_ref()
+                    .is_some_and(|until| until.is_match(&line))
+                {
+                    progress!("🔌 Found the exit pattern, stopping the capture");
+                    self.stopped = true;
+                }
+                return Ok(Some(line));
+            }
+
+            let chunk = match self.deadline {
+                Some(deadline) => {
+                    match tokio::time::timeout_at(deadline.into(), self.chunks.recv()).await {
+                        Ok(chunk) => chunk,
+                        Err(_) => {
+                            progress!("🔌 Timed out, stopping the capture");
+                            self.stopped = true;
+                            continue;
+                        }
+                    }
+                }
+                None => self.chunks.recv().await,
+            };
+
+            match chunk {
+                Some(Ok(data)) if !data.is_empty() => self.pending.extend(self.lines.push(&data)),
+                Some(Err(e)) => {
+                    return Err(anyhow!("Failed to read serial port {}: {e}", self.name).into())
+                }
+                _ => {
+                    self.stopped = true;
+                    return Ok(std::mem::take(&mut self.lines).finish());
+                }
+            }
+        }
+    }
+}
+
+/// Parse a `KEY=VALUE` line into the schema name and data of a block.
+///
+/// # Note
+/// Keys consist of letters, digits, `_`, `-` and `.`. The value is parsed as JSON, e.g. a number,
+/// and used as a string if it is not valid JSON.
+///
+/// # Returns
+/// The schema name and block data, or `None` if the line is no `KEY=VALUE` line.
+pub fn parse_block_line(line: &str) -> Option<(String, Value)> {
+    let (key, value) = line.trim().split_once('=')?;
+    let valid_key = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+    if !valid_key {
+        return None;
+    }
+
+    let value = value.trim();
+    let data = serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()));
+    Some((key.to_string(), data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn block_lines() {
+        assert_eq!(
+            parse_block_line("vdd_mv= 3301"),
+            Some(("vdd_mv".to_string(), serde_json::json!(3301)))
+        );
+        assert_eq!(
+            parse_block_line("fw.version=v1.2"),
+            Some(("fw.version".to_string(), serde_json::json!("v1.2")))
+        );
+        assert_eq!(parse_block_line("[W] a=b"), None);
+        assert_eq!(parse_block_line("=3"), None);
+        assert_eq!(parse_block_line("no block"), None);
+    }
+
+    #[cfg(unix)]
+    mod serial {
+        use std::io::Write;
+
+        use serialport::{SerialPort, TTYPort};
+
+        use super::*;
+
+        /// A pseudo terminal pair, with the name of the end that is captured.
+        fn pty() -> (TTYPort, String) {
+            let (master, slave) = TTYPort::pair().expect("Failed to open a pty pair");
+            let name = slave.name().expect("The pty has a name");
+            (master, name)
+        }
+
+        async fn lines(capture: &mut SerialCapture) -> Vec<String> {
+            let mut lines = Vec::new();
+            while let Some(line) = capture.next_line().await.unwrap() {
+                lines.push(line);
+            }
+            lines
+        }
+
+        #[tokio::test]
+        async fn stops_at_the_exit_pattern() {
+            let (mut master, name) = pty();
+            let until = Regex::new("^DONE").unwrap();
+            let mut capture = SerialCapture::open(&name, 115200, Some(until), None).unwrap();
+
+            master
+                .write_all(b"[W] battery low\r\n<3>no ack\nbooted\nDONE 2 tests\nlate\n")
+                .unwrap();
+            let lines = lines(&mut capture).await;
+            assert_eq!(
+                lines,
+                ["[W] battery low", "<3>no ack", "booted", "DONE 2 tests"]
+            );
+
+            let levels: Vec<_> = lines
+                .iter()
+                .map(|line| parse_log_entry(line).level)
+                .collect();
+            assert_eq!(levels, ["WARN", "ERROR", "INFO", "INFO"]);
+        }
+
+        #[tokio::test]
+        async fn stops_after_the_timeout() {
+            let (mut master, name) = pty();
+            let timeout = Duration::from_millis(300);
+            let mut capture = SerialCapture::open(&name, 115200, None, Some(timeout)).unwrap();
+
+            let started = Instant::now();
+            master.write_all(b"first\n").unwrap();
+            assert_eq!(lines(&mut capture).await, ["first"]);
+            assert!(started.elapsed() >= timeout);
+            assert!(started.elapsed() < timeout + SERIAL_POLL_INTERVAL * 5);
+        }
+    }
 }
@@ -0,0 +1,117 @@
+use std::fmt::Display;
+
+use anyhow::anyhow;
+use forged::auth::{Session, User, UserType};
+use serde::Serialize;
+
+use crate::{
+    input::{prompt, read_line_verbatim},
+    progress, Result,
+};
+
+/// The user that signed in or out.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignedIn {
+    pub email: Option<String>,
+    pub signed_in: bool,
+}
+
+impl Display for SignedIn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.email, self.signed_in) {
+            (Some(email), true) => writeln!(f, "signed in: {email}"),
+            (_, true) => writeln!(f, "signed in"),
+            (_, false) => writeln!(f, "signed out"),
+        }
+    }
+}
+
+/// The signed-in user.
+#[derive(Serialize)]
+pub struct WhoAmI(pub User);
+
+impl Display for WhoAmI {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let user = &self.0;
+        writeln!(f, "{} ({})", user.email, user.id)?;
+        match user.user_type {
+            UserType::Native => writeln!(f, "Sign in: email")?,
+            UserType::Oauth => writeln!(f, "Sign in: OAuth")?,
+        }
+        if user.admin {
+            writeln!(f, "Admin:   yes")?;
+        }
+        Ok(())
+    }
+}
+
+/// Sign in as a user and persist the session.
+///
+/// # Args
+/// * `email` - The email address of the user. Prompted for if `None`.
+/// * `password_stdin` - Read the password from STDIN instead of prompting for it.
+/// * `oauth` - Sign in with the given OAuth provider instead of email and password.
+pub async fn login(
+    client: &forged::Client,
+    email: Option<String>,
+    password_stdin: bool,
+    oauth: Option<String>,
+) -> Result<SignedIn> {
+    let (session, email) = if let Some(provider) = oauth {
+        let data = client.oauth_url(provider.clone()).await?;
+        eprintln!("🔑 Open the following URL to sign in with {provider}:");
+        eprintln!("{}", data.auth_url);
+        let code = prompt("Code: ")?;
+
+        let session = client.sign_in_with_oauth(provider, code, &data).await?;
+        (session, None)
+    } else {
+        let email = match email {
+            Some(email) => email,
+            None => prompt("Email: ")?,
+        };
+        let password = if password_stdin {
+            read_line_verbatim(std::io::stdin().lock())?
+        } else {
+            rpassword::prompt_password("Password: ")
+                .map_err(|e| anyhow!("Failed to read password: {e}"))?
+        };
+
+        let session = client.sign_in(email.clone(), password).await?;
+        (session, Some(email))
+    };
+
+    let path = session.save()?;
+    progress!("🔑 Signed in. The session is stored in {path:?}");
+
+    Ok(SignedIn {
+        email,
+        signed_in: true,
+    })
+}
+
+/// Sign out the current user by removing the persisted session.
+pub fn logout() -> Result<SignedIn> {
+    if !Session::remove()? {
+        progress!("Nobody is signed in");
+    }
+
+    Ok(SignedIn {
+        email: None,
+        signed_in: false,
+    })
+}
+
+/// Get the signed-in user.
+pub async fn whoami(client: &forged::Client) -> Result<WhoAmI> {
+    Ok(WhoAmI(client.current_user().await?))
+}
+
+/// Create a client that acts on behalf of the signed-in user.
+pub fn user_client() -> Result<forged::Client> {
+    let session = Session::load()?
+        .ok_or_else(|| anyhow!("Nobody is signed in. Run `forged-cli login` first."))?;
+
+    Ok(forged::Client::from_session(session))
+}
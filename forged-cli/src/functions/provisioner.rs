@@ -0,0 +1,302 @@
+use std::{
+    fmt::{self, Display},
+    path::Path,
+};
+
+use anyhow::anyhow;
+use cynic::{MutationBuilder, QueryBuilder};
+use forged::config::ConfigFile;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{
+    cli::ProvisionerOption,
+    output::write_table,
+    progress,
+    queries::{
+        AssignProvisionerStation, AssignProvisionerStationArguments, CreateProvisioner,
+        CreateProvisionerArguments, DeleteProvisioner, ProjectArguments, ProjectProvisioners,
+        Projects, ProvisionerArguments, ProvisionerDetails, RegenerateProvisionerToken,
+        RenameProvisioner, RenameProvisionerArguments,
+    },
+    Result,
+};
+
+/// The outcome of a `provisioner` command.
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum ProvisionerResult {
+    List(Vec<ProvisionerDetails>),
+    Changed(ProvisionerDetails),
+    Deleted {
+        #[serde(rename = "deletedId")]
+        id: Uuid,
+    },
+}
+
+impl Display for ProvisionerResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProvisionerResult::List(provisioners) => write_table(
+                f,
+                ["ID", "NAME", "STATION", "ACTIVE"],
+                provisioners.iter().map(|provisioner| {
+                    [
+                        provisioner.id.to_string(),
+                        provisioner.name.clone(),
+                        provisioner.station.clone(),
+                        if provisioner.active { "yes" } else { "no" }.to_string(),
+                    ]
+                }),
+            ),
+            ProvisionerResult::Changed(provisioner) => {
+                writeln!(f, "provisioner: {}", provisioner.id)?;
+                if let Some(token) = &provisioner.token {
+                    writeln!(f, "token: {token}")?;
+                }
+                Ok(())
+            }
+            ProvisionerResult::Deleted { id } => writeln!(f, "deleted: {id}"),
+        }
+    }
+}
+
+/// Administer the provisioners of a project.
+///
+/// # Args
+/// * `client` - A client acting on behalf of a signed-in user.
+/// * `option` - The provisioner command to run.
+pub async fn provisioner(
+    client: &forged::Client,
+    option: ProvisionerOption,
+) -> Result<ProvisionerResult> {
+    let changed = match option {
+        ProvisionerOption::Create {
+            project,
+            name,
+            station,
+            save_profile,
+        } => {
+            let project_id = find_project(client, &project).await?;
+            progress!("🛠️  Creating provisioner `{name}` for station `{station}` ...");
+            let provisioner = client
+                .run_query(CreateProvisioner::build(CreateProvisionerArguments {
+                    name,
+                    station,
+                    project_id,
+                }))
+                .await?
+                .provisioner_create;
+
+            save_token(client, provisioner, save_profile)?
+        }
+
+        ProvisionerOption::List { project } => {
+            let id = find_project(client, &project).await?;
+            let provisioners = client
+                .run_query(ProjectProvisioners::build(ProjectArguments { id }))
+                .await?
+                .project
+                .provisioners
+                .into_iter()
+                .map(|provisioner| ProvisionerDetails {
+                    token: None,
+                    ..provisioner
+                })
+                .collect();
+
+            return Ok(ProvisionerResult::List(provisioners));
+        }
+
+        ProvisionerOption::Rename { id, name } => {
+            progress!("🛠️  Renaming provisioner {id} to `{name}` ...");
+            let provisioner = client
+                .run_query(RenameProvisioner::build(RenameProvisionerArguments {
+                    id,
+                    name,
+                }))
+                .await?
+                .provisioner_rename;
+
+            ProvisionerDetails {
+                token: None,
+                ..provisioner
+            }
+        }
+
+        ProvisionerOption::AssignStation { id, station } => {
+            progress!("🛠️  Assigning provisioner {id} to station `{station}` ...");
+            let provisioner = client
+                .run_query(AssignProvisionerStation::build(
+                    AssignProvisionerStationArguments { id, station },
+                ))
+                .await?
+                .provisioner_assign_station;
+
+            ProvisionerDetails {
+                token: None,
+                ..provisioner
+            }
+        }
+
+        ProvisionerOption::RegenerateToken { id, save_profile } => {
+            progress!("🛠️  Regenerating the token of provisioner {id} ...");
+            let provisioner = client
+                .run_query(RegenerateProvisionerToken::build(ProvisionerArguments {
+                    id,
+                }))
+                .await?
+                .provisioner_regenerate_token;
+
+            save_token(client, provisioner, save_profile)?
+        }
+
+        ProvisionerOption::Delete { id } => {
+            progress!("🛠️  Deleting provisioner {id} ...");
+            let id = client
+                .run_query(DeleteProvisioner::build(ProvisionerArguments { id }))
+                .await?
+                .provisioner_delete;
+
+            return Ok(ProvisionerResult::Deleted { id });
+        }
+    };
+
+    Ok(ProvisionerResult::Changed(changed))
+}
+
+/// Find a project of the signed-in user by its name or id.
+async fn find_project(client: &forged::Client, project: &str) -> Result<Uuid> {
+    let projects = client.run_query(Projects::build(())).await?.projects;
+
+    if let Ok(id) = Uuid::parse_str(project) {
+        if projects.iter().any(|candidate| candidate.id == id) {
+            return Ok(id);
+        }
+    }
+
+    let mut matches = projects
+        .iter()
+        .filter(|candidate| candidate.name == project);
+    match (matches.next(), matches.next()) {
+        (Some(found), None) => Ok(found.id),
+        (Some(_), Some(_)) => Err(anyhow!(
+            "Multiple projects are named `{project}`. Please specify the project id."
+        )
+        .into()),
+        (None, _) => Err(anyhow!(
+            "Project `{project}` not found. Available projects: [ {} ]",
+            projects
+                .iter()
+                .map(|project| project.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+        .into()),
+    }
+}
+
+/// Store the token of a provisioner in a configuration profile.
+///
+/// # Returns
+/// The provisioner. If the token was stored, it is removed from the provisioner so that it is not
+/// printed.
+fn save_token(
+    client: &forged::Client,
+    provisioner: ProvisionerDetails,
+    profile: Option<String>,
+) -> Result<ProvisionerDetails> {
+    let Some(profile) = profile else {
+        return Ok(provisioner);
+    };
+
+    let token = provisioner
+        .token
+        .clone()
+        .ok_or_else(|| anyhow!("The API did not return a token for the provisioner"))?;
+
+    let path = ConfigFile::path().ok_or_else(|| anyhow!("Could not determine the config path"))?;
+    store_token(&path, &profile, token, client.instance_url())?;
+    progress!("⚙️  Stored the provisioner token in profile `{profile}` of {path:?}");
+
+    Ok(ProvisionerDetails {
+        token: None,
+        ..provisioner
+    })
+}
+
+/// Store a token and the instance it belongs to in a profile of the configuration file at `path`.
+fn store_token(path: &Path, profile: &str, token: String, instance_url: &str) -> Result<()> {
+    let mut file = ConfigFile::load_from(path)?;
+    let settings = file.profile_mut(profile);
+    settings.token = Some(token);
+    if instance_url != forged::DEFAULT_API_URL {
+        settings.api_url = Some(instance_url.to_string());
+    }
+    file.save_to(path)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stores_the_token_in_a_profile() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        store_token(
+            &path,
+            "station",
+            "first".to_string(),
+            forged::DEFAULT_API_URL,
+        )
+        .unwrap();
+
+        let file = ConfigFile::load_from(&path).unwrap();
+        assert_eq!(file.profiles["station"].token.as_deref(), Some("first"));
+        assert_eq!(file.profiles["station"].api_url, None);
+    }
+
+    #[test]
+    fn keeps_other_profiles_of_an_existing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(
+            &path,
+            r#"
+profile = "lab"
+
+[profiles.lab]
+token = "lab-token"
+
+[profiles.station]
+token = "old"
+"#,
+        )
+        .unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        }
+
+        let instance = "https://api.staging.forged.dev/graphql";
+        store_token(&path, "station", "new".to_string(), instance).unwrap();
+
+        let file = ConfigFile::load_from(&path).unwrap();
+        assert_eq!(file.profile.as_deref(), Some("lab"));
+        assert_eq!(file.profiles["lab"].token.as_deref(), Some("lab-token"));
+        assert_eq!(file.profiles["station"].token.as_deref(), Some("new"));
+        assert_eq!(file.profiles["station"].api_url.as_deref(), Some(instance));
+
+        // The new token must not be readable by other users of the station.
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+}
@@ -0,0 +1,537 @@
+use std::{
+    fmt::{self, Display, Write as _},
+    path::PathBuf,
+};
+
+use anyhow::anyhow;
+use clap::ArgEnum;
+use forged::runs::{RunDetails, StepStatus};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{functions::run::logs_text, progress, Result};
+
+/// The format of a run report.
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// JUnit XML, as consumed by CI servers.
+    Junit,
+    /// A self-contained HTML page.
+    Html,
+    /// The test cases and logs as JSON.
+    Json,
+}
+
+impl ReportFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ReportFormat::Junit => "xml",
+            ReportFormat::Html => "html",
+            ReportFormat::Json => "json",
+        }
+    }
+}
+
+/// The report that was written.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Reported {
+    pub run_id: Uuid,
+    pub path: PathBuf,
+    pub tests: usize,
+    pub failures: usize,
+}
+
+impl Display for Reported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "report: {}", self.path.display())
+    }
+}
+
+/// A run mapped to test cases.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Report {
+    pub run_id: Uuid,
+    pub device_id: Uuid,
+    pub station: String,
+    pub timestamp: String,
+    pub status: StepStatus,
+    pub test_cases: Vec<TestCase>,
+    pub logs: String,
+}
+
+/// A step, requirement check or the overall outcome of a run.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TestCase {
+    pub name: String,
+    pub class_name: String,
+    pub outcome: Outcome,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Passed,
+    Failed,
+    Skipped,
+}
+
+impl Report {
+    /// Map the steps, requirement checks and errors of a run to test cases.
+    pub fn new(details: &RunDetails) -> Self {
+        let mut test_cases = Vec::new();
+
+        let mut steps: Vec<_> = details.steps.iter().collect();
+        steps.sort_by_key(|step| step.step.position);
+        for step in steps {
+            test_cases.push(TestCase {
+                name: step
+                    .step
+                    .label
+                    .clone()
+                    .unwrap_or_else(|| format!("Step {}", step.step.position + 1)),
+                class_name: format!("{}.steps", step.station),
+                outcome: outcome(step.status),
+                message: (step.status == StepStatus::Fail).then(|| "The step failed".to_string()),
+            });
+        }
+
+        for check in &details.requirement_checks {
+            let value = check.block_id.and_then(|id| {
+                details
+                    .blocks
+                    .iter()
+                    .find(|block| block.id == id)
+                    .map(|block| block.data_decoded.to_string())
+            });
+            test_cases.push(TestCase {
+                name: check.requirement.to_string(),
+                class_name: format!("{}.requirements", details.station),
+                outcome: if check.passed {
+                    Outcome::Passed
+                } else {
+                    Outcome::Failed
+                },
+                message: (!check.passed).then(|| match value {
+                    Some(value) => format!(
+                        "{} = {value} violates the requirement",
+                        check.requirement.schema.name
+                    ),
+                    None => "The requirement was violated".to_string(),
+                }),
+            });
+        }
+
+        test_cases.push(TestCase {
+            name: "Run".to_string(),
+            class_name: format!("{}.run", details.station),
+            outcome: outcome(details.run_status),
+            message: match details.run_status {
+                StepStatus::Fail if details.errors.is_empty() => Some("The run failed".to_string()),
+                StepStatus::Fail => Some(details.errors.join("\n")),
+                StepStatus::Pending if !details.finished => {
+                    Some("The run is not finished".to_string())
+                }
+                _ => None,
+            },
+        });
+
+        Report {
+            run_id: details.id,
+            device_id: details.device_id,
+            station: details.station.clone(),
+            timestamp: details.creation_date.to_string(),
+            status: details.run_status,
+            test_cases,
+            logs: logs_text(&details.logs),
+        }
+    }
+
+    fn count(&self, outcome: Outcome) -> usize {
+        self.test_cases
+            .iter()
+            .filter(|case| case.outcome == outcome)
+            .count()
+    }
+
+    /// Render the report as a JUnit XML document with a single test suite.
+    pub fn junit(&self) -> String {
+        let mut xml = String::new();
+        self.write_junit(&mut xml)
+            .expect("Writing to a string cannot fail");
+        xml
+    }
+
+    fn write_junit(&self, xml: &mut String) -> fmt::Result {
+        let name = escape(&format!("{} run {}", self.station, self.run_id));
+        let (tests, failures) = (self.test_cases.len(), self.count(Outcome::Failed));
+        writeln!(xml, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+        writeln!(
+            xml,
+            "<testsuites name=\"{name}\" tests=\"{tests}\" failures=\"{failures}\">"
+        )?;
+        writeln!(
+            xml,
+            "  <testsuite name=\"{name}\" tests=\"{tests}\" failures=\"{failures}\" errors=\"0\" \
+             skipped=\"{}\" timestamp=\"{}\" hostname=\"{}\">",
+            self.count(Outcome::Skipped),
+            escape(&self.timestamp),
+            self.device_id,
+        )?;
+        writeln!(xml, "    <properties>")?;
+        for (name, value) in [
+            ("run", self.run_id.to_string()),
+            ("device", self.device_id.to_string()),
+            ("station", self.station.clone()),
+        ] {
+            writeln!(
+                xml,
+                "      <property name=\"{name}\" value=\"{}\"/>",
+                escape(&value)
+            )?;
+        }
+        writeln!(xml, "    </properties>")?;
+
+        for case in &self.test_cases {
+            write!(
+                xml,
+                "    <testcase name=\"{}\" classname=\"{}\"",
+                escape(&case.name),
+                escape(&case.class_name)
+            )?;
+            let message = case.message.as_deref().unwrap_or_default();
+            match case.outcome {
+                Outcome::Passed => writeln!(xml, "/>")?,
+                Outcome::Failed => writeln!(
+                    xml,
+                    ">\n      <failure message=\"{}\">{}</failure>\n    </testcase>",
+                    escape(message.lines().next().unwrap_or_default()),
+                    escape(message)
+                )?,
+                Outcome::Skipped => writeln!(
+                    xml,
+                    ">\n      <skipped message=\"{}\"/>\n    </testcase>",
+                    escape(message)
+                )?,
+            }
+        }
+
+        writeln!(xml, "    <system-out>{}</system-out>", escape(&self.logs))?;
+        writeln!(xml, "  </testsuite>\n</testsuites>")
+    }
+
+    /// Render the report as a self-contained HTML page.
+    pub fn html(&self) -> String {
+        let mut html = String::new();
+        self.write_html(&mut html)
+            .expect("Writing to a string cannot fail");
+        html
+    }
+
+    fn write_html(&self, html: &mut String) -> fmt::Result {
+        let title = escape(&format!("{} run {}", self.station, self.run_id));
+        writeln!(
+            html,
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>"
+        )?;
+        writeln!(
+            html,
+            "<style>{STYLE}</style>\n</head>\n<body>\n<h1>{title}</h1>"
+        )?;
+        writeln!(
+            html,
+            "<p>Device {} &middot; started {} &middot; <span class=\"{}\">{:?}</span> &middot; \
+             {} tests, {} failed, {} skipped</p>",
+            self.device_id,
+            escape(&self.timestamp),
+            outcome_class(outcome(self.status)),
+            self.status,
+            self.test_cases.len(),
+            self.count(Outcome::Failed),
+            self.count(Outcome::Skipped),
+        )?;
+        writeln!(
+            html,
+            "<table>\n<tr><th>Test</th><th>Group</th><th>Outcome</th><th>Message</th></tr>"
+        )?;
+        for case in &self.test_cases {
+            let class = outcome_class(case.outcome);
+            writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td class=\"{class}\">{class}</td><td>{}</td></tr>",
+                escape(&case.name),
+                escape(&case.class_name),
+                escape(case.message.as_deref().unwrap_or_default()).replace('\n', "<br>")
+            )?;
+        }
+        writeln!(
+            html,
+            "</table>\n<h2>Logs</h2>\n<pre>{}</pre>\n</body>\n</html>",
+            escape(&self.logs)
+        )
+    }
+
+    fn render(&self, format: ReportFormat) -> String {
+        match format {
+            ReportFormat::Junit => self.junit(),
+            ReportFormat::Html => self.html(),
+            ReportFormat::Json => serde_json::to_string_pretty(self)
+                .expect("Serializing this should always work. Please report this as a bug."),
+        }
+    }
+}
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; }
+td, th { border: 1px solid #ccc; padding: 0.3em 0.6em; text-align: left; vertical-align: top; }
+.passed { color: #1a7f37; } .failed { color: #cf222e; } .skipped { color: #9a6700; }
+pre { background: #f6f8fa; padding: 1em; overflow-x: auto; }
+";
+
+fn outcome(status: StepStatus) -> Outcome {
+    match status {
+        StepStatus::Pass => Outcome::Passed,
+        StepStatus::Fail => Outcome::Failed,
+        StepStatus::Pending => Outcome::Skipped,
+    }
+}
+
+fn outcome_class(outcome: Outcome) -> &'static str {
+    match outcome {
+        Outcome::Passed => "passed",
+        Outcome::Failed => "failed",
+        Outcome::Skipped => "skipped",
+    }
+}
+
+/// Escape text for XML and HTML, dropping control characters that XML does not allow.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c.is_control() => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Write a report of a run to a file.
+///
+/// # Args
+/// * `details` - The run to report on.
+/// * `format` - The format of the report.
+/// * `out_file` - The path of the report. Defaults to `report-<run id>.<extension>`.
+pub fn write_report(
+    details: &RunDetails,
+    format: ReportFormat,
+    out_file: Option<PathBuf>,
+) -> Result<Reported> {
+    let report = Report::new(details);
+    let path = out_file
+        .unwrap_or_else(|| PathBuf::from(format!("report-{}.{}", details.id, format.extension())));
+    progress!("📝 Writing {path:?}");
+    std::fs::write(&path, report.render(format))
+        .map_err(|e| anyhow!("Failed to write {path:?}: {e}"))?;
+
+    Ok(Reported {
+        run_id: details.id,
+        path,
+        tests: report.test_cases.len(),
+        failures: report.count(Outcome::Failed),
+    })
+}
+
+pub async fn report(
+    client: &forged::Client,
+    run: forged::runs::RunRef,
+    format: ReportFormat,
+    out_file: Option<PathBuf>,
+) -> Result<Reported> {
+    progress!("⬇️  Fetching run ...");
+    let details = client.run_details(run).await?;
+    write_report(&details, format, out_file)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    const BLOCK_ID: &str = "33333333-3333-3333-3333-333333333333";
+
+    fn details() -> RunDetails {
+        let step = |position: i32, label: Option<&str>, status: &str| {
+            json!({
+                "status": status,
+                "station": "flash",
+                "creationDate": "2024-05-01T12:00:00Z",
+                "changeDate": "2024-05-01T12:00:01Z",
+                "step": { "label": label, "position": position },
+            })
+        };
+        let check = |passed: bool, name: &str, block_id: Option<&str>| {
+            json!({
+                "passed": passed,
+                "checkDate": "2024-05-01T12:00:02Z",
+                "blockId": block_id,
+                "requirement": {
+                    "id": "44444444-4444-4444-4444-444444444444",
+                    "name": name,
+                    "description": null,
+                    "active": true,
+                    "lowerLimit": 3.0,
+                    "upperLimit": 3.6,
+                    "inclusiveLower": true,
+                    "inclusiveUpper": false,
+                    "schema": { "name": "vdd" },
+                },
+            })
+        };
+
+        serde_json::from_value(json!({
+            "id": "11111111-1111-1111-1111-111111111111",
+            "deviceId": "22222222-2222-2222-2222-222222222222",
+            "station": "line-3",
+            "creationDate": "2024-05-01T12:00:00Z",
+            "finished": true,
+            "runStatus": "FAIL",
+            "errors": ["Supply out of range", "Self test <3> failed"],
+            "steps": [
+                step(1, None, "FAIL"),
+                step(0, Some("Flash & verify"), "PASS"),
+                step(2, Some("Calibrate"), "PENDING"),
+            ],
+            "logs": [{
+                "id": "55555555-5555-5555-5555-555555555555",
+                "level": "ERROR",
+                "message": "vdd=3.9",
+                "creationDate": "2024-05-01T12:00:01Z",
+            }],
+            "blocks": [{ "id": BLOCK_ID, "schema": { "name": "vdd" }, "dataDecoded": 3.9 }],
+            "requirementChecks": [
+                check(false, "Supply", Some(BLOCK_ID)),
+                check(false, "Supply again", None),
+                check(true, "Supply idle", None),
+            ],
+            "attachments": [],
+        }))
+        .unwrap()
+    }
+
+    fn case<'a>(report: &'a Report, name: &str) -> &'a TestCase {
+        report
+            .test_cases
+            .iter()
+            .find(|case| case.name == name)
+            .unwrap_or_else(|| panic!("No test case {name}"))
+    }
+
+    #[test]
+    fn steps_in_playbook_order() {
+        let report = Report::new(&details());
+        let steps: Vec<_> = report
+            .test_cases
+            .iter()
+            .filter(|case| case.class_name == "flash.steps")
+            .map(|case| (case.name.as_str(), case.outcome))
+            .collect();
+        assert_eq!(
+            steps,
+            [
+                ("Flash & verify", Outcome::Passed),
+                ("Step 2", Outcome::Failed),
+                ("Calibrate", Outcome::Skipped),
+            ]
+        );
+        assert_eq!(
+            case(&report, "Step 2").message.as_deref(),
+            Some("The step failed")
+        );
+    }
+
+    #[test]
+    fn violated_requirements() {
+        let report = Report::new(&details());
+        let name = |name: &str| format!("{name} (3 <= vdd < 3.6)");
+
+        let violated = case(&report, &name("Supply"));
+        assert_eq!(violated.class_name, "line-3.requirements");
+        assert_eq!(violated.outcome, Outcome::Failed);
+        assert_eq!(
+            violated.message.as_deref(),
+            Some("vdd = 3.9 violates the requirement")
+        );
+        assert_eq!(
+            case(&report, &name("Supply again")).message.as_deref(),
+            Some("The requirement was violated")
+        );
+        let passed = case(&report, &name("Supply idle"));
+        assert_eq!(
+            (passed.outcome, passed.message.as_ref()),
+            (Outcome::Passed, None)
+        );
+    }
+
+    #[test]
+    fn run_errors() {
+        let report = Report::new(&details());
+        let run = case(&report, "Run");
+        assert_eq!(run.outcome, Outcome::Failed);
+        assert_eq!(
+            run.message.as_deref(),
+            Some("Supply out of range\nSelf test <3> failed")
+        );
+        assert_eq!(
+            (report.count(Outcome::Failed), report.test_cases.len()),
+            (4, 7)
+        );
+    }
+
+    #[test]
+    fn junit() {
+        let xml = Report::new(&details()).junit();
+
+        assert!(xml.contains(
+            "<testsuites name=\"line-3 run 11111111-1111-1111-1111-111111111111\" tests=\"7\" \
+             failures=\"4\">"
+        ));
+        assert!(xml.contains("skipped=\"1\""));
+        assert!(xml.contains("<testcase name=\"Flash &amp; verify\" classname=\"flash.steps\"/>"));
+        assert!(xml.contains(
+            "<testcase name=\"Run\" classname=\"line-3.run\">\n      \
+             <failure message=\"Supply out of range\">Supply out of range\n\
+             Self test &lt;3&gt; failed</failure>\n    </testcase>"
+        ));
+        assert!(xml.contains(
+            "<failure message=\"vdd = 3.9 violates the requirement\">vdd = 3.9 violates the \
+             requirement</failure>"
+        ));
+        assert!(xml.contains("<skipped message=\"\"/>"));
+        assert!(xml.contains("<system-out>2024-05-01T12:00:01Z ERROR vdd=3.9\n</system-out>"));
+    }
+
+    #[test]
+    fn escape_markup() {
+        assert_eq!(
+            escape("<a href=\"x\">Tom & 'Jerry'</a>"),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; &apos;Jerry&apos;&lt;/a&gt;"
+        );
+        assert_eq!(
+            escape("tab\tline\r\nbell\u{7}nul\0"),
+            "tab\tline\r\nbellnul"
+        );
+    }
+}
@@ -0,0 +1,277 @@
+use std::time::{Duration, Instant};
+
+use anyhow::anyhow;
+use forged::{
+    logs::{LogSink, SinkHandle, SinkOptions},
+    BinaryKind, BinaryPartUpload,
+};
+use probe_rs::{
+    rtt::{Rtt, ScanRegion},
+    Session,
+};
+use uuid::Uuid;
+
+use crate::{
+    cli::{ProbeOptions, RttOptions},
+    defmt::{StreamDecoder, Table},
+    functions::{
+        download::{find_release, Release},
+        log::{finish_sink, parse_log_entry, queue_log, Logged},
+    },
+    progress,
+    queries::{Binary, BinaryKind as PartKind, CreateLogArguments},
+    Result,
+};
+
+/// How long to wait for the firmware to set up the RTT control block.
+const ATTACH_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long to wait before polling an empty channel again.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Where the RTT control block of the running target is found.
+pub enum RttTarget {
+    /// A release on the server whose RTT header location is used.
+    Release(Release),
+    /// A chip with this part number. The RAM is scanned for the control block.
+    PartNumber(String),
+}
+
+/// Capture the RTT output of the running target into logs.
+///
+/// # Args
+/// * `target` - The chip the target runs and where its RTT control block is found.
+/// * `address` - The address of the RTT control block, overriding the release.
+/// * `options` - Which channel to capture and when to stop.
+/// * `probe` - Which debug probe to attach to the target with.
+pub async fn rtt(
+    client: &mut forged::Client,
+    target: RttTarget,
+    address: Option<u64>,
+    options: &RttOptions,
+    probe: &ProbeOptions,
+) -> Result<Logged> {
+    let (part_number, header, defmt) = match target {
+        RttTarget::Release(release) => {
+            let (chip, binary) = find_release(client, release).await?;
+            let elf = binary
+                .parts
+                .iter()
+                .find(|part| matches!(part.kind, PartKind::Elf));
+            let defmt = match elf {
+                Some(part) => {
+                    progress!("⛅ Grabbing the ELF part for defmt decoding ...");
+                    let image = client
+                        .binary_part(chip.id, binary.id, part.id, None)
+                        .await?;
+                    Table::parse(&image)?
+                }
+                None => None,
+            };
+            (chip.part_number, header_location(&binary), defmt)
+        }
+        RttTarget::PartNumber(part_number) => (part_number, None, None),
+    };
+    let header = match address {
+        Some(address) => Some(
+            u32::try_from(address).map_err(|_| anyhow!("Address {address:#x} is out of range"))?,
+        ),
+        None => header,
+    };
+
+    let mut session = crate::probe::attach(&part_number, probe)?;
+    let sink = client.log_sink(SinkOptions::default());
+    let log_ids = capture_logs(sink, &mut session, header, defmt.as_ref(), options).await?;
+
+    Ok(Logged {
+        log_ids,
+        block_ids: Vec::new(),
+    })
+}
+
+/// The RTT header location the server found in the parts of a binary.
+pub fn header_location(binary: &Binary) -> Option<u32> {
+    binary
+        .parts
+        .iter()
+        .filter_map(|part| part.analysis.as_ref()?.rtt.header_location)
+        .map(|location| location as u32)
+        .next()
+}
+
+/// The defmt table of the first ELF part, if the firmware logs with defmt.
+pub fn defmt_table(parts: &[BinaryPartUpload]) -> Result<Option<Table>> {
+    match parts.iter().find(|part| part.kind == BinaryKind::Elf) {
+        Some(part) => Table::parse(&part.image),
+        None => Ok(None),
+    }
+}
+
+/// Forward the lines of an RTT up channel of the attached target as logs.
+///
+/// Capturing stops at a line containing [`RttOptions::rtt_until`], after
+/// [`RttOptions::rtt_timeout`], or when the core halts and the channel is drained.
+///
+/// # Args
+/// * `sink` - The sink the logs are created with. It is finished even if capturing fails.
+/// * `session` - The session with the attached, running target.
+/// * `header` - The address of the RTT control block. If `None`, the RAM is scanned for it.
+/// * `defmt` - The defmt table of the firmware. If given, the channel is decoded as defmt frames
+///   instead of lines.
+/// * `options` - Which channel to capture and when to stop.
+///
+/// # Returns
+/// The IDs of all logs created with the sink.
+pub async fn capture_logs(
+    sink: LogSink,
+    session: &mut Session,
+    header: Option<u32>,
+    defmt: Option<&Table>,
+    options: &RttOptions,
+) -> Result<Vec<Uuid>> {
+    let captured = capture(&sink.handle(), session, header, defmt, options).await;
+    let log_ids = finish_sink(sink).await?;
+    captured?;
+    Ok(log_ids)
+}
+
+/// Queue the lines of an RTT up channel in a sink, see [`capture_logs`].
+///
+/// The probe is polled without waiting for the sink, so the target's buffer does not overflow.
+async fn capture(
+    sink: &SinkHandle,
+    session: &mut Session,
+    header: Option<u32>,
+    defmt: Option<&Table>,
+    options: &RttOptions,
+) -> Result<()> {
+    let memory_map = session.target().memory_map.clone();
+    let mut core = session.core(0)?;
+    let region = header.map(ScanRegion::Exact).unwrap_or_default();
+
+    let started = Instant::now();
+    let mut rtt = loop {
+        match Rtt::attach_region(&mut core, &memory_map, &region) {
+            Ok(rtt) => break rtt,
+            Err(error) if started.elapsed() > ATTACH_TIMEOUT => {
+                return Err(anyhow!("Failed to attach to RTT: {error}").into())
+            }
+            Err(_) => tokio::time::sleep(POLL_INTERVAL).await,
+        }
+    };
+    let channel = rtt
+        .up_channels()
+        .take(options.rtt_channel)
+        .ok_or_else(|| anyhow!("The target has no RTT up channel {}", options.rtt_channel))?;
+    progress!(
+        "📡 Capturing RTT channel {} ({})",
+        channel.number(),
+        channel.name().unwrap_or("unnamed")
+    );
+
+    let deadline = options
+        .rtt_timeout
+        .map(|timeout| Instant::now() + Duration::from_secs(timeout));
+    let mut decoder = match defmt {
+        Some(table) => {
+            progress!("🧩 Decoding defmt frames");
+            Decoder::Defmt(table.decoder())
+        }
+        None => Decoder::Lines(LineBuffer::default()),
+    };
+    let mut buffer = [0; 1024];
+    loop {
+        let count = channel
+            .read(&mut core, &mut buffer)
+            .map_err(|e| anyhow!("Failed to read the RTT channel: {e}"))?;
+        for entry in decoder.push(&buffer[..count]) {
+            let stop = options
+                .rtt_until
+                .as_ref()
+                .is_some_and(|until| entry.message.contains(until.as_str()));
+            queue_log(sink, entry);
+            if stop {
+                return Ok(());
+            }
+        }
+
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            progress!("📡 Timed out, stopping the RTT capture");
+            break;
+        }
+        // A halted core writes no more data, so a read that did not fill the buffer drained it.
+        if count < buffer.len() && core.core_halted()? {
+            progress!("📡 The core halted, stopping the RTT capture");
+            break;
+        }
+        if count == 0 {
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    if let Decoder::Lines(lines) = decoder {
+        if let Some(line) = lines.finish() {
+            queue_log(sink, parse_log_entry(&line));
+        }
+    }
+
+    Ok(())
+}
+
+/// Turns the data of an RTT channel into log entries.
+enum Decoder<'a> {
+    Lines(LineBuffer),
+    Defmt(StreamDecoder<'a>),
+}
+
+impl Decoder<'_> {
+    fn push(&mut self, data: &[u8]) -> Vec<CreateLogArguments> {
+        match self {
+            Decoder::Lines(lines) => lines
+                .push(data)
+                .iter()
+                .map(|line| parse_log_entry(line))
+                .collect(),
+            Decoder::Defmt(decoder) => decoder
+                .push(data)
+                .into_iter()
+                .map(|frame| frame.into_log())
+                .collect(),
+        }
+    }
+}
+
+/// Splits a byte stream into lines.
+#[derive(Default)]
+pub struct LineBuffer {
+    pending: Vec<u8>,
+}
+
+impl LineBuffer {
+    /// Add data to the buffer.
+    ///
+    /// # Returns
+    /// The lines completed by the data, without line endings. Empty lines are skipped.
+    pub fn push(&mut self, data: &[u8]) -> Vec<String> {
+        self.pending.extend_from_slice(data);
+
+        let mut lines = Vec::new();
+        while let Some(end) = self.pending.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\r', '\n']);
+            if !line.is_empty() {
+                lines.push(line.to_string());
+            }
+        }
+        lines
+    }
+
+    /// Take the incomplete last line, if any.
+    pub fn finish(self) -> Option<String> {
+        let line = String::from_utf8_lossy(&self.pending)
+            .trim_end_matches('\r')
+            .to_string();
+        (!line.is_empty()).then_some(line)
+    }
+}
@@ -0,0 +1,207 @@
+use std::{
+    fmt::{self, Display, Write as _},
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::anyhow;
+use forged::runs::{Attachment, RunDetails};
+use serde::Serialize;
+use uuid::Uuid;
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+use crate::{cli::RunOption, functions::attachments::file_names, progress, Result};
+
+/// The archive a run was exported to.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Exported {
+    pub run_id: Uuid,
+    pub path: PathBuf,
+    pub logs: usize,
+    pub blocks: usize,
+    pub attachments: usize,
+}
+
+impl Display for Exported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "export: {}", self.path.display())
+    }
+}
+
+pub async fn run(client: &forged::Client, option: RunOption) -> Result<Exported> {
+    match option {
+        RunOption::Export { run, out_file } => {
+            progress!("⬇️  Fetching run ...");
+            let details = client.run_details(run.run_ref()).await?;
+            // Fetch the attachments of the same run, even if a new current run started meanwhile.
+            let attachments = client
+                .attachments(forged::runs::RunRef::Device {
+                    device_id: details.device_id,
+                    run_id: Some(details.id),
+                })
+                .await?;
+
+            let path = out_file.unwrap_or_else(|| PathBuf::from(format!("run-{}.zip", details.id)));
+            progress!("📦 Writing {path:?}");
+            write_archive(&path, &details, &attachments)?;
+
+            Ok(Exported {
+                run_id: details.id,
+                path,
+                logs: details.logs.len(),
+                blocks: details.blocks.len(),
+                attachments: attachments.len(),
+            })
+        }
+    }
+}
+
+/// Write a zip archive of the details, the logs and the attachments of a run.
+fn write_archive(path: &Path, details: &RunDetails, attachments: &[Attachment]) -> Result<()> {
+    let file = File::create(path).map_err(|e| anyhow!("Failed to create {path:?}: {e}"))?;
+    let mut writer = ZipWriter::new(file);
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    let mut files = vec![
+        (
+            "run.json".to_string(),
+            serde_json::to_vec_pretty(details)
+                .expect("Serializing this should always work. Please report this as a bug."),
+        ),
+        (
+            "logs.txt".to_string(),
+            logs_text(&details.logs).into_bytes(),
+        ),
+    ];
+    for (attachment, name) in attachments.iter().zip(file_names(attachments)) {
+        files.push((format!("attachments/{name}"), attachment.data.clone()));
+    }
+    for (name, data) in files {
+        writer
+            .start_file(name.as_str(), options)
+            .and_then(|_| Ok(writer.write_all(&data)?))
+            .map_err(|e| anyhow!("Failed to write {name} to {path:?}: {e}"))?;
+    }
+    writer
+        .finish()
+        .map_err(|e| anyhow!("Failed to write {path:?}: {e}"))?;
+
+    Ok(())
+}
+
+/// Render logs as lines of creation date, level and message.
+pub fn logs_text(logs: &[forged::runs::RunLog]) -> String {
+    let mut text = String::new();
+    for log in logs {
+        writeln!(
+            text,
+            "{} {:<5} {}",
+            log.creation_date, log.level, log.message
+        )
+        .expect("Writing to a string cannot fail");
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use serde_json::json;
+    use zip::ZipArchive;
+
+    use super::*;
+
+    fn details() -> RunDetails {
+        serde_json::from_value(json!({
+            "id": "11111111-1111-1111-1111-111111111111",
+            "deviceId": "22222222-2222-2222-2222-222222222222",
+            "station": "line-3",
+            "creationDate": "2024-05-01T12:00:00Z",
+            "finished": true,
+            "runStatus": "PASS",
+            "errors": [],
+            "steps": [],
+            "logs": [
+                {
+                    "id": "55555555-5555-5555-5555-555555555555",
+                    "level": "INFO",
+                    "message": "booted",
+                    "creationDate": "2024-05-01T12:00:01Z",
+                },
+                {
+                    "id": "66666666-6666-6666-6666-666666666666",
+                    "level": "ERROR",
+                    "message": "vdd=3.9",
+                    "creationDate": "2024-05-01T12:00:02Z",
+                },
+            ],
+            "blocks": [],
+            "requirementChecks": [],
+            "attachments": [],
+        }))
+        .unwrap()
+    }
+
+    fn attachment(id: u128, name: &str, data: &[u8]) -> Attachment {
+        Attachment {
+            id: Uuid::from_u128(id),
+            name: name.to_string(),
+            mime_type: "application/octet-stream".to_string(),
+            data: data.to_vec(),
+        }
+    }
+
+    #[test]
+    fn logs_as_text() {
+        assert_eq!(
+            logs_text(&details().logs),
+            "2024-05-01T12:00:01Z INFO  booted\n2024-05-01T12:00:02Z ERROR vdd=3.9\n"
+        );
+    }
+
+    #[test]
+    fn archive_of_a_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("run.zip");
+        let attachments = [
+            attachment(1, "../trace.bin", &[0, 1, 255]),
+            attachment(2, "trace.bin", b"second"),
+        ];
+
+        write_archive(&path, &details(), &attachments).unwrap();
+
+        let mut archive = ZipArchive::new(File::open(&path).unwrap()).unwrap();
+        let mut names: Vec<_> = archive.file_names().map(str::to_string).collect();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                "attachments/trace-1.bin",
+                "attachments/trace.bin",
+                "logs.txt",
+                "run.json"
+            ]
+        );
+
+        let mut read = |name: &str| {
+            let mut data = Vec::new();
+            archive
+                .by_name(name)
+                .unwrap()
+                .read_to_end(&mut data)
+                .unwrap();
+            data
+        };
+        assert_eq!(read("attachments/trace.bin"), [0, 1, 255]);
+        assert_eq!(read("attachments/trace-1.bin"), b"second");
+        assert_eq!(read("logs.txt"), logs_text(&details().logs).into_bytes());
+
+        let run: serde_json::Value = serde_json::from_slice(&read("run.json")).unwrap();
+        assert_eq!(run["id"], "11111111-1111-1111-1111-111111111111");
+        assert_eq!(run["runStatus"], "PASS");
+        assert_eq!(run["logs"].as_array().unwrap().len(), 2);
+    }
+}
@@ -1,9 +1,392 @@
-use cynic::MutationBuilder;
+use std::fmt::Display;
 
-use crate::{queries::CreateDevice, Result};
+use anyhow::anyhow;
+use clap::ArgEnum;
+use cynic::{MutationBuilder, QueryBuilder};
+use serde::Serialize;
+use uuid::Uuid;
 
-pub async fn start(client: &mut forged::Client) -> Result<()> {
-    eprintln!("🚀 Creating a new device ...");
-    client.run_query(CreateDevice::build(())).await?;
-    Ok(())
+use crate::{
+    cli::ProbeOptions,
+    input::read_line,
+    memory::{MemoryDump, MemoryReader},
+    progress,
+    queries::{BlockSchema, BlockSchemas, CreateDevice, CreateRunFor, CreateRunForArguments},
+    Result,
+};
+
+/// Where the identifier of the device to resume is taken from.
+pub enum IdentifierSource {
+    /// The identifier was given on the command line. If `None`, a new device is created.
+    Argument(Option<String>),
+    /// Read the identifier as a single line from STDIN.
+    Stdin,
+    /// Read the identifier from the memory region of the project's identifier schema.
+    Device {
+        /// How the memory is decoded. If `None`, the format is taken from the schema.
+        format: Option<IdentifierFormat>,
+        /// An optional memory dump file and its base address to read from instead of the target.
+        memory_dump: Option<(String, u64)>,
+        /// Which debug probe to read the target memory with.
+        probe: ProbeOptions,
+    },
+}
+
+/// How the identifier memory of a device is decoded.
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IdentifierFormat {
+    U8,
+    U16,
+    U32,
+    U64,
+    I8,
+    I16,
+    I32,
+    I64,
+    /// UTF-8 up to the first NUL or erased (`0xFF`) byte.
+    String,
+    /// The raw bytes as lowercase hexadecimal.
+    Hex,
+}
+
+impl IdentifierFormat {
+    /// The format a schema contract names, e.g. `u32` or `"string"`.
+    ///
+    /// # Returns
+    /// `None` if the contract is not a plain type name, e.g. a structured schema.
+    fn from_schema(schema: &str) -> Option<Self> {
+        let name = schema.trim().trim_matches('"').to_ascii_lowercase();
+        match name.as_str() {
+            "str" => Some(Self::String),
+            name => Self::from_str(name, true).ok(),
+        }
+    }
+
+    /// The number of bytes of an integer format.
+    fn size(self) -> Option<usize> {
+        match self {
+            Self::U8 | Self::I8 => Some(1),
+            Self::U16 | Self::I16 => Some(2),
+            Self::U32 | Self::I32 => Some(4),
+            Self::U64 | Self::I64 => Some(8),
+            Self::String | Self::Hex => None,
+        }
+    }
+}
+
+/// The device and run that provisioning was started for.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Started {
+    pub device_id: Uuid,
+    pub run_id: Option<Uuid>,
+    pub identifier: Option<String>,
+}
+
+impl Display for Started {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "device: {}", self.device_id)?;
+        if let Some(run_id) = self.run_id {
+            writeln!(f, "run: {run_id}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Start provisioning a device.
+///
+/// # Args
+/// * `source` - Where to take the identifier of an existing device to resume from.
+/// * `station` - The station the run of an existing device is executed on.
+pub async fn start(
+    client: &mut forged::Client,
+    source: IdentifierSource,
+    station: Option<String>,
+) -> Result<Started> {
+    let identifier = match source {
+        IdentifierSource::Argument(identifier) => identifier,
+        IdentifierSource::Stdin => Some(read_identifier(std::io::stdin().lock())?),
+        IdentifierSource::Device {
+            format,
+            memory_dump,
+            probe,
+        } => Some(identifier_from_device(client, format, memory_dump, &probe).await?),
+    };
+
+    let Some(identifier) = identifier else {
+        progress!("🚀 Creating a new device ...");
+        let device = client
+            .run_query(CreateDevice::build(()))
+            .await?
+            .device_create;
+        return Ok(Started {
+            device_id: device.id,
+            run_id: None,
+            identifier: None,
+        });
+    };
+
+    let station = station.ok_or_else(|| anyhow!("A station is required to resume a device"))?;
+
+    progress!("🔁 Resuming device `{identifier}` on station `{station}` ...");
+    let run = client
+        .run_query(CreateRunFor::build(CreateRunForArguments {
+            identifier: identifier.clone(),
+            station,
+        }))
+        .await?
+        .run_create_for;
+
+    Ok(Started {
+        device_id: run.device_id,
+        run_id: Some(run.id),
+        identifier: Some(identifier),
+    })
+}
+
+/// Read a device identifier from an input source.
+///
+/// # Note
+/// Barcode scanners type the identifier followed by Enter, so only the first line is consumed and
+/// surrounding whitespace is stripped.
+fn read_identifier(input: impl std::io::BufRead) -> Result<String> {
+    let identifier = read_line(input)?;
+    if identifier.is_empty() {
+        return Err(anyhow!("No device identifier was provided").into());
+    }
+
+    Ok(identifier)
+}
+
+/// Read the device identifier from the target memory described by the project's identifier schema.
+///
+/// # Args
+/// * `format` - How the memory is decoded. If `None`, the format is taken from the schema.
+/// * `memory_dump` - An optional memory dump file and its base address to read from instead of
+///   attaching to the target.
+/// * `probe` - Which debug probe to attach to the target with.
+async fn identifier_from_device(
+    client: &forged::Client,
+    format: Option<IdentifierFormat>,
+    memory_dump: Option<(String, u64)>,
+    probe: &ProbeOptions,
+) -> Result<String> {
+    let query = client.run_query(BlockSchemas::build(())).await?;
+    let schema = query
+        .current_provisioner
+        .project
+        .block_schemas
+        .into_iter()
+        .find(|schema| schema.active && schema.is_device_identifier)
+        .ok_or_else(|| anyhow!("The project has no device identifier schema"))?;
+
+    if let Some((path, base_address)) = memory_dump {
+        progress!(
+            "🔍 Reading device identifier `{}` from {path} ...",
+            schema.name
+        );
+        read_schema(&schema, format, &mut MemoryDump::load(&path, base_address)?)
+    } else {
+        let source = schema.memory_source.as_ref().ok_or_else(|| {
+            anyhow!(
+                "The device identifier schema `{}` has no memory source",
+                schema.name
+            )
+        })?;
+
+        progress!(
+            "🔍 Reading device identifier `{}` from {} ({}) ...",
+            schema.name,
+            source.chip.name,
+            source.chip.part_number
+        );
+        let mut session = crate::probe::attach(&source.chip.part_number, probe)?;
+        let mut core = session.core(0)?;
+        read_schema(&schema, format, &mut core)
+    }
+}
+
+/// Read the memory source region of a block schema and decode it.
+///
+/// # Args
+/// * `format` - How the memory is decoded. If `None`, the format is taken from the schema.
+fn read_schema(
+    schema: &BlockSchema,
+    format: Option<IdentifierFormat>,
+    memory: &mut impl MemoryReader,
+) -> Result<String> {
+    let format = format
+        .or_else(|| IdentifierFormat::from_schema(&schema.schema))
+        .ok_or_else(|| {
+            anyhow!(
+                "The device identifier schema `{}` does not name an identifier format. Pass \
+                 --identifier-format to decode its memory",
+                schema.name
+            )
+        })?;
+    let source = schema.memory_source.as_ref().ok_or_else(|| {
+        anyhow!(
+            "The device identifier schema `{}` has no memory source",
+            schema.name
+        )
+    })?;
+
+    let length = usize::try_from(source.length)
+        .ok()
+        .filter(|length| *length > 0)
+        .ok_or_else(|| {
+            anyhow!(
+                "The memory source of the device identifier schema `{}` has an invalid length of \
+                 {} bytes",
+                schema.name,
+                source.length
+            )
+        })?;
+    let mut data = vec![0; length];
+    memory.read(source.address.0, &mut data)?;
+
+    decode_identifier(format, &data)
+}
+
+/// Decode raw identifier memory.
+///
+/// # Note
+/// Integers are decoded little-endian.
+fn decode_identifier(format: IdentifierFormat, data: &[u8]) -> Result<String> {
+    let mut buffer = [0; 8];
+    if let Some(size) = format.size() {
+        let bytes = data.get(..size).ok_or_else(|| {
+            anyhow!(
+                "Identifier of type {format:?} needs {size} bytes, got {}",
+                data.len()
+            )
+        })?;
+        buffer[..size].copy_from_slice(bytes);
+    }
+
+    let identifier = match format {
+        IdentifierFormat::U8
+        | IdentifierFormat::U16
+        | IdentifierFormat::U32
+        | IdentifierFormat::U64 => u64::from_le_bytes(buffer).to_string(),
+        IdentifierFormat::I8 => (buffer[0] as i8).to_string(),
+        IdentifierFormat::I16 => i16::from_le_bytes([buffer[0], buffer[1]]).to_string(),
+        IdentifierFormat::I32 => i32::from_le_bytes(buffer[..4].try_into().unwrap()).to_string(),
+        IdentifierFormat::I64 => i64::from_le_bytes(buffer).to_string(),
+        IdentifierFormat::String => {
+            let end = data
+                .iter()
+                .position(|byte| *byte == 0x00 || *byte == 0xFF)
+                .unwrap_or(data.len());
+            std::str::from_utf8(&data[..end])
+                .map_err(|e| anyhow!("Identifier is not valid UTF-8: {e}"))?
+                .trim()
+                .to_string()
+        }
+        IdentifierFormat::Hex => data.iter().map(|byte| format!("{byte:02x}")).collect(),
+    };
+
+    if identifier.is_empty() {
+        return Err(anyhow!("The device identifier read from memory is empty").into());
+    }
+
+    Ok(identifier)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::queries::{MemoryAddress, MemoryEndpoint, MemoryEndpointChip};
+
+    const BASE_ADDRESS: u64 = 0x1fff_7000;
+
+    fn schema(kind: &str, address: u64, length: i32) -> BlockSchema {
+        BlockSchema {
+            name: "serial".to_string(),
+            schema: kind.to_string(),
+            is_device_identifier: true,
+            active: true,
+            memory_source: Some(MemoryEndpoint {
+                address: MemoryAddress(address),
+                length,
+                chip: MemoryEndpointChip {
+                    name: "nrf52".to_string(),
+                    part_number: "nRF52840_xxAA".to_string(),
+                },
+            }),
+        }
+    }
+
+    fn dump() -> MemoryDump {
+        let mut data = vec![0xff; 64];
+        data[..8].copy_from_slice(&(-2i64).to_le_bytes());
+        data[8..12].copy_from_slice(&0xdead_beefu32.to_le_bytes());
+        data[16..26].copy_from_slice(b"SN-0042\0\0\0");
+        MemoryDump::new(BASE_ADDRESS, data)
+    }
+
+    fn read(kind: &str, offset: u64, length: i32) -> Result<String> {
+        read_schema(
+            &schema(kind, BASE_ADDRESS + offset, length),
+            None,
+            &mut dump(),
+        )
+    }
+
+    fn read_as(format: IdentifierFormat, kind: &str, offset: u64, length: i32) -> Result<String> {
+        let schema = schema(kind, BASE_ADDRESS + offset, length);
+        read_schema(&schema, Some(format), &mut dump())
+    }
+
+    #[test]
+    fn integers() {
+        assert_eq!(read("u8", 8, 1).unwrap(), "239");
+        assert_eq!(read("\"u32\"", 8, 4).unwrap(), "3735928559");
+        assert_eq!(read("i64", 0, 8).unwrap(), "-2");
+        assert!(read("u32", 8, 2).is_err());
+    }
+
+    #[test]
+    fn strings_end_at_nul_or_erased_bytes() {
+        assert_eq!(read("string", 16, 16).unwrap(), "SN-0042");
+        assert!(read("string", 26, 6).is_err());
+    }
+
+    #[test]
+    fn unknown_schemas_need_a_format() {
+        let contract = r#"{ "type": "object", "properties": { "lot": { "type": "integer" } } }"#;
+        for kind in ["uuid", contract] {
+            let Err(error) = read(kind, 8, 4) else {
+                panic!("Expected an error for {kind}");
+            };
+            assert!(error.to_string().contains("--identifier-format"), "{error}");
+        }
+
+        assert_eq!(
+            read_as(IdentifierFormat::Hex, contract, 8, 4).unwrap(),
+            "efbeadde"
+        );
+    }
+
+    #[test]
+    fn format_overrides_the_schema() {
+        assert_eq!(
+            read_as(IdentifierFormat::U16, "u32", 8, 4).unwrap(),
+            "48879"
+        );
+        assert_eq!(read_as(IdentifierFormat::I8, "string", 0, 8).unwrap(), "-2");
+        assert_eq!(read("str", 16, 16).unwrap(), "SN-0042");
+    }
+
+    #[test]
+    fn regions_outside_the_dump_are_rejected() {
+        assert!(read("u8", 64, 1).is_err());
+        assert!(read_schema(&schema("u8", BASE_ADDRESS - 1, 1), None, &mut dump()).is_err());
+    }
+
+    #[test]
+    fn lengths_must_be_positive() {
+        assert!(read("u8", 0, 0).is_err());
+        assert!(read("u8", 0, -1).is_err());
+    }
 }
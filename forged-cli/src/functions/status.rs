@@ -0,0 +1,146 @@
+use std::fmt::{self, Display};
+
+use cynic::QueryBuilder;
+use forged::runs::{RunDetails, StepStatus};
+use serde::Serialize;
+
+use crate::{
+    output::write_table,
+    queries::{Status, StatusDevice},
+    Result,
+};
+
+/// The current device and run of the provisioner.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ProvisionerStatus {
+    pub name: String,
+    pub station: String,
+    pub current_device: Option<StatusDevice>,
+    pub current_run: Option<RunDetails>,
+}
+
+/// Get the current device and run of the provisioner.
+pub async fn status(client: &mut forged::Client) -> Result<ProvisionerStatus> {
+    let provisioner = client
+        .run_query(Status::build(()))
+        .await?
+        .current_provisioner;
+    let current_run = match provisioner.current_run {
+        Some(run) => Some(client.run_details(run.id).await?),
+        None => None,
+    };
+
+    Ok(ProvisionerStatus {
+        name: provisioner.name,
+        station: provisioner.station,
+        current_device: provisioner.current_device,
+        current_run,
+    })
+}
+
+impl ProvisionerStatus {
+    /// The overall status of the current run. If there is no current run, `Pending` is returned.
+    pub fn run_status(&self) -> StepStatus {
+        self.current_run
+            .as_ref()
+            .map(|run| run.run_status)
+            .unwrap_or(StepStatus::Pending)
+    }
+}
+
+impl Display for ProvisionerStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Provisioner: {} (station {})", self.name, self.station)?;
+
+        match &self.current_device {
+            Some(device) => match &device.name {
+                Some(name) => writeln!(f, "Device:      {} ({name})", device.id)?,
+                None => writeln!(f, "Device:      {}", device.id)?,
+            },
+            None => writeln!(f, "Device:      -")?,
+        }
+
+        match &self.current_run {
+            Some(run) => write_run(f, run),
+            None => writeln!(f, "Run:         -"),
+        }
+    }
+}
+
+fn write_run(f: &mut fmt::Formatter<'_>, run: &RunDetails) -> fmt::Result {
+    writeln!(
+        f,
+        "Run:         {} on {} {}{}",
+        run.id,
+        run.station,
+        status_label(run.run_status),
+        if run.finished { " (finished)" } else { "" }
+    )?;
+
+    if !run.steps.is_empty() {
+        writeln!(f)?;
+        write_table(
+            f,
+            ["STEP", "STATION", "STATUS"],
+            run.steps.iter().map(|step| {
+                [
+                    step.step
+                        .label
+                        .clone()
+                        .unwrap_or_else(|| format!("#{}", step.step.position)),
+                    step.station.clone(),
+                    status_label(step.status).to_string(),
+                ]
+            }),
+        )?;
+    }
+
+    if !run.blocks.is_empty() {
+        writeln!(f)?;
+        write_table(
+            f,
+            ["BLOCK", "VALUE"],
+            run.blocks.iter().map(|block| {
+                let value = block
+                    .data_decoded
+                    .get("value")
+                    .or(block.data_decoded.get("values"))
+                    .unwrap_or(&block.data_decoded);
+                [block.schema.name.clone(), value.to_string()]
+            }),
+        )?;
+    }
+
+    if !run.requirement_checks.is_empty() {
+        writeln!(f)?;
+        write_table(
+            f,
+            ["REQUIREMENT", "RESULT"],
+            run.requirement_checks.iter().map(|check| {
+                [
+                    check.requirement.name.clone(),
+                    if check.passed { "✅ PASS" } else { "❌ FAIL" }.to_string(),
+                ]
+            }),
+        )?;
+    }
+
+    if !run.errors.is_empty() {
+        writeln!(f)?;
+        writeln!(f, "Errors:")?;
+        for error in &run.errors {
+            writeln!(f, "  - {error}")?;
+        }
+    }
+
+    Ok(())
+}
+
+fn status_label(status: StepStatus) -> &'static str {
+    match status {
+        StepStatus::Pending => "⏳ PENDING",
+        StepStatus::Pass => "✅ PASS",
+        StepStatus::Fail => "❌ FAIL",
+    }
+}
@@ -0,0 +1,50 @@
+use std::io::BufRead;
+
+use anyhow::anyhow;
+
+use crate::Result;
+
+/// Read a single line of input, without surrounding whitespace.
+pub fn read_line(input: impl BufRead) -> Result<String> {
+    Ok(read_line_verbatim(input)?.trim().to_string())
+}
+
+/// Read a single line of input, only without its line ending, e.g. for passwords.
+pub fn read_line_verbatim(mut input: impl BufRead) -> Result<String> {
+    let mut line = String::new();
+    input
+        .read_line(&mut line)
+        .map_err(|e| anyhow!("Failed to read input: {e}"))?;
+
+    let end = line.trim_end_matches(['\r', '\n']).len();
+    line.truncate(end);
+    Ok(line)
+}
+
+/// Prompt for a single line of input on STDERR.
+pub fn prompt(message: &str) -> Result<String> {
+    eprint!("{message}");
+    read_line(std::io::stdin().lock())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_first_line() {
+        assert_eq!(read_line(&b"  abc-123 \r\nnext\n"[..]).unwrap(), "abc-123");
+        assert_eq!(read_line(&b"last"[..]).unwrap(), "last");
+        assert_eq!(read_line(&b""[..]).unwrap(), "");
+    }
+
+    #[test]
+    fn keeps_whitespace_verbatim() {
+        assert_eq!(
+            read_line_verbatim(&b" pass word \r\nnext\n"[..]).unwrap(),
+            " pass word "
+        );
+        assert_eq!(read_line_verbatim(&b"\tsecret\n"[..]).unwrap(), "\tsecret");
+        assert_eq!(read_line_verbatim(&b"last "[..]).unwrap(), "last ");
+    }
+}
@@ -1,49 +1,293 @@
 mod cli;
+mod defmt;
 mod functions;
+mod input;
+mod memory;
+mod output;
+mod probe;
 mod queries;
 
-use std::{env, fmt::Display};
+use std::fmt::Display;
 
 use clap::StructOpt;
 use dotenv::dotenv;
+use forged::{
+    config::{Config, Overrides},
+    logs::parser::LineParser,
+    runs::StepStatus,
+};
 use functions::block::block;
 
 use crate::{
-    cli::{Cli, Command},
-    functions::{attach::attach, download::download, end::end, log::log, start::start},
+    cli::{Cli, Command, FlashOptions, LogOption, ProbeOption},
+    functions::{
+        attach::attach,
+        attachments::attachments,
+        binary::binary,
+        config::config,
+        device::device,
+        download::{download, download_local, Downloaded, FlashMode, Gang, LocalFiles, Release},
+        end::end,
+        log::{install_parser, log},
+        login::{login, logout, user_client, whoami},
+        provisioner::provisioner,
+        report::report,
+        rtt::{rtt, RttTarget},
+        run::run,
+        start::{start, IdentifierSource},
+        status::status,
+    },
+    output::OutputFormat,
 };
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
+    let output = cli.output;
+    output.install();
 
     dotenv().ok();
-    let token = cli
-        .api_token
-        .or_else(|| env::var("FORGED_TOKEN").ok())
-        .expect("FORGED_TOKEN is not set");
-
-    let mut client = forged::Client::new(token);
-
-    if let Some(endpoint) = cli
-        .api_instance_url
-        .or_else(|| env::var("FORGED_INSTANCE_URL").ok())
-    {
-        client = client.api(endpoint);
+    let overrides = Overrides {
+        profile: cli.profile,
+        token: cli.api_token,
+        api_url: cli.api_instance_url,
+    };
+
+    if let Command::Config(option) = cli.command {
+        output.emit(&config(&overrides, option)?)?;
+        return Ok(());
+    }
+
+    let settings = Config::load(&overrides)?;
+    // Only commands that parse lines fail on invalid log settings.
+    if parses_lines(&cli.command) {
+        let mut log_settings = settings.log.clone();
+        if let Some(preset) = cli.log_preset {
+            log_settings.preset = Some(preset);
+        }
+        install_parser(LineParser::from_settings(&log_settings)?);
     }
 
     match cli.command {
-        Command::Start => start(&mut client).await?,
-        Command::Download { chip, version } => download(&mut client, chip, version).await?,
-        Command::Log(option) => log(&mut client, option).await?,
-        Command::Attach { file_path } => attach(&mut client, file_path).await?,
-        Command::Block { data, schema_name } => block(&mut client, schema_name, data).await?,
-        Command::End => end(&mut client).await?,
+        Command::Login {
+            email,
+            password_stdin,
+            oauth,
+        } => {
+            let client = forged::Client::from_config(&settings);
+            return output.emit(&login(&client, email, password_stdin, oauth).await?);
+        }
+        Command::Logout => return output.emit(&logout()?),
+        Command::Whoami => return output.emit(&whoami(&user_client()?).await?),
+        Command::Provisioner(option) => {
+            return output.emit(&provisioner(&user_client()?, option).await?)
+        }
+        Command::Download {
+            file,
+            chip_part: Some(part_number),
+            record: false,
+            probe,
+            all_probes,
+            probe_serials,
+            no_runs,
+            flash,
+            rtt: false,
+            ..
+        } if no_runs || !ganged(all_probes, &probe_serials) => {
+            let local = LocalFiles {
+                part_number,
+                files: file,
+                record: false,
+            };
+            let gang = gang(all_probes, probe_serials, false);
+            let mode = FlashMode::resolve(&flash, &settings.flash)?;
+            let downloaded = download_local(None, local, &probe, mode, gang, None).await?;
+            return emit_download(output, &downloaded);
+        }
+        Command::Probe(ProbeOption::List) => return output.emit(&probe::list()),
+        _ => {}
+    }
+
+    if let Err(forged::Error::Api(error)) = settings.token() {
+        return Err(error);
+    }
+    let mut client = forged::Client::from_config(&settings);
+
+    match cli.command {
+        Command::Start {
+            identifier,
+            identifier_from_stdin,
+            identifier_from_device,
+            identifier_format,
+            memory_dump,
+            memory_dump_address,
+            station,
+            probe,
+        } => {
+            let source = if identifier_from_stdin {
+                IdentifierSource::Stdin
+            } else if identifier_from_device {
+                IdentifierSource::Device {
+                    format: identifier_format,
+                    memory_dump: memory_dump.map(|path| (path, memory_dump_address)),
+                    probe,
+                }
+            } else {
+                IdentifierSource::Argument(identifier)
+            };
+            output.emit(&start(&mut client, source, station).await?)?
+        }
+        Command::Download {
+            file,
+            chip_part: Some(part_number),
+            record,
+            probe,
+            all_probes,
+            probe_serials,
+            no_runs,
+            flash,
+            rtt,
+            rtt_options,
+            ..
+        } => {
+            let local = LocalFiles {
+                part_number,
+                files: file,
+                record,
+            };
+            let gang = gang(all_probes, probe_serials, !no_runs);
+            let mode = flash_mode(&flash, &settings, rtt)?;
+            let rtt = rtt.then_some(&rtt_options);
+            let downloaded = download_local(Some(&client), local, &probe, mode, gang, rtt).await?;
+            emit_download(output, &downloaded)?
+        }
+        Command::Download {
+            chip,
+            version,
+            server_newest,
+            probe,
+            all_probes,
+            probe_serials,
+            no_runs,
+            flash,
+            rtt,
+            rtt_options,
+            ..
+        } => {
+            let release = Release {
+                chip,
+                version,
+                server_newest,
+            };
+            let gang = gang(all_probes, probe_serials, !no_runs);
+            let mode = flash_mode(&flash, &settings, rtt)?;
+            let rtt = rtt.then_some(&rtt_options);
+            let downloaded = download(&mut client, release, &probe, mode, gang, rtt).await?;
+            emit_download(output, &downloaded)?
+        }
+        Command::Rtt {
+            chip,
+            version,
+            chip_part,
+            address,
+            rtt_options,
+            probe,
+        } => {
+            let target = match chip_part {
+                Some(part_number) => RttTarget::PartNumber(part_number),
+                None => RttTarget::Release(Release {
+                    chip,
+                    version,
+                    server_newest: false,
+                }),
+            };
+            output.emit(&rtt(&mut client, target, address, &rtt_options, &probe).await?)?
+        }
+        Command::Log(option) => output.emit(&log(&mut client, option).await?)?,
+        Command::Attach {
+            paths,
+            name,
+            zip,
+            max_size,
+        } => output.emit(&attach(&mut client, &paths, name, zip, max_size).await?)?,
+        Command::Block { data, schema_name } => {
+            output.emit(&block(&mut client, schema_name, data).await?)?
+        }
+        Command::Binary(option) => output.emit(&binary(&mut client, option).await?)?,
+        Command::Device(option) => output.emit(&device(&client, option).await?)?,
+        Command::Attachments(option) => output.emit(&attachments(&client, option).await?)?,
+        Command::Run(option) => output.emit(&run(&client, option).await?)?,
+        Command::End {
+            report,
+            report_format,
+        } => {
+            let report = report.map(|path| (report_format, path));
+            output.emit(&end(&mut client, report).await?)?
+        }
+        Command::Report {
+            run,
+            format,
+            out_file,
+        } => output.emit(&report(&client, run.run_ref(), format, out_file).await?)?,
+        Command::Status => {
+            let status = status(&mut client).await?;
+            output.emit(&status)?;
+            if status.run_status() == StepStatus::Fail {
+                std::process::exit(1);
+            }
+        }
+        Command::Config(_)
+        | Command::Login { .. }
+        | Command::Logout
+        | Command::Whoami
+        | Command::Provisioner(_)
+        | Command::Probe(_) => unreachable!(),
     }
 
     Ok(())
 }
 
+/// Check if the targets of several probes are flashed at once.
+fn ganged(all_probes: bool, serials: &[String]) -> bool {
+    all_probes || !serials.is_empty()
+}
+
+/// Flash the targets of several probes at once if `--all-probes` or `--probe-serials` is given.
+fn gang(all_probes: bool, serials: Vec<String>, run_per_device: bool) -> Option<Gang> {
+    ganged(all_probes, &serials).then_some(Gang {
+        serials,
+        run_per_device,
+    })
+}
+
+/// Resolve how to flash the target. Capturing RTT output requires the target to run afterwards.
+fn flash_mode(flash: &FlashOptions, settings: &Config, rtt: bool) -> Result<FlashMode> {
+    let mode = FlashMode::resolve(flash, &settings.flash)?;
+    if rtt {
+        return mode.capture_rtt();
+    }
+    Ok(mode)
+}
+
+/// Check if a command parses log entries from lines of text.
+fn parses_lines(command: &Command) -> bool {
+    matches!(
+        command,
+        Command::Log(LogOption::Stream { .. } | LogOption::Serial { .. })
+            | Command::Rtt { .. }
+            | Command::Download { rtt: true, .. }
+    )
+}
+
+/// Print the result of a download and exit with a non-zero code if any target failed.
+fn emit_download(output: OutputFormat, downloaded: &Downloaded) -> anyhow::Result<()> {
+    output.emit(downloaded)?;
+    if downloaded.failed() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(thiserror::Error, Debug)]
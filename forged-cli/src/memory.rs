@@ -0,0 +1,153 @@
+use anyhow::anyhow;
+use probe_rs::MemoryInterface;
+
+use crate::Result;
+
+/// A source of target memory that device data can be read back from.
+pub trait MemoryReader {
+    /// Read `data.len()` bytes of memory starting at `address`.
+    fn read(&mut self, address: u64, data: &mut [u8]) -> Result<()>;
+}
+
+impl MemoryReader for probe_rs::Core<'_> {
+    fn read(&mut self, address: u64, data: &mut [u8]) -> Result<()> {
+        MemoryInterface::read(self, address, data)?;
+        Ok(())
+    }
+}
+
+/// A raw memory image of the target that was previously dumped to a file.
+pub struct MemoryDump {
+    base_address: u64,
+    data: Vec<u8>,
+}
+
+impl MemoryDump {
+    /// Create a memory dump from raw image data.
+    ///
+    /// # Args
+    /// * `base_address` - The target address of the first byte of `data`.
+    /// * `data` - The contents of the dumped memory.
+    pub fn new(base_address: u64, data: Vec<u8>) -> Self {
+        Self { base_address, data }
+    }
+
+    /// Load a memory dump from a raw binary file.
+    ///
+    /// # Args
+    /// * `path` - The path of the dump file.
+    /// * `base_address` - The target address of the first byte in the file.
+    pub fn load(path: &str, base_address: u64) -> Result<Self> {
+        let data =
+            std::fs::read(path).map_err(|e| anyhow!("Failed to read memory dump {path}: {e}"))?;
+        Ok(Self::new(base_address, data))
+    }
+}
+
+impl MemoryReader for MemoryDump {
+    fn read(&mut self, address: u64, data: &mut [u8]) -> Result<()> {
+        let length = data.len();
+        let out_of_range = || {
+            anyhow!(
+                "Memory region {address:#010x}..{:#010x} is not contained in the dump",
+                address + length as u64
+            )
+        };
+
+        let start = address
+            .checked_sub(self.base_address)
+            .ok_or_else(out_of_range)? as usize;
+        let end = start + length;
+        let source = self.data.get(start..end).ok_or_else(out_of_range)?;
+        data.copy_from_slice(source);
+
+        Ok(())
+    }
+}
+
+/// Compare target memory to the expected contents.
+///
+/// # Args
+/// * `memory` - The memory to read.
+/// * `address` - The target address of the first expected byte.
+/// * `expected` - The expected memory contents.
+///
+/// # Returns
+/// The address of the first differing byte, or `None` if the memory matches.
+pub fn find_mismatch(
+    memory: &mut impl MemoryReader,
+    address: u64,
+    expected: &[u8],
+) -> Result<Option<u64>> {
+    const CHUNK_SIZE: usize = 4096;
+
+    let mut actual = vec![0; CHUNK_SIZE];
+    for (index, chunk) in expected.chunks(CHUNK_SIZE).enumerate() {
+        let chunk_address = address + (index * CHUNK_SIZE) as u64;
+        let actual = &mut actual[..chunk.len()];
+        memory.read(chunk_address, actual)?;
+
+        if let Some(offset) = actual.iter().zip(chunk).position(|(a, b)| a != b) {
+            return Ok(Some(chunk_address + offset as u64));
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: u64 = 0x0800_0000;
+
+    /// A dump of `length` bytes that spans several chunks of [`find_mismatch`].
+    fn image(length: usize) -> Vec<u8> {
+        (0..length).map(|index| (index % 251) as u8).collect()
+    }
+
+    fn mismatch(dump: Vec<u8>, expected: &[u8]) -> Option<u64> {
+        let result = find_mismatch(&mut MemoryDump::new(BASE, dump), BASE, expected);
+        result.map_err(|e| e.to_string()).unwrap()
+    }
+
+    #[test]
+    fn matching_memory() {
+        let expected = image(10_000);
+        assert_eq!(mismatch(expected.clone(), &expected), None);
+        assert_eq!(mismatch(Vec::new(), &[]), None);
+    }
+
+    #[test]
+    fn first_mismatch_is_reported() {
+        let expected = image(10_000);
+        for offset in [0, 4095, 4096, 5000, 9_999] {
+            let mut dump = expected.clone();
+            dump[offset] ^= 0xff;
+            dump[9_999] ^= 0x0f;
+            assert_eq!(
+                mismatch(dump, &expected),
+                Some(BASE + offset as u64),
+                "{offset}"
+            );
+        }
+    }
+
+    #[test]
+    fn mismatch_relative_to_the_start_address() {
+        let mut dump = image(64);
+        dump[40] = !dump[40];
+        let expected = &image(64)[32..];
+
+        let result = find_mismatch(&mut MemoryDump::new(BASE, dump), BASE + 32, expected);
+        assert_eq!(result.map_err(|e| e.to_string()).unwrap(), Some(BASE + 40));
+    }
+
+    #[test]
+    fn memory_outside_the_dump_is_an_error() {
+        let mut dump = MemoryDump::new(BASE, image(16));
+
+        assert!(find_mismatch(&mut dump, BASE, &image(17)).is_err());
+        assert!(find_mismatch(&mut dump, BASE - 1, &image(4)).is_err());
+    }
+}
@@ -0,0 +1,167 @@
+use std::{
+    fmt::{self, Display},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use clap::ArgEnum;
+use serde::Serialize;
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// The format in which command results are printed to STDOUT.
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human readable results.
+    Text,
+    /// Machine readable JSON results.
+    Json,
+    /// No results and no progress messages. Errors are still reported.
+    Quiet,
+}
+
+impl OutputFormat {
+    /// Apply the format to progress messages and progress bars of the whole process.
+    pub fn install(self) {
+        QUIET.store(self == OutputFormat::Quiet, Ordering::Relaxed);
+    }
+
+    /// Print the result of a command to STDOUT.
+    pub fn emit<T: Serialize + Display>(self, result: &T) -> anyhow::Result<()> {
+        match self {
+            OutputFormat::Text => {
+                let text = result.to_string();
+                if !text.is_empty() {
+                    println!("{}", text.trim_end());
+                }
+            }
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(result)?),
+            OutputFormat::Quiet => {}
+        }
+
+        Ok(())
+    }
+}
+
+/// Check if progress messages and progress bars should be suppressed.
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// Print a human readable progress message to STDERR unless the output is quiet.
+///
+/// STDOUT is reserved for command results so that it can be consumed by scripts.
+#[macro_export]
+macro_rules! progress {
+    ($($arg:tt)*) => {
+        if !$crate::output::is_quiet() {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+/// Write rows of cells as a table with left-aligned columns.
+pub fn write_table<const N: usize>(
+    f: &mut fmt::Formatter<'_>,
+    header: [&str; N],
+    rows: impl Iterator<Item = [String; N]>,
+) -> fmt::Result {
+    let rows: Vec<[String; N]> = rows.collect();
+
+    let mut widths = header.map(|cell| cell.chars().count());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut write_row = |cells: &[&str]| {
+        let line = cells
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+        writeln!(f, "{}", line.trim_end())
+    };
+
+    write_row(&header)?;
+    for row in &rows {
+        write_row(&row.each_ref().map(String::as_str))?;
+    }
+
+    Ok(())
+}
+
+/// Write rows of cells as CSV, quoting cells that contain separators, quotes or line breaks.
+pub fn write_csv<const N: usize>(
+    f: &mut fmt::Formatter<'_>,
+    header: [&str; N],
+    rows: impl Iterator<Item = [String; N]>,
+) -> fmt::Result {
+    let mut write_row = |cells: &[&str]| {
+        let line = cells
+            .iter()
+            .map(|cell| {
+                if cell.contains([',', '"', '\n', '\r']) {
+                    format!("\"{}\"", cell.replace('"', "\"\""))
+                } else {
+                    cell.to_string()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        writeln!(f, "{line}")
+    };
+
+    write_row(&header)?;
+    for row in rows {
+        write_row(&row.each_ref().map(String::as_str))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Rows of an `ID` and a `NAME` column, displayed as CSV or as a table.
+    struct Rows(Vec<[&'static str; 2]>, bool);
+
+    impl Display for Rows {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let rows = self.0.iter().map(|row| row.map(str::to_string));
+            if self.1 {
+                write_csv(f, ["ID", "NAME"], rows)
+            } else {
+                write_table(f, ["ID", "NAME"], rows)
+            }
+        }
+    }
+
+    #[test]
+    fn csv_quotes_only_when_needed() {
+        let rows = vec![
+            ["1", "plain"],
+            ["2", "a, b"],
+            ["3", "say \"hi\""],
+            ["4", "two\nlines"],
+            ["5", "carriage\rreturn"],
+            ["6", ""],
+        ];
+        assert_eq!(
+            Rows(rows, true).to_string(),
+            "ID,NAME\n1,plain\n2,\"a, b\"\n3,\"say \"\"hi\"\"\"\n4,\"two\nlines\"\n\
+             5,\"carriage\rreturn\"\n6,\n"
+        );
+    }
+
+    #[test]
+    fn table_aligns_columns() {
+        let rows = vec![["1", "a longer name"], ["22", "b"]];
+        assert_eq!(
+            Rows(rows, false).to_string(),
+            "ID  NAME\n1   a longer name\n22  b\n"
+        );
+    }
+}
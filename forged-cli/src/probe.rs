@@ -0,0 +1,299 @@
+use std::fmt::Display;
+
+use anyhow::anyhow;
+use probe_rs::{DebugProbeInfo, DebugProbeSelector};
+use serde::Serialize;
+
+use crate::{cli::ProbeOptions, output::write_table, Result};
+
+/// A connected debug probe.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProbeListing {
+    pub identifier: String,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub serial_number: Option<String>,
+    /// The `--probe` argument that selects this probe.
+    pub selector: String,
+}
+
+/// The connected debug probes.
+#[derive(Serialize)]
+pub struct Probes(pub Vec<ProbeListing>);
+
+impl Display for Probes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write_table(
+            f,
+            ["PROBE", "SELECTOR"],
+            self.0
+                .iter()
+                .map(|probe| [probe.identifier.clone(), probe.selector.clone()]),
+        )
+    }
+}
+
+/// List the connected debug probes.
+pub fn list() -> Probes {
+    Probes(
+        probe_rs::Lister::new()
+            .list_all()
+            .iter()
+            .map(|probe| ProbeListing {
+                identifier: probe.identifier.clone(),
+                vendor_id: probe.vendor_id,
+                product_id: probe.product_id,
+                serial_number: probe.serial_number.clone(),
+                selector: DebugProbeSelector::from(probe).to_string(),
+            })
+            .collect(),
+    )
+}
+
+/// Select the debug probe to use from the connected probes.
+///
+/// # Args
+/// * `probes` - The connected probes.
+/// * `selector` - The `VID:PID[:SERIAL]` of the probe to use. May be omitted if exactly one probe
+///   is connected.
+///
+/// # Returns
+/// The selected probe, or an error if no probe or more than one probe matches.
+pub fn select<'a>(
+    probes: &'a [DebugProbeInfo],
+    selector: Option<&DebugProbeSelector>,
+) -> Result<&'a DebugProbeInfo> {
+    let matches = |probe: &&DebugProbeInfo| match selector {
+        Some(selector) => {
+            probe.vendor_id == selector.vendor_id
+                && probe.product_id == selector.product_id
+                && (selector.serial_number.is_none()
+                    || probe.serial_number == selector.serial_number)
+        }
+        None => true,
+    };
+    let describe = |probes: &mut dyn Iterator<Item = &DebugProbeInfo>| {
+        probes
+            .map(|probe| format!("{} ({})", probe.identifier, DebugProbeSelector::from(probe)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    let mut candidates = probes.iter().filter(matches);
+    match (candidates.next(), candidates.next()) {
+        (Some(probe), None) => Ok(probe),
+        (None, _) if probes.is_empty() => Err(anyhow!("No probe found").into()),
+        (None, _) => Err(anyhow!(
+            "No probe matches `{}`. Connected probes: [ {} ]",
+            selector.map(ToString::to_string).unwrap_or_default(),
+            describe(&mut probes.iter())
+        )
+        .into()),
+        (Some(_), Some(_)) => Err(anyhow!(
+            "Multiple probes found. Please select one with `--probe VID:PID:SERIAL`. \
+            Matching probes: [ {} ]",
+            describe(&mut probes.iter().filter(matches))
+        )
+        .into()),
+    }
+}
+
+/// Select the debug probes to flash at once from the connected probes.
+///
+/// # Args
+/// * `probes` - The connected probes.
+/// * `serials` - The serial numbers of the probes to use. If empty, all probes are used. Repeated
+///   serial numbers select their probe once.
+///
+/// # Returns
+/// The selected probes, or an error if no probe is connected or a serial number does not match
+/// exactly one probe.
+pub fn select_all<'a>(
+    probes: &'a [DebugProbeInfo],
+    serials: &[String],
+) -> Result<Vec<&'a DebugProbeInfo>> {
+    if serials.is_empty() {
+        if probes.is_empty() {
+            return Err(anyhow!("No probe found").into());
+        }
+        return Ok(probes.iter().collect());
+    }
+
+    // A probe can only be flashed by one session at a time.
+    let mut unique = Vec::with_capacity(serials.len());
+    for serial in serials {
+        if !unique.contains(&serial) {
+            unique.push(serial);
+        }
+    }
+
+    unique
+        .into_iter()
+        .map(|serial| {
+            let mut candidates = probes
+                .iter()
+                .filter(|probe| probe.serial_number.as_ref() == Some(serial));
+            match (candidates.next(), candidates.next()) {
+                (Some(probe), None) => Ok(probe),
+                (None, _) => Err(anyhow!("No probe with serial number `{serial}` found").into()),
+                (Some(_), Some(_)) => {
+                    Err(anyhow!("Multiple probes have the serial number `{serial}`").into())
+                }
+            }
+        })
+        .collect()
+}
+
+/// Open the selected debug probe and attach to the target chip.
+///
+/// # Args
+/// * `part_number` - The part number of the chip to attach to, as known by probe-rs.
+/// * `options` - Which probe to use and how to connect to the target.
+///
+/// # Returns
+/// A session with the attached target.
+pub fn attach(part_number: &str, options: &ProbeOptions) -> Result<probe_rs::Session> {
+    let probes = probe_rs::Lister::new().list_all();
+    attach_probe(
+        select(&probes, options.probe.as_ref())?,
+        part_number,
+        options,
+    )
+}
+
+/// Open a debug probe and attach to the target chip.
+///
+/// # Args
+/// * `probe` - The probe to open.
+/// * `part_number` - The part number of the chip to attach to, as known by probe-rs.
+/// * `options` - How to connect to the target. The probe selector is ignored.
+///
+/// # Returns
+/// A session with the attached target.
+pub fn attach_probe(
+    probe: &DebugProbeInfo,
+    part_number: &str,
+    options: &ProbeOptions,
+) -> Result<probe_rs::Session> {
+    let mut probe = probe
+        .open(&probe_rs::Lister::new())
+        .map_err(probe_rs::Error::Probe)?;
+
+    if let Some(protocol) = options.protocol {
+        probe
+            .select_protocol(protocol)
+            .map_err(probe_rs::Error::Probe)?;
+    }
+    if let Some(speed) = options.speed {
+        probe.set_speed(speed).map_err(probe_rs::Error::Probe)?;
+    }
+    {
+        let protocol_speed = probe.speed_khz();
+
+        log::info!("Protocol speed {} kHz", protocol_speed);
+    }
+
+    if options.connect_under_reset {
+        Ok(probe.attach_under_reset(part_number, probe_rs::Permissions::default())?)
+    } else {
+        Ok(probe.attach(part_number, probe_rs::Permissions::default())?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use probe_rs::DebugProbeType;
+
+    use super::*;
+
+    fn probe(vendor_id: u16, product_id: u16, serial: &str) -> DebugProbeInfo {
+        DebugProbeInfo::new(
+            format!("Probe {serial}"),
+            vendor_id,
+            product_id,
+            Some(serial.to_string()),
+            DebugProbeType::CmsisDap,
+            None,
+        )
+    }
+
+    fn selector(selector: &str) -> DebugProbeSelector {
+        DebugProbeSelector::try_from(selector).unwrap()
+    }
+
+    fn probes() -> Vec<DebugProbeInfo> {
+        vec![
+            probe(0x0483, 0x374b, "A1"),
+            probe(0x1366, 0x1015, "B1"),
+            probe(0x1366, 0x1015, "B2"),
+        ]
+    }
+
+    #[test]
+    fn select_without_probes() {
+        assert!(select(&[], None).is_err());
+        assert!(select(&[], Some(&selector("1366:1015"))).is_err());
+    }
+
+    #[test]
+    fn select_the_only_probe() {
+        let probes = [probe(0x0483, 0x374b, "A1")];
+        assert_eq!(select(&probes, None).unwrap().identifier, "Probe A1");
+    }
+
+    #[test]
+    fn select_needs_a_selector_for_several_probes() {
+        assert!(select(&probes(), None).is_err());
+    }
+
+    #[test]
+    fn select_by_vid_pid() {
+        let probes = probes();
+        let selected = select(&probes, Some(&selector("0483:374b"))).unwrap();
+        assert_eq!(selected.identifier, "Probe A1");
+        // The VID:PID of several probes is ambiguous.
+        assert!(select(&probes, Some(&selector("1366:1015"))).is_err());
+        assert!(select(&probes, Some(&selector("1234:5678"))).is_err());
+    }
+
+    #[test]
+    fn select_by_vid_pid_serial() {
+        let probes = probes();
+        let selected = select(&probes, Some(&selector("1366:1015:B2"))).unwrap();
+        assert_eq!(selected.identifier, "Probe B2");
+        assert!(select(&probes, Some(&selector("1366:1015:B3"))).is_err());
+    }
+
+    #[test]
+    fn select_all_probes() {
+        let probes = probes();
+        assert_eq!(select_all(&probes, &[]).unwrap().len(), 3);
+        assert!(select_all(&[], &[]).is_err());
+
+        let serials = ["B2".to_string(), "A1".to_string()];
+        let selected: Vec<_> = select_all(&probes, &serials)
+            .unwrap()
+            .into_iter()
+            .map(|probe| probe.identifier.as_str())
+            .collect();
+        assert_eq!(selected, ["Probe B2", "Probe A1"]);
+    }
+
+    #[test]
+    fn select_all_with_an_unknown_serial() {
+        let serials = ["A1".to_string(), "C1".to_string()];
+        assert!(select_all(&probes(), &serials).is_err());
+    }
+
+    #[test]
+    fn select_all_with_repeated_serials() {
+        let serials = ["B1", "A1", "B1", "A1"].map(str::to_string);
+        let selected: Vec<_> = select_all(&probes(), &serials)
+            .unwrap()
+            .into_iter()
+            .map(|probe| probe.identifier.clone())
+            .collect();
+        assert_eq!(selected, ["Probe B1", "Probe A1"]);
+    }
+}
@@ -5,8 +5,9 @@ pub use queries::*;
 
 #[cynic::schema_for_derives(file = "schema.graphql", module = "schema")]
 pub mod queries {
-    use super::schema;
+    use super::{schema, DateTime, MemoryAddress};
     use forged::cynic;
+    use serde::Serialize;
     use uuid::Uuid;
 
     cynic::impl_scalar!(serde_json::Value, schema::JSON);
@@ -22,6 +23,219 @@ pub mod queries {
         pub id: Uuid,
     }
 
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "MutationRoot", variables = "CreateRunForArguments")]
+    pub struct CreateRunFor {
+        #[arguments(identifier: $identifier, station: $station)]
+        pub run_create_for: Run,
+    }
+
+    #[derive(cynic::QueryVariables, Debug)]
+    pub struct CreateRunForArguments {
+        pub identifier: String,
+        pub station: String,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    pub struct Run {
+        pub id: Uuid,
+        pub device_id: Uuid,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "QueryRoot")]
+    pub struct BlockSchemas {
+        pub current_provisioner: ProvisionerProjectBlockSchemas,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "Provisioner")]
+    pub struct ProvisionerProjectBlockSchemas {
+        pub project: ProjectBlockSchemas,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "Project")]
+    pub struct ProjectBlockSchemas {
+        pub block_schemas: Vec<BlockSchema>,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    pub struct BlockSchema {
+        pub name: String,
+        pub schema: String,
+        pub is_device_identifier: bool,
+        pub active: bool,
+        pub memory_source: Option<MemoryEndpoint>,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    pub struct MemoryEndpoint {
+        pub address: MemoryAddress,
+        pub length: i32,
+        pub chip: MemoryEndpointChip,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "Chip")]
+    pub struct MemoryEndpointChip {
+        pub name: String,
+        pub part_number: String,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "QueryRoot")]
+    pub struct Status {
+        pub current_provisioner: StatusProvisioner,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "Provisioner")]
+    pub struct StatusProvisioner {
+        pub name: String,
+        pub station: String,
+        pub current_device: Option<StatusDevice>,
+        pub current_run: Option<StatusRun>,
+    }
+
+    #[derive(cynic::QueryFragment, Serialize, Debug)]
+    #[cynic(graphql_type = "Device")]
+    pub struct StatusDevice {
+        pub id: Uuid,
+        pub name: Option<String>,
+    }
+
+    /// Only the ID of the current run, its details are fetched with [`forged::Client::run_details`].
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "Run")]
+    pub struct StatusRun {
+        pub id: Uuid,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "QueryRoot")]
+    pub struct Projects {
+        pub projects: Vec<ProjectSummary>,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "Project")]
+    pub struct ProjectSummary {
+        pub id: Uuid,
+        pub name: String,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "QueryRoot", variables = "ProjectArguments")]
+    pub struct ProjectProvisioners {
+        #[arguments(id: $id)]
+        pub project: ProjectProvisionerList,
+    }
+
+    #[derive(cynic::QueryVariables, Debug)]
+    pub struct ProjectArguments {
+        pub id: Uuid,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "Project")]
+    pub struct ProjectProvisionerList {
+        pub provisioners: Vec<ProvisionerDetails>,
+    }
+
+    #[derive(cynic::QueryFragment, Serialize, Debug)]
+    #[cynic(graphql_type = "Provisioner")]
+    #[serde(rename_all = "camelCase")]
+    pub struct ProvisionerDetails {
+        pub id: Uuid,
+        pub project_id: Uuid,
+        pub name: String,
+        pub station: String,
+        pub active: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub token: Option<String>,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(
+        graphql_type = "MutationRoot",
+        variables = "CreateProvisionerArguments"
+    )]
+    pub struct CreateProvisioner {
+        #[arguments(name: $name, station: $station, projectId: $project_id)]
+        pub provisioner_create: ProvisionerDetails,
+    }
+
+    #[derive(cynic::QueryVariables, Debug)]
+    pub struct CreateProvisionerArguments {
+        pub name: String,
+        pub station: String,
+        pub project_id: Uuid,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(
+        graphql_type = "MutationRoot",
+        variables = "RenameProvisionerArguments"
+    )]
+    pub struct RenameProvisioner {
+        #[arguments(id: $id, name: $name)]
+        pub provisioner_rename: ProvisionerDetails,
+    }
+
+    #[derive(cynic::QueryVariables, Debug)]
+    pub struct RenameProvisionerArguments {
+        pub id: Uuid,
+        pub name: String,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(
+        graphql_type = "MutationRoot",
+        variables = "AssignProvisionerStationArguments"
+    )]
+    pub struct AssignProvisionerStation {
+        #[arguments(id: $id, station: $station)]
+        pub provisioner_assign_station: ProvisionerDetails,
+    }
+
+    #[derive(cynic::QueryVariables, Debug)]
+    pub struct AssignProvisionerStationArguments {
+        pub id: Uuid,
+        pub station: String,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "MutationRoot", variables = "ProvisionerArguments")]
+    pub struct RegenerateProvisionerToken {
+        #[arguments(id: $id)]
+        pub provisioner_regenerate_token: ProvisionerDetails,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "MutationRoot", variables = "ProvisionerArguments")]
+    pub struct DeleteProvisioner {
+        #[arguments(id: $id)]
+        pub provisioner_delete: Uuid,
+    }
+
+    #[derive(cynic::QueryVariables, Debug)]
+    pub struct ProvisionerArguments {
+        pub id: Uuid,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "QueryRoot")]
+    pub struct CurrentDevice {
+        pub current_provisioner: CurrentDeviceProvisioner,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "Provisioner")]
+    pub struct CurrentDeviceProvisioner {
+        pub current_device: Option<StatusDevice>,
+    }
+
     #[derive(cynic::QueryFragment, Debug)]
     #[cynic(graphql_type = "MutationRoot")]
     pub struct FinishRun {
@@ -46,12 +260,13 @@ pub mod queries {
         pub id: Uuid,
     }
 
-    #[derive(cynic::QueryFragment, Debug)]
+    #[derive(cynic::QueryFragment, Clone, Debug)]
     #[cynic(graphql_type = "Chip")]
     pub struct Chip {
         pub id: Uuid,
         pub name: String,
         pub part_number: String,
+        pub binary_newest: Option<BinaryId>,
         pub binaries: Vec<Binary>,
     }
 
@@ -73,52 +288,128 @@ pub mod queries {
         pub project: ProjectChips,
     }
 
-    #[derive(cynic::QueryFragment, Debug)]
+    #[derive(cynic::QueryFragment, Serialize, Clone, Debug)]
+    #[serde(rename_all = "camelCase")]
     pub struct Binary {
         pub id: Uuid,
+        pub version: String,
+        #[serde(skip)]
         pub version_major: i32,
+        #[serde(skip)]
         pub version_minor: i32,
+        #[serde(skip)]
         pub version_patch: i32,
+        pub creation_date: DateTime,
+        pub deletion_date: Option<DateTime>,
+        pub active: bool,
+        pub analysis: Option<BinaryAnalysis>,
         pub parts: Vec<BinaryPart>,
     }
 
     impl Binary {
+        /// The version of the binary including pre-release and build metadata.
         pub fn version(&self) -> semver::Version {
-            semver::Version {
+            semver::Version::parse(&self.version).unwrap_or_else(|_| semver::Version {
                 major: self.version_major as u64,
                 minor: self.version_minor as u64,
                 patch: self.version_patch as u64,
                 pre: Default::default(),
                 build: Default::default(),
-            }
+            })
+        }
+
+        /// Check if the binary can be flashed, i.e. it is active and not deleted.
+        pub fn is_available(&self) -> bool {
+            self.active && self.deletion_date.is_none()
         }
     }
 
     #[derive(cynic::QueryFragment, Clone, Debug)]
+    #[cynic(graphql_type = "Binary")]
+    pub struct BinaryId {
+        pub id: Uuid,
+    }
+
+    #[derive(cynic::QueryFragment, Serialize, Clone, Debug)]
+    #[serde(rename_all = "camelCase")]
     pub struct BinaryPart {
         pub id: Uuid,
+        pub name: Option<String>,
         pub kind: BinaryKind,
         pub memory_offset: Option<i32>,
+        #[serde(serialize_with = "serialize_hash")]
+        pub image_hash: Vec<i32>,
         pub analysis: Option<BinaryPartAnalysis>,
     }
 
-    #[derive(cynic::QueryFragment, Debug, Clone)]
+    impl BinaryPart {
+        /// The SHA-256 hash of the part image as a hex string.
+        pub fn hash(&self) -> String {
+            hex_hash(&self.image_hash)
+        }
+    }
+
+    fn hex_hash(hash: &[i32]) -> String {
+        hash.iter()
+            .map(|byte| format!("{:02x}", *byte as u8))
+            .collect()
+    }
+
+    fn serialize_hash<S: serde::Serializer>(
+        hash: &[i32],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex_hash(hash))
+    }
+
+    #[derive(cynic::QueryFragment, Serialize, Debug, Clone)]
+    #[serde(rename_all = "camelCase")]
     pub struct BinaryAnalysis {
         pub nvm_size: i32,
     }
 
-    #[derive(cynic::QueryFragment, Debug, Clone)]
+    #[derive(cynic::QueryFragment, Serialize, Debug, Clone)]
+    #[serde(rename_all = "camelCase")]
     pub struct BinaryPartAnalysis {
         pub nvm_size: i32,
+        pub rtt: RttAnalysis,
+    }
+
+    #[derive(cynic::QueryFragment, Serialize, Debug, Clone)]
+    #[serde(rename_all = "camelCase")]
+    pub struct RttAnalysis {
+        pub header_location: Option<i32>,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "MutationRoot", variables = "DeleteBinaryArguments")]
+    pub struct DeleteBinary {
+        #[arguments(id: $id)]
+        pub binary_delete: Uuid,
+    }
+
+    #[derive(cynic::QueryVariables, Debug)]
+    pub struct DeleteBinaryArguments {
+        pub id: Uuid,
     }
 
-    #[derive(cynic::Enum, Debug, Clone)]
+    #[derive(cynic::Enum, Debug, Clone, PartialEq, Eq)]
     pub enum BinaryKind {
         Elf,
         Bin,
         Hex,
     }
 
+    impl From<&BinaryKind> for forged::BinaryKind {
+        fn from(kind: &BinaryKind) -> Self {
+            match kind {
+                BinaryKind::Elf => forged::BinaryKind::Elf,
+                BinaryKind::Bin => forged::BinaryKind::Bin,
+                BinaryKind::Hex => forged::BinaryKind::Hex,
+            }
+        }
+    }
+
     #[derive(cynic::QueryFragment, Debug)]
     #[cynic(graphql_type = "MutationRoot", variables = "CreateAttachmentArguments")]
     pub struct CreateAttachment {
@@ -134,6 +425,8 @@ pub mod queries {
     #[derive(cynic::QueryFragment, Debug)]
     pub struct Attachment {
         pub id: Uuid,
+        pub name: String,
+        pub mime_type: String,
     }
 
     #[derive(cynic::QueryFragment, Debug)]
@@ -159,5 +452,61 @@ mod schema {
     cynic::use_schema!("schema.graphql");
 }
 
+/// A target memory address.
+///
+/// # Note
+/// The API reports addresses either as integers or as (hexadecimal) strings, both are accepted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryAddress(pub u64);
+
+impl<'de> serde::Deserialize<'de> for MemoryAddress {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        use serde::de::Error;
+
+        match serde_json::Value::deserialize(deserializer)? {
+            serde_json::Value::Number(number) => number
+                .as_u64()
+                .map(MemoryAddress)
+                .ok_or_else(|| D::Error::custom(format!("Invalid memory address: {number}"))),
+            serde_json::Value::String(string) => {
+                let parsed = match string.strip_prefix("0x") {
+                    Some(hex) => u64::from_str_radix(hex, 16),
+                    None => string.parse(),
+                };
+                parsed
+                    .map(MemoryAddress)
+                    .map_err(|_| D::Error::custom(format!("Invalid memory address: {string}")))
+            }
+            other => Err(D::Error::custom(format!("Invalid memory address: {other}"))),
+        }
+    }
+}
+
+impl serde::Serialize for MemoryAddress {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_u64(self.0)
+    }
+}
+
+/// An RFC 3339 timestamp as reported by the API.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+pub struct DateTime(pub String);
+
+impl DateTime {
+    /// The calendar date of the timestamp.
+    pub fn date(&self) -> &str {
+        self.0.split('T').next().unwrap_or(&self.0)
+    }
+}
+
 impl_scalar!(forged::Upload, schema::Upload);
+impl_scalar!(MemoryAddress, schema::MemoryAddress);
 impl_scalar!(Uuid, schema::UUID);
+impl_scalar!(DateTime, schema::DateTime);
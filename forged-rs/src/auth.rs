@@ -0,0 +1,412 @@
+use std::{
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Context};
+use base64::Engine;
+use cynic::{MutationBuilder, QueryBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::{config::ConfigFile, Client, Error};
+
+/// Sessions are refreshed when they expire within this margin.
+const REFRESH_MARGIN: Duration = Duration::from_secs(5 * 60);
+
+/// A signed-in user session.
+///
+/// Unlike provisioner tokens, user session tokens expire and are refreshed by the [`Client`]
+/// shortly before they do.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Session {
+    /// The session token.
+    pub token: String,
+    /// The HTTP URL of the forged API endpoint the session belongs to.
+    pub api_url: String,
+    /// The expiry of the token in seconds since the UNIX epoch, if it could be determined.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+}
+
+impl Session {
+    /// Create a session from a session token.
+    ///
+    /// # Args
+    /// * `token` - The session token returned by the API.
+    /// * `api_url` - The HTTP URL of the forged API endpoint the session belongs to.
+    pub fn new(token: String, api_url: String) -> Self {
+        Self {
+            expires_at: token_expiry(&token),
+            token,
+            api_url,
+        }
+    }
+
+    /// The path of the persisted session, next to the configuration file.
+    pub fn path() -> Option<PathBuf> {
+        let mut path = ConfigFile::path()?;
+        path.set_file_name("session.toml");
+        Some(path)
+    }
+
+    /// Load the persisted session.
+    ///
+    /// # Returns
+    /// The session, or `None` if nobody is signed in.
+    pub fn load() -> Result<Option<Self>, Error> {
+        let Some(path) = Self::path() else {
+            return Ok(None);
+        };
+
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(anyhow!("Failed to read session {path:?}: {error}").into()),
+        };
+
+        Ok(Some(toml::from_str(&content).with_context(|| {
+            format!("Invalid session file {path:?}")
+        })?))
+    }
+
+    /// Persist the session so that it is only readable by the current user.
+    pub fn save(&self) -> Result<PathBuf, Error> {
+        let path = Self::path().ok_or_else(|| anyhow!("Could not determine the session path"))?;
+        self.save_to(&path)?;
+        Ok(path)
+    }
+
+    /// Persist the session to a specific path.
+    fn save_to(&self, path: &std::path::Path) -> Result<(), Error> {
+        let content = toml::to_string_pretty(self).context("Failed to serialize the session")?;
+        crate::config::write_private(path, content.as_bytes())
+    }
+
+    /// Remove the persisted session.
+    ///
+    /// # Returns
+    /// `true` if a session was removed, `false` if nobody was signed in.
+    pub fn remove() -> Result<bool, Error> {
+        let Some(path) = Self::path() else {
+            return Ok(false);
+        };
+
+        match std::fs::remove_file(&path) {
+            Ok(()) => Ok(true),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(error) => Err(anyhow!("Failed to remove session {path:?}: {error}").into()),
+        }
+    }
+
+    /// Check if the session token has to be refreshed before it is used.
+    pub fn needs_refresh(&self) -> bool {
+        let Some(expires_at) = self.expires_at else {
+            return false;
+        };
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        now + REFRESH_MARGIN >= Duration::from_secs(expires_at)
+    }
+}
+
+/// Read the expiry (`exp` claim) of a JWT session token without verifying it.
+fn token_expiry(token: &str) -> Option<u64> {
+    let payload = token.split('.').nth(1)?;
+    let payload = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(payload.trim_end_matches('='))
+        .ok()?;
+    let claims: serde_json::Value = serde_json::from_slice(&payload).ok()?;
+    claims.get("exp")?.as_u64()
+}
+
+impl Client {
+    /// Create a client that acts on behalf of a signed-in user.
+    ///
+    /// # Args
+    /// * `session` - The user session, e.g. from [`Session::load`].
+    ///
+    /// # Note
+    /// The session is refreshed and persisted again shortly before it expires.
+    pub fn from_session(session: Session) -> Self {
+        Self {
+            token: session.token.clone(),
+            instance_url: session.api_url.clone(),
+            session: Some(Arc::new(tokio::sync::Mutex::new(session))),
+            ..Default::default()
+        }
+    }
+
+    /// Get the bearer token for the next request, refreshing the user session if required.
+    pub(crate) async fn bearer_token(&self) -> Result<String, Error> {
+        let Some(session) = &self.session else {
+            return Ok(self.token.clone());
+        };
+
+        let mut session = session.lock().await;
+        if session.needs_refresh() {
+            log::info!("Refreshing the user session");
+            let token = self
+                .run_query_with_token(
+                    queries::Refresh::build(queries::RefreshArguments {
+                        token: session.token.clone(),
+                        impersonate: None,
+                    }),
+                    &session.token,
+                )
+                .await?
+                .refresh;
+            *session = Session::new(token, session.api_url.clone());
+
+            if let Err(error) = session.save() {
+                log::warn!("Failed to persist the refreshed session: {error}");
+            }
+        }
+
+        Ok(session.token.clone())
+    }
+
+    /// Sign in as a user with email and password.
+    ///
+    /// # Returns
+    /// The new user session. It is not persisted automatically.
+    pub async fn sign_in(&self, email: String, password: String) -> Result<Session, Error> {
+        let token = self
+            .run_query_with_token(
+                queries::SignIn::build(queries::SignInArguments { email, password }),
+                "",
+            )
+            .await?
+            .sign_in;
+
+        Ok(Session::new(token, self.instance_url.clone()))
+    }
+
+    /// Get the URL to sign in with an OAuth provider.
+    ///
+    /// # Args
+    /// * `provider` - The OAuth provider, e.g. `google` or `github`.
+    pub async fn oauth_url(&self, provider: String) -> Result<queries::OauthData, Error> {
+        Ok(self
+            .run_query_with_token(
+                queries::OauthUrl::build(queries::OauthUrlArguments { provider }),
+                "",
+            )
+            .await?
+            .oauth_url)
+    }
+
+    /// Sign in as a user with the code returned by an OAuth provider.
+    ///
+    /// # Args
+    /// * `provider` - The OAuth provider, e.g. `google` or `github`.
+    /// * `code` - The code returned by the provider.
+    /// * `oauth` - The data of the sign in request from [`Client::oauth_url`].
+    ///
+    /// # Returns
+    /// The new user session. It is not persisted automatically.
+    pub async fn sign_in_with_oauth(
+        &self,
+        provider: String,
+        code: String,
+        oauth: &queries::OauthData,
+    ) -> Result<Session, Error> {
+        let token = self
+            .run_query_with_token(
+                queries::SignInWithOauth::build(queries::SignInWithOauthArguments {
+                    provider,
+                    code,
+                    csrf_token: Some(oauth.csrf_token.clone()),
+                    nonce: oauth.nonce.clone(),
+                }),
+                "",
+            )
+            .await?
+            .sign_in_with_oauth;
+
+        Ok(Session::new(token, self.instance_url.clone()))
+    }
+
+    /// Get the signed-in user.
+    pub async fn current_user(&self) -> Result<queries::User, Error> {
+        Ok(self
+            .run_query(queries::CurrentUser::build(()))
+            .await?
+            .current_user)
+    }
+}
+
+pub use queries::{OauthData, User, UserType};
+
+#[cynic::schema_for_derives(file = "schema.graphql", module = "schema")]
+pub mod queries {
+    use super::schema;
+    use uuid::Uuid;
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "MutationRoot", variables = "SignInArguments")]
+    pub struct SignIn {
+        #[arguments(email: $email, password: $password)]
+        pub sign_in: String,
+    }
+
+    #[derive(cynic::QueryVariables, Debug)]
+    pub struct SignInArguments {
+        pub email: String,
+        pub password: String,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "MutationRoot", variables = "RefreshArguments")]
+    pub struct Refresh {
+        #[arguments(token: $token, impersonate: $impersonate)]
+        pub refresh: String,
+    }
+
+    #[derive(cynic::QueryVariables, Debug)]
+    pub struct RefreshArguments {
+        pub token: String,
+        pub impersonate: Option<Uuid>,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "QueryRoot", variables = "OauthUrlArguments")]
+    pub struct OauthUrl {
+        #[arguments(provider: $provider)]
+        pub oauth_url: OauthData,
+    }
+
+    #[derive(cynic::QueryVariables, Debug)]
+    pub struct OauthUrlArguments {
+        pub provider: String,
+    }
+
+    #[derive(cynic::QueryFragment, Debug, Clone)]
+    pub struct OauthData {
+        pub auth_url: String,
+        pub csrf_token: String,
+        pub nonce: Option<String>,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "MutationRoot", variables = "SignInWithOauthArguments")]
+    pub struct SignInWithOauth {
+        #[arguments(provider: $provider, code: $code, csrfToken: $csrf_token, nonce: $nonce)]
+        pub sign_in_with_oauth: String,
+    }
+
+    #[derive(cynic::QueryVariables, Debug)]
+    pub struct SignInWithOauthArguments {
+        pub provider: String,
+        pub code: String,
+        pub csrf_token: Option<String>,
+        pub nonce: Option<String>,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "QueryRoot")]
+    pub struct CurrentUser {
+        pub current_user: User,
+    }
+
+    #[derive(cynic::QueryFragment, serde::Serialize, Debug, Clone)]
+    #[serde(rename_all = "camelCase")]
+    pub struct User {
+        pub id: Uuid,
+        pub email: String,
+        pub admin: bool,
+        pub user_type: UserType,
+    }
+
+    #[derive(cynic::Enum, Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum UserType {
+        Native,
+        Oauth,
+    }
+}
+
+mod schema {
+    cynic::use_schema!("schema.graphql");
+}
+
+cynic::impl_scalar!(uuid::Uuid, schema::UUID);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jwt(claims: &str) -> String {
+        let encode = |part: &str| base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(part);
+        format!(
+            "{}.{}.signature",
+            encode(r#"{"alg":"HS256","typ":"JWT"}"#),
+            encode(claims)
+        )
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    fn session(expires_at: Option<u64>) -> Session {
+        Session {
+            token: "token".to_string(),
+            api_url: "https://api.forged.dev/graphql".to_string(),
+            expires_at,
+        }
+    }
+
+    #[test]
+    fn expiry_of_a_jwt() {
+        assert_eq!(
+            token_expiry(&jwt(r#"{"sub":"user","exp":1700000000}"#)),
+            Some(1_700_000_000)
+        );
+        // Padded payloads are accepted as well.
+        let padded = jwt(r#"{"exp":42}"#).replacen(".sig", "=.sig", 1);
+        assert_eq!(token_expiry(&padded), Some(42));
+
+        let session = Session::new(jwt(r#"{"exp":42}"#), String::new());
+        assert_eq!(session.expires_at, Some(42));
+    }
+
+    #[test]
+    fn expiry_of_other_tokens() {
+        assert_eq!(token_expiry("provisioner-token"), None);
+        assert_eq!(token_expiry("a.!!!.c"), None);
+        assert_eq!(token_expiry(&jwt("not json")), None);
+        assert_eq!(token_expiry(&jwt(r#"{"sub":"user"}"#)), None);
+        assert_eq!(token_expiry(&jwt(r#"{"exp":"tomorrow"}"#)), None);
+    }
+
+    #[test]
+    fn refresh_margin() {
+        assert!(!session(None).needs_refresh());
+        assert!(!session(Some(now() + 6 * 60)).needs_refresh());
+        assert!(session(Some(now() + 4 * 60)).needs_refresh());
+        assert!(session(Some(now() - 60)).needs_refresh());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn saving_restricts_an_existing_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.toml");
+        std::fs::write(&path, "token = \"old\"\n").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+
+        session(None).save_to(&path).unwrap();
+
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        let saved: Session = toml::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved.token, "token");
+    }
+}
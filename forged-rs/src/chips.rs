@@ -1,7 +1,7 @@
-use crate::{Client, Error};
+use crate::{Client, Error, Upload};
 use anyhow::anyhow;
 use cynic::impl_scalar;
-use cynic::QueryBuilder;
+use cynic::{MutationBuilder, QueryBuilder};
 use sha2::{Digest, Sha256};
 use uuid::Uuid;
 
@@ -39,6 +39,54 @@ impl Client {
             .await
     }
 
+    /// Upload a new firmware binary for a chip.
+    ///
+    /// # Args
+    /// * `chip_id` - The ID of the chip the binary is built for.
+    /// * `version` - The semantic version of the binary, e.g. `1.2.3` or `2.0.0-rc.1`.
+    /// * `parts` - The parts that make up the binary.
+    ///
+    /// # Returns
+    /// The ID of the created binary.
+    pub async fn upload_binary(
+        &self,
+        chip_id: Uuid,
+        version: &str,
+        parts: Vec<BinaryPartUpload>,
+    ) -> Result<Uuid, Error> {
+        let version = semver::Version::parse(version)
+            .map_err(|e| anyhow!("Invalid binary version `{version}`: {e}"))?;
+        if parts.is_empty() {
+            return Err(anyhow!("A binary needs at least one part").into());
+        }
+
+        let mut files = Vec::with_capacity(parts.len());
+        let mut inputs = Vec::with_capacity(parts.len());
+        for (index, part) in parts.into_iter().enumerate() {
+            let upload = Upload::new(part.name.clone(), part.image);
+            files.push((format!("variables.parts.{index}.image"), upload.clone()));
+            inputs.push(InsertBinaryPart {
+                name: Some(part.name),
+                kind: part.kind,
+                memory_offset: part.memory_offset.map(stored_offset),
+                image: upload,
+            });
+        }
+
+        let result = self
+            .run_query_with_mapped_uploads(
+                CreateBinary::build(CreateBinaryArguments {
+                    chip_id,
+                    version: version.to_string(),
+                    parts: inputs,
+                }),
+                files,
+            )
+            .await?;
+
+        Ok(result.binary_create.id)
+    }
+
     async fn fetch_url(
         &self,
         project_id: Uuid,
@@ -87,7 +135,7 @@ impl Client {
 
         let response = reqwest::Client::new()
             .get(url)
-            .bearer_auth(self.token.clone())
+            .bearer_auth(self.bearer_token().await?)
             .send()
             .await?;
 
@@ -125,6 +173,87 @@ impl Client {
     }
 }
 
+/// A part of a binary to upload with [`Client::upload_binary`].
+#[derive(Debug, Clone)]
+pub struct BinaryPartUpload {
+    /// The file name of the part.
+    pub name: String,
+    /// The format of the part.
+    pub kind: BinaryKind,
+    /// The memory address the part is loaded at. Only used for `BIN` parts.
+    pub memory_offset: Option<u32>,
+    /// The content of the part.
+    pub image: Vec<u8>,
+}
+
+impl BinaryPartUpload {
+    /// Create a part from the content of a file, inferring its format.
+    ///
+    /// # Args
+    /// * `name` - The file name of the part.
+    /// * `image` - The content of the part.
+    /// * `memory_offset` - The memory address the part is loaded at.
+    pub fn new(name: String, image: Vec<u8>, memory_offset: Option<u32>) -> Self {
+        Self {
+            kind: BinaryKind::infer(&name, &image),
+            name,
+            memory_offset,
+            image,
+        }
+    }
+}
+
+impl BinaryKind {
+    /// Infer the format of a binary part from its file extension, or its content if the extension
+    /// is unknown.
+    ///
+    /// # Note
+    /// Content that is neither an ELF file nor Intel HEX records is considered a raw binary.
+    pub fn infer(name: &str, image: &[u8]) -> Self {
+        let extension = std::path::Path::new(name)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+
+        match extension.as_deref() {
+            Some("elf" | "axf" | "out") => BinaryKind::Elf,
+            Some("hex" | "ihex" | "ihx") => BinaryKind::Hex,
+            Some("bin") => BinaryKind::Bin,
+            _ if image.starts_with(b"\x7fELF") => BinaryKind::Elf,
+            _ if is_intel_hex(image) => BinaryKind::Hex,
+            _ => BinaryKind::Bin,
+        }
+    }
+}
+
+/// The memory offset of a part as stored by the server.
+///
+/// # Note
+/// The schema stores offsets as 32 bit signed integers, readers reinterpret them as unsigned, so
+/// offsets from `0x8000_0000` on are stored as negative numbers.
+fn stored_offset(offset: u32) -> i32 {
+    offset as i32
+}
+
+/// Check if content consists of Intel HEX records.
+fn is_intel_hex(image: &[u8]) -> bool {
+    let Ok(text) = std::str::from_utf8(image) else {
+        return false;
+    };
+
+    let mut lines = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .peekable();
+    lines.peek().is_some()
+        && lines.all(|line| {
+            line.len() >= 11
+                && line.starts_with(':')
+                && line[1..].chars().all(|c| c.is_ascii_hexdigit())
+        })
+}
+
 pub use queries::*;
 
 #[cynic::schema_for_derives(file = "schema.graphql", module = "schema")]
@@ -171,6 +300,41 @@ pub mod queries {
         pub image_hash: Vec<i32>,
     }
 
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "MutationRoot", variables = "CreateBinaryArguments")]
+    pub struct CreateBinary {
+        #[arguments(chipId: $chip_id, version: $version, parts: $parts)]
+        pub binary_create: CreatedBinary,
+    }
+
+    #[derive(cynic::QueryVariables, Debug)]
+    pub struct CreateBinaryArguments {
+        pub chip_id: Uuid,
+        pub version: String,
+        pub parts: Vec<InsertBinaryPart>,
+    }
+
+    #[derive(cynic::InputObject, Debug)]
+    pub struct InsertBinaryPart {
+        pub name: Option<String>,
+        pub kind: BinaryKind,
+        pub memory_offset: Option<i32>,
+        pub image: crate::Upload,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "Binary")]
+    pub struct CreatedBinary {
+        pub id: Uuid,
+    }
+
+    #[derive(cynic::Enum, Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum BinaryKind {
+        Elf,
+        Hex,
+        Bin,
+    }
+
     #[derive(cynic::QueryVariables, Debug)]
     pub struct PartHashArguments {
         pub chip_id: Uuid,
@@ -183,3 +347,74 @@ mod schema {
 }
 
 impl_scalar!(Uuid, schema::UUID);
+impl_scalar!(Upload, schema::Upload);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ELF: &[u8] = b"\x7fELF\x01\x01\x01\0";
+    const HEX: &[u8] =
+        b":020000040800F2\n:10000000000102030405060708090A0B0C0D0E0F78\n:00000001FF\n";
+
+    #[test]
+    fn offsets_round_trip() {
+        for offset in [
+            0,
+            0x0800_0000,
+            0x7fff_ffff,
+            0x8000_0000,
+            0x9000_0000,
+            u32::MAX,
+        ] {
+            assert_eq!(stored_offset(offset) as u32, offset);
+        }
+        assert_eq!(stored_offset(0x9000_0000), -0x7000_0000);
+    }
+
+    #[test]
+    fn infer_from_extension() {
+        assert_eq!(BinaryKind::infer("app.elf", b""), BinaryKind::Elf);
+        assert_eq!(BinaryKind::infer("app.AXF", b""), BinaryKind::Elf);
+        assert_eq!(BinaryKind::infer("app.hex", b""), BinaryKind::Hex);
+        assert_eq!(BinaryKind::infer("app.ihx", b""), BinaryKind::Hex);
+        assert_eq!(BinaryKind::infer("app.bin", b""), BinaryKind::Bin);
+        // The extension takes precedence over the content.
+        assert_eq!(BinaryKind::infer("app.bin", ELF), BinaryKind::Bin);
+        assert_eq!(BinaryKind::infer("app.hex", ELF), BinaryKind::Hex);
+    }
+
+    #[test]
+    fn infer_from_content() {
+        assert_eq!(BinaryKind::infer("firmware", ELF), BinaryKind::Elf);
+        assert_eq!(BinaryKind::infer("firmware.img", HEX), BinaryKind::Hex);
+        assert_eq!(
+            BinaryKind::infer("firmware", b"\r\n:00000001FF\r\n\r\n"),
+            BinaryKind::Hex
+        );
+    }
+
+    #[test]
+    fn infer_unknown_content() {
+        assert_eq!(BinaryKind::infer("firmware", b""), BinaryKind::Bin);
+        assert_eq!(BinaryKind::infer("firmware", b"\n\n"), BinaryKind::Bin);
+        assert_eq!(
+            BinaryKind::infer("firmware", b"\x00\x20\x00\x20\xff"),
+            BinaryKind::Bin
+        );
+        // Lines that are too short, lack the colon or contain other characters.
+        assert_eq!(BinaryKind::infer("firmware", b":00FF\n"), BinaryKind::Bin);
+        assert_eq!(
+            BinaryKind::infer("firmware", b"00000001FF00\n"),
+            BinaryKind::Bin
+        );
+        assert_eq!(
+            BinaryKind::infer("firmware", b":00000001FF\nhello world\n"),
+            BinaryKind::Bin
+        );
+        assert_eq!(
+            BinaryKind::infer("firmware", b":0000000ZFF00\n"),
+            BinaryKind::Bin
+        );
+    }
+}
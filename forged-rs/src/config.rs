@@ -0,0 +1,690 @@
+//! Configuration of the forged.dev API access.
+//!
+//! Settings are resolved from the following sources, highest precedence first:
+//!
+//! 1. Explicit overrides, e.g. command line flags.
+//! 2. The environment variables `FORGED_API_TOKEN` and `FORGED_API_URL`. The names `FORGED_TOKEN`
+//!    and `FORGED_INSTANCE_URL` are accepted as well.
+//! 3. The selected profile of the configuration file. The profile is taken from the overrides, the
+//!    `FORGED_PROFILE` environment variable or the `profile` key of the file, in that order, and
+//!    falls back to `default`.
+//! 4. Built-in defaults.
+//!
+//! A profile selected by the overrides or `FORGED_PROFILE` must exist, and the environment
+//! variables of step 2 are ignored for it. Its token is therefore never combined with a URL from
+//! the environment, or the other way around.
+//!
+//! The configuration file lives at `$XDG_CONFIG_HOME/forged/config.toml`, which defaults to
+//! `~/.config/forged/config.toml`. `FORGED_CONFIG` points to a different file.
+//!
+//! ```toml
+//! profile = "line-3"
+//!
+//! [profiles.default]
+//! token = "..."
+//!
+//! [profiles.staging]
+//! api_url = "https://api.staging.forged.dev"
+//! token = "..."
+//!
+//! [profiles.staging.flash]
+//! chip_erase = true
+//! reset_after = "run"
+//!
+//! [profiles.staging.log]
+//! preset = "zephyr"
+//! patterns = ['^(?P<timestamp>\S+) (?P<level>\w+): (?P<message>.*)$']
+//! levels = { FAIL = "ERROR" }
+//! ```
+use std::{collections::BTreeMap, fmt, path::PathBuf};
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::{logs::parser::Preset, Error, DEFAULT_API_URL};
+
+/// The name of the profile that is used if none is selected.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// The contents of the configuration file.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfigFile {
+    /// The profile that is used if none is selected explicitly.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+
+    /// The named profiles.
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+/// A named set of settings, e.g. for one station or one forged.dev instance.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Profile {
+    /// The provisioner token used to authenticate with forged.dev.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+
+    /// The HTTP URL of the forged API endpoint.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_url: Option<String>,
+
+    /// How targets are flashed.
+    #[serde(default, skip_serializing_if = "FlashSettings::is_empty")]
+    pub flash: FlashSettings,
+
+    /// How log entries are parsed from lines of text.
+    #[serde(default, skip_serializing_if = "LogSettings::is_empty")]
+    pub log: LogSettings,
+}
+
+/// How targets are flashed. Unset settings use the defaults of the flashing tool.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FlashSettings {
+    /// Erase the whole chip instead of only the sectors that are written.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chip_erase: Option<bool>,
+
+    /// Do not erase before programming. Cannot be combined with `chip_erase`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skip_erase: Option<bool>,
+
+    /// Read back the written data to verify it after programming.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verify: Option<bool>,
+
+    /// Only compare the flash contents to the binary without programming it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verify_only: Option<bool>,
+
+    /// Prepare flashing without writing anything.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dry_run: Option<bool>,
+
+    /// Restore erased bytes of partially written sectors.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep_unwritten_bytes: Option<bool>,
+
+    /// What happens to the target after programming.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reset_after: Option<ResetBehavior>,
+}
+
+impl FlashSettings {
+    /// Check if no setting is set.
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// How log entries are parsed from lines of text, see [`crate::logs::parser`].
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogSettings {
+    /// The built-in line format.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preset: Option<Preset>,
+
+    /// Regular expressions with named `level`, `message` and `timestamp` groups, tried before the
+    /// ones of the preset.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub patterns: Vec<String>,
+
+    /// Level names and the log levels they map to, e.g. `FAIL = "ERROR"`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub levels: BTreeMap<String, String>,
+}
+
+impl LogSettings {
+    /// Check if no setting is set.
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// What happens to the target after programming.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResetBehavior {
+    /// Leave the target as it is.
+    None,
+    /// Reset the target and let it run.
+    Run,
+    /// Reset the target and halt it.
+    Halt,
+}
+
+impl std::str::FromStr for ResetBehavior {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "none" => Ok(ResetBehavior::None),
+            "run" => Ok(ResetBehavior::Run),
+            "halt" => Ok(ResetBehavior::Halt),
+            _ => Err(format!(
+                "`{value}` is not a valid reset behavior. Choose from [none, run, halt]."
+            )),
+        }
+    }
+}
+
+impl ConfigFile {
+    /// The path of the configuration file.
+    ///
+    /// # Returns
+    /// The path, or `None` if no home directory could be determined.
+    pub fn path() -> Option<PathBuf> {
+        if let Ok(path) = std::env::var("FORGED_CONFIG") {
+            return Some(path.into());
+        }
+
+        let mut path = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| home::home_dir().map(|home| home.join(".config")))?;
+        path.push("forged");
+        path.push("config.toml");
+        Some(path)
+    }
+
+    /// Load the configuration file.
+    ///
+    /// # Returns
+    /// The parsed configuration. A missing file yields an empty configuration.
+    pub fn load() -> Result<Self, Error> {
+        match Self::path() {
+            Some(path) => Self::load_from(&path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Load a configuration file from a specific path.
+    pub fn load_from(path: &std::path::Path) -> Result<Self, Error> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Self::default())
+            }
+            Err(error) => {
+                return Err(anyhow!("Failed to read config file {path:?}: {error}").into())
+            }
+        };
+
+        Ok(toml::from_str(&content).with_context(|| format!("Invalid config file {path:?}"))?)
+    }
+
+    /// Save the configuration file.
+    ///
+    /// # Note
+    /// The file contains tokens, so it is only made readable by the current user.
+    pub fn save(&self) -> Result<PathBuf, Error> {
+        let path = Self::path().ok_or_else(|| anyhow!("Could not determine the config path"))?;
+        self.save_to(&path)?;
+        Ok(path)
+    }
+
+    /// Save the configuration file to a specific path.
+    pub fn save_to(&self, path: &std::path::Path) -> Result<(), Error> {
+        let content = toml::to_string_pretty(self).context("Failed to serialize the config")?;
+        write_private(path, content.as_bytes())
+    }
+
+    /// Get a profile for modification, creating it if it does not exist.
+    pub fn profile_mut(&mut self, name: &str) -> &mut Profile {
+        self.profiles.entry(name.to_string()).or_default()
+    }
+}
+
+/// Write a file that is only readable and writable by the current user.
+pub(crate) fn write_private(path: &std::path::Path, content: &[u8]) -> Result<(), Error> {
+    use std::io::Write;
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory {parent:?}"))?;
+    }
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("Failed to open {path:?}"))?;
+    // The mode only applies to new files, an existing file keeps its permissions otherwise.
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))
+            .with_context(|| format!("Failed to restrict the permissions of {path:?}"))?;
+    }
+    file.write_all(content)
+        .with_context(|| format!("Failed to write {path:?}"))?;
+
+    Ok(())
+}
+
+/// Explicit settings that take precedence over all other sources.
+#[derive(Debug, Default, Clone)]
+pub struct Overrides {
+    pub profile: Option<String>,
+    pub token: Option<String>,
+    pub api_url: Option<String>,
+}
+
+/// Where a resolved setting was taken from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Override,
+    Environment(&'static str),
+    ConfigFile,
+    Profile(String),
+    Default,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Override => write!(f, "command line"),
+            Source::Environment(name) => write!(f, "environment variable {name}"),
+            Source::ConfigFile => write!(f, "config file"),
+            Source::Profile(name) => write!(f, "profile `{name}`"),
+            Source::Default => write!(f, "default"),
+        }
+    }
+}
+
+/// A resolved setting and where it was taken from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Setting<T> {
+    pub value: T,
+    pub source: Source,
+}
+
+/// The resolved configuration.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// The name of the selected profile.
+    pub profile: Setting<String>,
+    /// The API token, if any was configured.
+    pub token: Option<Setting<String>>,
+    /// The HTTP URL of the forged API endpoint.
+    pub api_url: Setting<String>,
+    /// How targets are flashed, from the selected profile.
+    pub flash: FlashSettings,
+    /// How log entries are parsed, from the selected profile.
+    pub log: LogSettings,
+}
+
+impl Config {
+    /// Resolve the configuration from all sources.
+    ///
+    /// # Args
+    /// * `overrides` - Explicit settings, e.g. from command line flags.
+    pub fn load(overrides: &Overrides) -> Result<Self, Error> {
+        Self::resolve(&ConfigFile::load()?, overrides)
+    }
+
+    /// Resolve the configuration from an already loaded configuration file.
+    ///
+    /// # Args
+    /// * `file` - The contents of the configuration file.
+    /// * `overrides` - Explicit settings, e.g. from command line flags.
+    ///
+    /// # Returns
+    /// An error if the profile selected by `overrides` or `FORGED_PROFILE` does not exist.
+    pub fn resolve(file: &ConfigFile, overrides: &Overrides) -> Result<Self, Error> {
+        Self::resolve_with(file, overrides, |name| std::env::var(name).ok())
+    }
+
+    /// Select the profile without resolving its settings, e.g. to create it.
+    pub fn select_profile(file: &ConfigFile, overrides: &Overrides) -> Setting<String> {
+        Self::select_profile_with(file, overrides, |name| std::env::var(name).ok())
+    }
+
+    /// Resolve the configuration, looking up environment variables with `env`.
+    fn resolve_with(
+        file: &ConfigFile,
+        overrides: &Overrides,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, Error> {
+        let profile = Self::select_profile_with(file, overrides, &env);
+        let selected = file.profiles.get(&profile.value);
+        let explicit = matches!(profile.source, Source::Override | Source::Environment(_));
+        if explicit && selected.is_none() {
+            return Err(anyhow!(
+                "Profile `{}` from the {} does not exist. Available profiles: [ {} ]",
+                profile.value,
+                profile.source,
+                file.profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+            )
+            .into());
+        }
+
+        let resolve = |value: &Option<String>,
+                       variables: [&'static str; 2],
+                       field: fn(&Profile) -> &Option<String>| {
+            if let Some(value) = value {
+                return Some(Setting {
+                    value: value.clone(),
+                    source: Source::Override,
+                });
+            }
+
+            // An explicitly selected profile is used as a whole.
+            for variable in variables.into_iter().filter(|_| !explicit) {
+                if let Some(value) = env(variable) {
+                    return Some(Setting {
+                        value,
+                        source: Source::Environment(variable),
+                    });
+                }
+            }
+
+            selected
+                .and_then(|selected| field(selected).as_ref())
+                .map(|value| Setting {
+                    value: value.clone(),
+                    source: Source::Profile(profile.value.clone()),
+                })
+        };
+
+        let token = resolve(
+            &overrides.token,
+            ["FORGED_API_TOKEN", "FORGED_TOKEN"],
+            |profile| &profile.token,
+        );
+        let api_url = resolve(
+            &overrides.api_url,
+            ["FORGED_API_URL", "FORGED_INSTANCE_URL"],
+            |profile| &profile.api_url,
+        )
+        .unwrap_or_else(|| Setting {
+            value: DEFAULT_API_URL.to_string(),
+            source: Source::Default,
+        });
+
+        Ok(Self {
+            flash: selected
+                .map(|selected| selected.flash.clone())
+                .unwrap_or_default(),
+            log: selected
+                .map(|selected| selected.log.clone())
+                .unwrap_or_default(),
+            profile,
+            token,
+            api_url,
+        })
+    }
+
+    /// Select the profile, looking up environment variables with `env`.
+    fn select_profile_with(
+        file: &ConfigFile,
+        overrides: &Overrides,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Setting<String> {
+        if let Some(profile) = &overrides.profile {
+            Setting {
+                value: profile.clone(),
+                source: Source::Override,
+            }
+        } else if let Some(profile) = env("FORGED_PROFILE") {
+            Setting {
+                value: profile,
+                source: Source::Environment("FORGED_PROFILE"),
+            }
+        } else if let Some(profile) = &file.profile {
+            Setting {
+                value: profile.clone(),
+                source: Source::ConfigFile,
+            }
+        } else {
+            Setting {
+                value: DEFAULT_PROFILE.to_string(),
+                source: Source::Default,
+            }
+        }
+    }
+
+    /// The API token, or an error explaining how to configure one.
+    pub fn token(&self) -> Result<&str, Error> {
+        self.token
+            .as_ref()
+            .map(|token| token.value.as_str())
+            .ok_or_else(|| {
+                anyhow!(
+                    "No API token is configured for profile `{}`. Pass --api-token, set \
+                    FORGED_API_TOKEN or run `forged-cli config set token <TOKEN>`.",
+                    self.profile.value
+                )
+                .into()
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn file() -> ConfigFile {
+        let mut file = ConfigFile {
+            profile: Some("station".to_string()),
+            ..Default::default()
+        };
+        *file.profile_mut("station") = Profile {
+            token: Some("station-token".to_string()),
+            api_url: Some("https://station.example/graphql".to_string()),
+            flash: FlashSettings {
+                chip_erase: Some(true),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        *file.profile_mut("lab") = Profile {
+            token: Some("lab-token".to_string()),
+            ..Default::default()
+        };
+        file
+    }
+
+    fn resolve_result(
+        file: &ConfigFile,
+        overrides: &Overrides,
+        env: &[(&str, &str)],
+    ) -> Result<Config, Error> {
+        let env: HashMap<_, _> = env.iter().copied().collect();
+        Config::resolve_with(file, overrides, |name| env.get(name).map(|v| v.to_string()))
+    }
+
+    fn resolve(file: &ConfigFile, overrides: &Overrides, env: &[(&str, &str)]) -> Config {
+        resolve_result(file, overrides, env).unwrap()
+    }
+
+    fn setting(value: &str, source: Source) -> Setting<String> {
+        Setting {
+            value: value.to_string(),
+            source,
+        }
+    }
+
+    #[test]
+    fn defaults_without_any_source() {
+        let config = resolve(&ConfigFile::default(), &Overrides::default(), &[]);
+        assert_eq!(config.profile, setting(DEFAULT_PROFILE, Source::Default));
+        assert_eq!(config.token, None);
+        assert_eq!(config.api_url, setting(DEFAULT_API_URL, Source::Default));
+        assert_eq!(config.flash, FlashSettings::default());
+    }
+
+    #[test]
+    fn profile_of_the_config_file() {
+        let config = resolve(&file(), &Overrides::default(), &[]);
+        let profile = Source::Profile("station".to_string());
+        assert_eq!(config.profile, setting("station", Source::ConfigFile));
+        assert_eq!(
+            config.token,
+            Some(setting("station-token", profile.clone()))
+        );
+        assert_eq!(
+            config.api_url,
+            setting("https://station.example/graphql", profile)
+        );
+        assert_eq!(config.flash.chip_erase, Some(true));
+    }
+
+    #[test]
+    fn environment_overrides_the_config_file() {
+        let config = resolve(
+            &file(),
+            &Overrides::default(),
+            &[
+                ("FORGED_TOKEN", "old-token"),
+                ("FORGED_API_TOKEN", "env-token"),
+                ("FORGED_INSTANCE_URL", "https://env.example/graphql"),
+            ],
+        );
+        assert_eq!(config.profile, setting("station", Source::ConfigFile));
+        assert_eq!(
+            config.token,
+            Some(setting(
+                "env-token",
+                Source::Environment("FORGED_API_TOKEN")
+            ))
+        );
+        assert_eq!(
+            config.api_url,
+            setting(
+                "https://env.example/graphql",
+                Source::Environment("FORGED_INSTANCE_URL")
+            )
+        );
+        // The other settings still come from the profile.
+        assert_eq!(config.flash.chip_erase, Some(true));
+    }
+
+    #[test]
+    fn selected_profile_overrides_the_environment() {
+        let env = [
+            ("FORGED_PROFILE", "station"),
+            ("FORGED_API_TOKEN", "env-token"),
+            ("FORGED_API_URL", "https://env.example/graphql"),
+        ];
+        let config = resolve(&file(), &Overrides::default(), &env);
+        let station = Source::Profile("station".to_string());
+        assert_eq!(
+            config.profile,
+            setting("station", Source::Environment("FORGED_PROFILE"))
+        );
+        assert_eq!(
+            config.token,
+            Some(setting("station-token", station.clone()))
+        );
+        assert_eq!(
+            config.api_url,
+            setting("https://station.example/graphql", station)
+        );
+
+        // A token from the environment is not sent to the URL of the profile, or vice versa.
+        let overrides = Overrides {
+            profile: Some("lab".to_string()),
+            ..Default::default()
+        };
+        let config = resolve(&file(), &overrides, &env);
+        assert_eq!(
+            config.token,
+            Some(setting("lab-token", Source::Profile("lab".to_string())))
+        );
+        assert_eq!(config.api_url, setting(DEFAULT_API_URL, Source::Default));
+        assert_eq!(config.flash, FlashSettings::default());
+    }
+
+    #[test]
+    fn unknown_selected_profile() {
+        let overrides = Overrides {
+            profile: Some("line3".to_string()),
+            ..Default::default()
+        };
+        let Err(Error::Api(error)) = resolve_result(&file(), &overrides, &[]) else {
+            panic!("Expected an unknown profile error");
+        };
+        assert_eq!(
+            error.to_string(),
+            "Profile `line3` from the command line does not exist. Available profiles: \
+             [ lab, station ]"
+        );
+
+        let env = [("FORGED_PROFILE", "line3")];
+        assert!(resolve_result(&file(), &Overrides::default(), &env).is_err());
+
+        // Only explicitly selected profiles must exist.
+        let file = ConfigFile {
+            profile: Some("line3".to_string()),
+            ..Default::default()
+        };
+        let config = resolve(
+            &file,
+            &Overrides::default(),
+            &[("FORGED_API_TOKEN", "env-token")],
+        );
+        assert_eq!(config.token.unwrap().value, "env-token");
+    }
+
+    #[test]
+    fn overrides_take_precedence() {
+        let overrides = Overrides {
+            profile: Some("lab".to_string()),
+            token: Some("flag-token".to_string()),
+            api_url: Some("https://flag.example/graphql".to_string()),
+        };
+        let config = resolve(
+            &file(),
+            &overrides,
+            &[
+                ("FORGED_PROFILE", "station"),
+                ("FORGED_API_TOKEN", "env-token"),
+                ("FORGED_API_URL", "https://env.example/graphql"),
+            ],
+        );
+        assert_eq!(config.profile, setting("lab", Source::Override));
+        assert_eq!(config.token, Some(setting("flag-token", Source::Override)));
+        assert_eq!(
+            config.api_url,
+            setting("https://flag.example/graphql", Source::Override)
+        );
+    }
+
+    #[test]
+    fn missing_token() {
+        let config = resolve(&ConfigFile::default(), &Overrides::default(), &[]);
+        assert_eq!(config.token, None);
+        let Err(Error::Api(error)) = config.token() else {
+            panic!("Expected a missing token error");
+        };
+        assert!(error.to_string().contains("profile `default`"));
+
+        let config = resolve(&file(), &Overrides::default(), &[]);
+        assert_eq!(config.token().unwrap(), "station-token");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn saving_restricts_an_existing_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "profile = \"old\"\n").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+
+        file().save_to(&path).unwrap();
+
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(ConfigFile::load_from(&path).unwrap(), file());
+    }
+}
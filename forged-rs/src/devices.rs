@@ -0,0 +1,153 @@
+//! Listing the devices of a project.
+use cynic::QueryBuilder;
+use futures_util::Stream;
+use uuid::Uuid;
+
+use crate::{pagination::paginate, Client, Error};
+
+pub use queries::Device;
+use queries::{DevicesArguments, ProjectDevices, ProjectDevicesArguments, ProvisionerDevices};
+
+/// The number of devices requested at once.
+const PAGE_SIZE: i32 = 50;
+
+impl Client {
+    /// Stream the devices of a project, fetching them page by page.
+    ///
+    /// # Args
+    /// * `project` - The ID of the project. Defaults to the project of the provisioner.
+    /// * `filter` - A filter on the devices, as understood by the server.
+    pub fn devices(
+        &self,
+        project: Option<Uuid>,
+        filter: Option<String>,
+    ) -> impl Stream<Item = Result<Device, Error>> {
+        let client = self.clone();
+        paginate(move |after| {
+            let client = client.clone();
+            let filter = filter.clone();
+            async move { client.devices_page(project, filter, after).await }
+        })
+    }
+
+    async fn devices_page(
+        &self,
+        project: Option<Uuid>,
+        filter: Option<String>,
+        after: Option<String>,
+    ) -> Result<queries::DeviceConnection, Error> {
+        let first = Some(PAGE_SIZE);
+        Ok(match project {
+            Some(project) => {
+                self.run_query(ProjectDevices::build(ProjectDevicesArguments {
+                    project,
+                    after,
+                    first,
+                    filter,
+                }))
+                .await?
+                .project
+                .devices
+            }
+            None => {
+                self.run_query(ProvisionerDevices::build(DevicesArguments {
+                    after,
+                    first,
+                    filter,
+                }))
+                .await?
+                .current_provisioner
+                .project
+                .devices
+            }
+        })
+    }
+}
+
+#[cynic::schema_for_derives(file = "schema.graphql", module = "schema")]
+pub mod queries {
+    use serde::Serialize;
+    use uuid::Uuid;
+
+    use crate::{
+        pagination::{Page, PageInfo},
+        schema, DateTime,
+    };
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "QueryRoot", variables = "ProjectDevicesArguments")]
+    pub struct ProjectDevices {
+        #[arguments(id: $project)]
+        pub project: DevicesOfProject,
+    }
+
+    #[derive(cynic::QueryVariables, Debug)]
+    pub struct ProjectDevicesArguments {
+        pub project: Uuid,
+        pub after: Option<String>,
+        pub first: Option<i32>,
+        pub filter: Option<String>,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "Project", variables = "ProjectDevicesArguments")]
+    pub struct DevicesOfProject {
+        #[arguments(after: $after, first: $first, filter: $filter)]
+        pub devices: DeviceConnection,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "QueryRoot", variables = "DevicesArguments")]
+    pub struct ProvisionerDevices {
+        pub current_provisioner: DevicesOfProvisioner,
+    }
+
+    #[derive(cynic::QueryVariables, Debug)]
+    pub struct DevicesArguments {
+        pub after: Option<String>,
+        pub first: Option<i32>,
+        pub filter: Option<String>,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "Provisioner", variables = "DevicesArguments")]
+    pub struct DevicesOfProvisioner {
+        pub project: DevicesOfProvisionerProject,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "Project", variables = "DevicesArguments")]
+    pub struct DevicesOfProvisionerProject {
+        #[arguments(after: $after, first: $first, filter: $filter)]
+        pub devices: DeviceConnection,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    pub struct DeviceConnection {
+        pub page_info: PageInfo,
+        pub nodes: Vec<Device>,
+    }
+
+    /// A device of a project.
+    #[derive(cynic::QueryFragment, Serialize, Debug, Clone)]
+    #[serde(rename_all = "camelCase")]
+    pub struct Device {
+        pub id: Uuid,
+        pub name: Option<String>,
+        pub project_id: Uuid,
+        pub creation_date: DateTime,
+        pub change_date: DateTime,
+        /// Whether the device is active. Deactivated devices have a deletion date.
+        pub active: bool,
+        pub deletion_date: Option<DateTime>,
+    }
+
+    impl From<DeviceConnection> for Page<Device> {
+        fn from(connection: DeviceConnection) -> Self {
+            Page {
+                nodes: connection.nodes,
+                page_info: connection.page_info,
+            }
+        }
+    }
+}
@@ -1,9 +1,20 @@
 pub use cynic;
 
+pub mod auth;
 mod blocks;
 mod chips;
+pub mod config;
+pub mod devices;
+#[cfg(feature = "logger")]
+pub mod logger;
+pub mod logs;
+pub mod pagination;
+pub mod requirements;
+pub mod runs;
 
-use std::{collections::HashMap, future::Future, pin::Pin};
+pub use chips::{BinaryKind, BinaryPartUpload};
+
+use std::{collections::HashMap, future::Future, pin::Pin, sync::Arc};
 
 use cynic::{http::CynicReqwestError, GraphQlError, GraphQlResponse, Operation, QueryBuilder};
 use regex::Regex;
@@ -11,7 +22,7 @@ use reqwest::multipart;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 /// The default endpoint for the forged.dev API.
-const DEFAULT_API_URL: &str = "https://api.forged.dev";
+pub const DEFAULT_API_URL: &str = "https://api.forged.dev";
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -28,14 +39,48 @@ pub enum Error {
 }
 
 /// A client to interact with the forged.dev API.
+#[derive(Clone)]
 pub struct Client {
     token: String,
+    session: Option<Arc<tokio::sync::Mutex<auth::Session>>>,
     instance_url: String,
     cache_folder: Option<std::path::PathBuf>,
 }
 
 impl Default for Client {
+    /// Create a client from the configuration file and environment.
+    ///
+    /// # Note
+    /// See [`config`] for how the settings are resolved.
     fn default() -> Self {
+        let file = config::ConfigFile::load().unwrap_or_else(|e| {
+            log::warn!("Failed to load the config file. Using the environment only: {e}");
+            config::ConfigFile::default()
+        });
+        let overrides = config::Overrides::default();
+        match config::Config::resolve(&file, &overrides) {
+            Ok(config) => Self::from_config(&config),
+            Err(e) => {
+                // Falling back to other credentials could send them to the wrong instance.
+                log::error!("Failed to resolve the configuration. No token is used: {e}");
+                Self::from_config(&config::Config {
+                    profile: config::Config::select_profile(&file, &overrides),
+                    token: None,
+                    api_url: config::Setting {
+                        value: DEFAULT_API_URL.to_string(),
+                        source: config::Source::Default,
+                    },
+                    flash: Default::default(),
+                    log: Default::default(),
+                })
+            }
+        }
+    }
+}
+
+impl Client {
+    /// Create a client from a resolved configuration.
+    pub fn from_config(config: &config::Config) -> Self {
         // TODO: Handle non-existent home directory
         let mut cache_folder = home::home_dir().map(|mut dir| {
             dir.push(".forged");
@@ -50,19 +95,20 @@ impl Default for Client {
         }
 
         Self {
-            token: std::env::var("FORGED_API_TOKEN").unwrap_or_else(|_| {
-                log::info!("No Forged API token found");
-                "".to_string()
-            }),
-            instance_url: std::env::var("FORGED_API_URL")
-                .unwrap_or_else(|_| DEFAULT_API_URL.to_string()),
+            token: match &config.token {
+                Some(token) => token.value.clone(),
+                None => {
+                    log::info!("No Forged API token found");
+                    "".to_string()
+                }
+            },
+            session: None,
+            instance_url: config.api_url.value.clone(),
 
             cache_folder,
         }
     }
-}
 
-impl Client {
     /// Create a client to the forged.dev API.
     ///
     /// # Args
@@ -77,7 +123,11 @@ impl Client {
 
     /// Create a client to the forged.dev API.
     pub fn token(self, token: String) -> Self {
-        Self { token, ..self }
+        Self {
+            token,
+            session: None,
+            ..self
+        }
     }
 
     /// Specify a custom API endpoint for the client
@@ -94,6 +144,11 @@ impl Client {
         }
     }
 
+    /// The HTTP URL of the forged API endpoint the client talks to.
+    pub fn instance_url(&self) -> &str {
+        &self.instance_url
+    }
+
     /// Execute a query against the forged API.
     ///
     /// # Args
@@ -105,12 +160,56 @@ impl Client {
     where
         T: serde::de::DeserializeOwned + 'static,
         V: Serialize,
+    {
+        let token = self.bearer_token().await?;
+        self.run_query_with_token(operation, &token).await
+    }
+
+    /// Execute a query against the forged API with a specific bearer token.
+    async fn run_query_with_token<T, V>(
+        &self,
+        operation: Operation<T, V>,
+        token: &str,
+    ) -> Result<T, Error>
+    where
+        T: serde::de::DeserializeOwned + 'static,
+        V: Serialize,
+    {
+        self.post_graphql(&operation, token).await
+    }
+
+    /// Execute a GraphQL document that is built at runtime, e.g. with a variable number of fields.
+    ///
+    /// # Args
+    /// * `query` - The GraphQL document.
+    /// * `variables` - The variables of the document.
+    pub(crate) async fn run_document<T>(
+        &self,
+        query: String,
+        variables: serde_json::Value,
+    ) -> Result<T, Error>
+    where
+        T: DeserializeOwned + 'static,
+    {
+        let token = self.bearer_token().await?;
+        self.post_graphql(
+            &serde_json::json!({ "query": query, "variables": variables }),
+            &token,
+        )
+        .await
+    }
+
+    /// Post a GraphQL request body to the forged API.
+    async fn post_graphql<T, B>(&self, body: &B, token: &str) -> Result<T, Error>
+    where
+        T: DeserializeOwned + 'static,
+        B: Serialize,
     {
         let r = make_graphql_request(
             reqwest::Client::new()
                 .post(&self.instance_url)
-                .bearer_auth(&self.token)
-                .json(&operation),
+                .bearer_auth(token)
+                .json(body),
         )
         .await?;
 
@@ -164,12 +263,35 @@ impl Client {
         T: DeserializeOwned,
         V: Serialize,
     {
-        let mut files_map = HashMap::new();
-
         let re = Regex::new(r"\$(\w+): Upload").unwrap();
-        for cap in re.captures_iter(&operation.query) {
-            files_map.insert(files_map.len(), vec![format!("variables.{}", &cap[1])]);
-        }
+        let files = re
+            .captures_iter(&operation.query)
+            .map(|cap| format!("variables.{}", &cap[1]))
+            .zip(files)
+            .collect();
+
+        self.run_query_with_mapped_uploads(operation, files).await
+    }
+
+    /// Execute a query with file uploads at explicit variable paths to the forged API.
+    ///
+    /// # Args
+    /// * `operation` - The operation to execute.
+    /// * `files` - The files to upload alongside the query, each with the path of the variable it
+    ///   is uploaded for, e.g. `variables.parts.0.image` for an upload nested in an input object.
+    ///
+    /// # Returns
+    /// A GraphQL object representing the result of the executed query.
+    pub async fn run_query_with_mapped_uploads<T, V>(
+        &self,
+        operation: Operation<T, V>,
+        files: Vec<(String, Upload)>,
+    ) -> Result<T, Error>
+    where
+        T: DeserializeOwned,
+        V: Serialize,
+    {
+        let mut files_map = HashMap::new();
 
         let mut form = multipart::Form::new()
             // Adding just a simple text field...
@@ -179,9 +301,14 @@ impl Client {
                     .expect("Serializing this should always work. Please report this as a bug."),
             );
 
-        for (i, file) in files.into_iter().enumerate() {
+        for (i, (path, file)) in files.into_iter().enumerate() {
+            files_map.insert(i, vec![path]);
+
             let name = i.to_string();
-            let part = multipart::Part::bytes(file.content).file_name(file.name);
+            let mut part = multipart::Part::bytes(file.content).file_name(file.name);
+            if let Some(mime_type) = &file.mime_type {
+                part = part.mime_str(mime_type)?;
+            }
             form = form.part(name, part);
         }
 
@@ -194,7 +321,10 @@ impl Client {
         let r = make_graphql_request(
             reqwest::Client::new()
                 .post(&self.instance_url)
-                .header("Authorization", format!("Bearer {}", &self.token))
+                .header(
+                    "Authorization",
+                    format!("Bearer {}", self.bearer_token().await?),
+                )
                 .multipart(form),
         )
         .await?;
@@ -247,6 +377,7 @@ where
 pub struct Upload {
     pub(crate) name: String,
     pub(crate) content: Vec<u8>,
+    pub(crate) mime_type: Option<String>,
 }
 
 impl Upload {
@@ -256,7 +387,19 @@ impl Upload {
     /// * `name` - The name of the file being uploaded.
     /// * `content` - The binary content of the uploaded file.
     pub fn new(name: String, content: Vec<u8>) -> Self {
-        Self { name, content }
+        Self {
+            name,
+            content,
+            mime_type: None,
+        }
+    }
+
+    /// Set the MIME type of the upload, e.g. `text/plain`.
+    pub fn with_mime_type(self, mime_type: impl Into<String>) -> Self {
+        Self {
+            mime_type: Some(mime_type.into()),
+            ..self
+        }
     }
 }
 
@@ -268,3 +411,23 @@ impl Serialize for Upload {
         serializer.serialize_none()
     }
 }
+
+/// An RFC 3339 timestamp as reported by the API.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct DateTime(pub String);
+
+impl std::fmt::Display for DateTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// The schema of the queries whose fragments are shared between modules.
+mod schema {
+    cynic::use_schema!("schema.graphql");
+}
+
+cynic::impl_scalar!(uuid::Uuid, schema::UUID);
+cynic::impl_scalar!(DateTime, schema::DateTime);
+cynic::impl_scalar!(serde_json::Value, schema::JSON);
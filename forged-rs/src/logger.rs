@@ -0,0 +1,278 @@
+//! Forwarding of `log` records and `tracing` events to the current run.
+//!
+//! Both backends hand entries to a [`LogSink`] without waiting: if its queue is full, entries are
+//! dropped and counted by [`LogSink::dropped`] instead of blocking the caller.
+//!
+//! ```no_run
+//! # async fn example(client: forged::Client) -> Result<(), forged::Error> {
+//! use forged::{logger::ForgedLogger, logs::SinkOptions};
+//!
+//! let sink = client.log_sink(SinkOptions::default());
+//! ForgedLogger::new(&sink)
+//!     .max_level(log::LevelFilter::Info)
+//!     .install()?;
+//!
+//! log::info!("Supply voltage is {} V", 3.3);
+//!
+//! let log_ids = sink.finish().await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! For `tracing`, add a [`ForgedLayer`] to a subscriber, e.g.
+//! `tracing_subscriber::registry().with(ForgedLayer::new(&sink))`.
+use std::fmt::{self, Write};
+
+use anyhow::anyhow;
+use tracing_core::{
+    field::{Field, Visit},
+    Event, LevelFilter, Subscriber,
+};
+use tracing_subscriber::layer::{Context, Layer};
+
+use crate::{
+    logs::{LogEntry, LogSink, SinkHandle},
+    Error,
+};
+
+/// Targets that are never forwarded, since sending entries produces records of their own.
+const IGNORED_TARGETS: &[&str] = &[
+    "forged", "cynic", "reqwest", "hyper", "h2", "rustls", "tokio", "mio", "want",
+];
+
+fn is_ignored(target: &str) -> bool {
+    IGNORED_TARGETS.iter().any(|ignored| {
+        target
+            .strip_prefix(ignored)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+    })
+}
+
+/// A [`log::Log`] that forwards records to a [`LogSink`].
+///
+/// Messages are prefixed with the target of the record, e.g. `harness::power: Supply is on`.
+pub struct ForgedLogger {
+    handle: SinkHandle,
+    max_level: log::LevelFilter,
+}
+
+impl ForgedLogger {
+    /// Create a logger forwarding records of all levels to `sink`.
+    pub fn new(sink: &LogSink) -> Self {
+        Self {
+            handle: sink.handle(),
+            max_level: log::LevelFilter::Trace,
+        }
+    }
+
+    /// Only forward records up to `level`.
+    pub fn max_level(mut self, level: log::LevelFilter) -> Self {
+        self.max_level = level;
+        self
+    }
+
+    /// Install the logger as the global logger and set the global maximum level.
+    ///
+    /// # Returns
+    /// An error if a global logger is already installed.
+    pub fn install(self) -> Result<(), Error> {
+        let max_level = self.max_level;
+        log::set_boxed_logger(Box::new(self))
+            .map_err(|e| anyhow!("Could not install the logger: {e}"))?;
+        log::set_max_level(max_level);
+        Ok(())
+    }
+}
+
+impl log::Log for ForgedLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= self.max_level && !is_ignored(metadata.target())
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        self.handle.try_push(LogEntry::new(
+            record.level().as_str(),
+            format!("{}: {}", record.target(), record.args()),
+        ));
+    }
+
+    fn flush(&self) {}
+}
+
+/// A [`Layer`] that forwards `tracing` events to a [`LogSink`].
+///
+/// Messages are prefixed with the target of the event and followed by its fields, e.g.
+/// `harness::power: Supply is on voltage=3.3`.
+pub struct ForgedLayer {
+    handle: SinkHandle,
+    max_level: LevelFilter,
+}
+
+impl ForgedLayer {
+    /// Create a layer forwarding events of all levels to `sink`.
+    pub fn new(sink: &LogSink) -> Self {
+        Self {
+            handle: sink.handle(),
+            max_level: LevelFilter::TRACE,
+        }
+    }
+
+    /// Only forward events up to `level`.
+    ///
+    /// # Note
+    /// Other layers of the subscriber still see all events.
+    pub fn max_level(mut self, level: impl Into<LevelFilter>) -> Self {
+        self.max_level = level.into();
+        self
+    }
+}
+
+impl<S: Subscriber> Layer<S> for ForgedLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        if *metadata.level() > self.max_level || is_ignored(metadata.target()) {
+            return;
+        }
+
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+        self.handle.try_push(LogEntry::new(
+            metadata.level().as_str(),
+            format!(
+                "{}: {}{}",
+                metadata.target(),
+                visitor.message,
+                visitor.fields
+            ),
+        ));
+    }
+}
+
+/// Collects the message and the other fields of an event.
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+    fields: String,
+}
+
+impl Visit for MessageVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message.push_str(value);
+        } else {
+            write!(self.fields, " {}={value}", field.name())
+                .expect("Writing to a string cannot fail");
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            write!(self.message, "{value:?}")
+        } else {
+            write!(self.fields, " {}={value:?}", field.name())
+        }
+        .expect("Writing to a string cannot fail");
+    }
+}
+
+#[cfg(all(test, feature = "logger"))]
+mod tests {
+    use log::Log;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+    use crate::logs::{tests::server, SinkOptions};
+
+    fn log(logger: &ForgedLogger, level: log::Level, target: &str, message: &str) {
+        logger.log(
+            &log::Record::builder()
+                .level(level)
+                .target(target)
+                .args(format_args!("{message}"))
+                .build(),
+        );
+    }
+
+    #[test]
+    fn ignores_own_targets_and_their_modules() {
+        assert!(is_ignored("forged"));
+        assert!(is_ignored("forged::logs"));
+        assert!(is_ignored("hyper::client::pool"));
+        assert!(!is_ignored("forged_cli"));
+        assert!(!is_ignored("forgedx::power"));
+        assert!(!is_ignored("harness"));
+    }
+
+    #[tokio::test]
+    async fn logger_forwards_enabled_records() {
+        let (client, mut requests) = server().await;
+        let sink = client.log_sink(SinkOptions::default());
+        let logger = ForgedLogger::new(&sink).max_level(log::LevelFilter::Info);
+
+        log(&logger, log::Level::Warn, "harness::power", "Supply is low");
+        log(
+            &logger,
+            log::Level::Debug,
+            "harness::power",
+            "Supply is at 3.1 V",
+        );
+        log(&logger, log::Level::Error, "forged::logs", "Request failed");
+        log(&logger, log::Level::Info, "harness", "Supply is on");
+
+        assert_eq!(sink.dropped(), 0);
+        sink.finish().await.unwrap();
+        assert_eq!(
+            requests.recv().await.unwrap().1,
+            ["harness::power: Supply is low", "harness: Supply is on"]
+        );
+    }
+
+    #[tokio::test]
+    async fn layer_forwards_enabled_events_with_fields() {
+        let (client, mut requests) = server().await;
+        let sink = client.log_sink(SinkOptions::default());
+        let subscriber = tracing_subscriber::registry()
+            .with(ForgedLayer::new(&sink).max_level(tracing_core::Level::INFO));
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(target: "harness::power", voltage = 3.3, name = "psu", "Supply is on");
+            tracing::debug!(target: "harness::power", "Supply is stable");
+            tracing::warn!(target: "forged::logs", "Request failed");
+            tracing::error!(target: "harness", code = 7, "No acknowledgement");
+        });
+
+        sink.finish().await.unwrap();
+        assert_eq!(
+            requests.recv().await.unwrap().1,
+            [
+                "harness::power: Supply is on voltage=3.3 name=psu",
+                "harness: No acknowledgement code=7",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn full_or_finished_sinks_drop_entries() {
+        let (client, mut requests) = server().await;
+        let sink = client.log_sink(SinkOptions {
+            queue_size: 1,
+            ..SinkOptions::default()
+        });
+        let logger = ForgedLogger::new(&sink);
+
+        // The background task cannot receive before the next await, so the queue stays full.
+        for message in ["Supply is on", "Supply is stable", "Supply is off"] {
+            log(&logger, log::Level::Info, "harness", message);
+        }
+        assert_eq!(sink.dropped(), 2);
+
+        sink.finish().await.unwrap();
+        assert_eq!(requests.recv().await.unwrap().1, ["harness: Supply is on"]);
+        log(&logger, log::Level::Info, "harness", "Too late");
+        assert!(requests.try_recv().is_err());
+    }
+}
@@ -0,0 +1,519 @@
+//! Batched log streaming.
+//!
+//! A [`LogSink`] buffers log entries and creates them in batches, with one request carrying several
+//! `logCreate` mutations. Entries are created in the order they were pushed.
+//!
+//! ```no_run
+//! # async fn example(client: forged::Client) -> Result<(), forged::Error> {
+//! use forged::logs::{LogEntry, SinkOptions};
+//!
+//! let mut sink = client.log_sink(SinkOptions::default());
+//! for line in ["INFO booting", "INFO ready"] {
+//!     sink.push(LogEntry::new("INFO", line)).await?;
+//! }
+//! let log_ids = sink.finish().await?;
+//! # Ok(())
+//! # }
+//! ```
+pub mod parser;
+
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use anyhow::anyhow;
+use serde::Deserialize;
+use tokio::{
+    sync::mpsc,
+    task::JoinHandle,
+    time::{sleep_until, timeout_at, Instant},
+};
+use uuid::Uuid;
+
+use crate::{Client, Error};
+
+/// A log entry to create on the current run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogEntry {
+    pub level: String,
+    pub message: String,
+}
+
+impl LogEntry {
+    /// Create a log entry.
+    ///
+    /// # Args
+    /// * `level` - The level of the entry, e.g. `INFO`.
+    /// * `message` - The message of the entry.
+    pub fn new(level: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            level: level.into(),
+            message: message.into(),
+        }
+    }
+}
+
+/// When a [`LogSink`] sends its buffered entries.
+#[derive(Debug, Clone, Copy)]
+pub struct SinkOptions {
+    /// The maximum number of entries in a request. A full batch is sent right away.
+    pub batch_size: usize,
+    /// The longest time an entry is buffered before its batch is sent.
+    pub flush_interval: Duration,
+    /// The shortest time between two requests.
+    pub min_request_interval: Duration,
+    /// The number of entries that can wait for the background task. [`SinkHandle::try_push`] drops
+    /// entries beyond it.
+    pub queue_size: usize,
+}
+
+impl Default for SinkOptions {
+    fn default() -> Self {
+        Self {
+            batch_size: 100,
+            flush_interval: Duration::from_millis(500),
+            min_request_interval: Duration::from_millis(100),
+            queue_size: 1024,
+        }
+    }
+}
+
+/// Creates log entries in batches from a background task.
+///
+/// # Note
+/// Buffered entries are only guaranteed to be sent by [`LogSink::finish`]. Dropping the sink sends
+/// the remaining entries in the background, but errors and log IDs are lost.
+pub struct LogSink {
+    sender: mpsc::Sender<Message>,
+    task: Option<JoinHandle<Result<Vec<Uuid>, Error>>>,
+    dropped: Arc<AtomicUsize>,
+}
+
+/// A message to the background task of a [`LogSink`].
+enum Message {
+    Entry(LogEntry),
+    /// Send the remaining entries and stop, even if handles are still alive.
+    Finish,
+}
+
+impl LogSink {
+    /// Buffer a log entry.
+    ///
+    /// # Returns
+    /// An error if a previous batch failed. No more entries can be sent in that case.
+    pub async fn push(&mut self, entry: LogEntry) -> Result<(), Error> {
+        if self.sender.send(Message::Entry(entry)).await.is_ok() {
+            return Ok(());
+        }
+
+        // The task only stops receiving if a batch failed.
+        match self.join().await {
+            Err(error) => Err(error),
+            Ok(_) => Err(anyhow!("The log sink stopped unexpectedly").into()),
+        }
+    }
+
+    /// Create a handle that buffers entries without waiting, e.g. from synchronous code.
+    pub fn handle(&self) -> SinkHandle {
+        SinkHandle {
+            sender: self.sender.clone(),
+            dropped: self.dropped.clone(),
+        }
+    }
+
+    /// The number of entries handles dropped because the queue was full or the sink stopped.
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Send the remaining entries and stop the sink.
+    ///
+    /// # Note
+    /// Entries pushed through a [`SinkHandle`] afterwards are dropped.
+    ///
+    /// # Returns
+    /// The IDs of all created log entries, in the order they were pushed.
+    pub async fn finish(mut self) -> Result<Vec<Uuid>, Error> {
+        self.sender.send(Message::Finish).await.ok();
+        self.join().await
+    }
+
+    async fn join(&mut self) -> Result<Vec<Uuid>, Error> {
+        match self.task.take() {
+            Some(task) => task
+                .await
+                .map_err(|e| anyhow!("The log sink task failed: {e}"))?,
+            None => Err(anyhow!("The log sink already stopped").into()),
+        }
+    }
+}
+
+/// Buffers entries in a [`LogSink`] without ever waiting.
+#[derive(Clone)]
+pub struct SinkHandle {
+    sender: mpsc::Sender<Message>,
+    dropped: Arc<AtomicUsize>,
+}
+
+impl SinkHandle {
+    /// Buffer a log entry if there is room in the queue.
+    ///
+    /// # Returns
+    /// Whether the entry was buffered. Dropped entries are counted by [`LogSink::dropped`].
+    pub fn try_push(&self, entry: LogEntry) -> bool {
+        let sent = self.sender.try_send(Message::Entry(entry)).is_ok();
+        if !sent {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        sent
+    }
+}
+
+impl Client {
+    /// Start a sink that creates log entries on the current run in batches.
+    ///
+    /// # Note
+    /// The sink runs on the current Tokio runtime.
+    ///
+    /// # Args
+    /// * `options` - When buffered entries are sent.
+    pub fn log_sink(&self, options: SinkOptions) -> LogSink {
+        let batch_size = options.batch_size.max(1);
+        let (sender, receiver) = mpsc::channel(options.queue_size.max(1));
+        let task = tokio::spawn(run_sink(
+            self.clone(),
+            receiver,
+            SinkOptions {
+                batch_size,
+                ..options
+            },
+        ));
+
+        LogSink {
+            sender,
+            task: Some(task),
+            dropped: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Create several log entries on the current run in a single request.
+    ///
+    /// # Returns
+    /// The IDs of the created log entries, in the order of `entries`.
+    pub async fn create_logs(&self, entries: &[LogEntry]) -> Result<Vec<Uuid>, Error> {
+        if entries.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let (query, variables) = batch_document(entries);
+        let mut created: HashMap<String, CreatedLog> = self.run_document(query, variables).await?;
+
+        (0..entries.len())
+            .map(|index| {
+                created
+                    .remove(&format!("log{index}"))
+                    .map(|log| log.id)
+                    .ok_or_else(|| anyhow!("The server did not create log entry {index}").into())
+            })
+            .collect()
+    }
+}
+
+#[derive(Deserialize)]
+struct CreatedLog {
+    id: Uuid,
+}
+
+/// Build a mutation with one aliased `logCreate` field per entry.
+fn batch_document(entries: &[LogEntry]) -> (String, serde_json::Value) {
+    let mut definitions = Vec::with_capacity(entries.len());
+    let mut fields = String::new();
+    let mut variables = serde_json::Map::new();
+    for (index, entry) in entries.iter().enumerate() {
+        definitions.push(format!("$level{index}: String!, $message{index}: String!"));
+        write!(
+            fields,
+            " log{index}: logCreate(level: $level{index}, message: $message{index}) {{ id }}"
+        )
+        .expect("Writing to a string cannot fail");
+        variables.insert(format!("level{index}"), entry.level.clone().into());
+        variables.insert(format!("message{index}"), entry.message.clone().into());
+    }
+
+    (
+        format!(
+            "mutation CreateLogs({}) {{{fields} }}",
+            definitions.join(", ")
+        ),
+        variables.into(),
+    )
+}
+
+/// Receive entries and send them in batches until the sink finishes.
+async fn run_sink(
+    client: Client,
+    mut receiver: mpsc::Receiver<Message>,
+    options: SinkOptions,
+) -> Result<Vec<Uuid>, Error> {
+    let mut ids = Vec::new();
+    let mut pending = Vec::with_capacity(options.batch_size);
+    let mut deadline = Instant::now();
+    let mut next_request = Instant::now();
+
+    loop {
+        let received = if pending.is_empty() {
+            receiver.recv().await
+        } else {
+            match timeout_at(deadline, receiver.recv()).await {
+                Ok(received) => received,
+                // The oldest buffered entry is due.
+                Err(_) => {
+                    flush(&client, &mut pending, &mut ids, &mut next_request, &options).await?;
+                    continue;
+                }
+            }
+        };
+
+        let Some(Message::Entry(entry)) = received else {
+            break;
+        };
+        if pending.is_empty() {
+            deadline = Instant::now() + options.flush_interval;
+        }
+        pending.push(entry);
+        if pending.len() >= options.batch_size {
+            flush(&client, &mut pending, &mut ids, &mut next_request, &options).await?;
+        }
+    }
+    flush(&client, &mut pending, &mut ids, &mut next_request, &options).await?;
+
+    Ok(ids)
+}
+
+/// Send the buffered entries, waiting for the minimum interval since the previous request.
+async fn flush(
+    client: &Client,
+    pending: &mut Vec<LogEntry>,
+    ids: &mut Vec<Uuid>,
+    next_request: &mut Instant,
+    options: &SinkOptions,
+) -> Result<(), Error> {
+    if pending.is_empty() {
+        return Ok(());
+    }
+
+    sleep_until(*next_request).await;
+    ids.extend(client.create_logs(pending).await?);
+    pending.clear();
+    *next_request = Instant::now() + options.min_request_interval;
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use serde_json::{json, Value};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    use super::*;
+
+    /// A request to the test server: when it arrived and the messages of its entries.
+    pub(crate) type Request = (Instant, Vec<String>);
+
+    /// Start a GraphQL server that creates the logs of `CreateLogs` requests with consecutive IDs.
+    /// The aliases of the response are in reverse order.
+    pub(crate) async fn server() -> (Client, mpsc::UnboundedReceiver<Request>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            let mut next_id = 0;
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let request = read_request(&mut stream).await;
+                let variables = &request["variables"];
+                let messages: Vec<_> = (0..)
+                    .map_while(|index| variables[format!("message{index}")].as_str())
+                    .map(str::to_string)
+                    .collect();
+
+                let fields: Vec<_> = (0..messages.len())
+                    .map(|index| {
+                        let id = Uuid::from_u128(next_id + index as u128);
+                        format!("\"log{index}\": {{ \"id\": \"{id}\" }}")
+                    })
+                    .rev()
+                    .collect();
+                next_id += messages.len() as u128;
+                sender.send((Instant::now(), messages)).ok();
+
+                let body = format!("{{ \"data\": {{ {} }} }}", fields.join(", "));
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+                     Connection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        (Client::new("token".to_string()).api(url), receiver)
+    }
+
+    async fn read_request(stream: &mut TcpStream) -> Value {
+        let mut data = Vec::new();
+        let mut buffer = [0; 4096];
+        loop {
+            let count = stream.read(&mut buffer).await.unwrap();
+            data.extend_from_slice(&buffer[..count]);
+
+            let text = String::from_utf8_lossy(&data);
+            let Some((head, body)) = text.split_once("\r\n\r\n") else {
+                continue;
+            };
+            let length = head
+                .lines()
+                .find_map(|line| {
+                    let (name, value) = line.split_once(':')?;
+                    name.eq_ignore_ascii_case("content-length")
+                        .then(|| value.trim().parse::<usize>().unwrap())
+                })
+                .unwrap_or_default();
+            if body.len() >= length {
+                return serde_json::from_str(body).unwrap();
+            }
+        }
+    }
+
+    fn entries(count: usize) -> Vec<LogEntry> {
+        (0..count)
+            .map(|index| LogEntry::new("INFO", format!("entry {index}")))
+            .collect()
+    }
+
+    fn messages(range: std::ops::Range<usize>) -> Vec<String> {
+        range.map(|index| format!("entry {index}")).collect()
+    }
+
+    fn ids(range: std::ops::Range<u128>) -> Vec<Uuid> {
+        range.map(Uuid::from_u128).collect()
+    }
+
+    #[test]
+    fn batch_document_aliases_every_entry() {
+        let entries = [
+            LogEntry::new("WARN", "low battery"),
+            LogEntry::new("ERROR", "no \"ack\""),
+        ];
+        let (query, variables) = batch_document(&entries);
+
+        assert_eq!(
+            query,
+            "mutation CreateLogs($level0: String!, $message0: String!, $level1: String!, \
+             $message1: String!) { log0: logCreate(level: $level0, message: $message0) { id } \
+             log1: logCreate(level: $level1, message: $message1) { id } }"
+        );
+        assert_eq!(
+            variables,
+            json!({
+                "level0": "WARN",
+                "message0": "low battery",
+                "level1": "ERROR",
+                "message1": "no \"ack\"",
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn create_logs_returns_ids_in_input_order() {
+        let (client, mut requests) = server().await;
+
+        assert_eq!(client.create_logs(&entries(3)).await.unwrap(), ids(0..3));
+        assert_eq!(requests.recv().await.unwrap().1, messages(0..3));
+        assert!(client.create_logs(&[]).await.unwrap().is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sink_sends_full_batches_right_away() {
+        let (client, mut requests) = server().await;
+        let options = SinkOptions {
+            batch_size: 2,
+            flush_interval: Duration::from_secs(3600),
+            min_request_interval: Duration::from_millis(100),
+            ..SinkOptions::default()
+        };
+        let mut sink = client.log_sink(options);
+
+        let started = Instant::now();
+        for entry in entries(5) {
+            sink.push(entry).await.unwrap();
+        }
+        let (first, batch) = requests.recv().await.unwrap();
+        assert_eq!((first - started, batch), (Duration::ZERO, messages(0..2)));
+        // The next full batch waits for the minimum interval between requests.
+        let (second, batch) = requests.recv().await.unwrap();
+        assert_eq!(
+            (second - first, batch),
+            (options.min_request_interval, messages(2..4))
+        );
+
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        assert!(requests.try_recv().is_err());
+        assert_eq!(sink.finish().await.unwrap(), ids(0..5));
+        assert_eq!(requests.recv().await.unwrap().1, messages(4..5));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sink_sends_entries_after_the_flush_interval() {
+        let (client, mut requests) = server().await;
+        let options = SinkOptions {
+            batch_size: 10,
+            ..SinkOptions::default()
+        };
+        let mut sink = client.log_sink(options);
+
+        let started = Instant::now();
+        sink.push(LogEntry::new("INFO", "entry 0")).await.unwrap();
+        tokio::time::sleep(options.flush_interval / 2).await;
+        sink.push(LogEntry::new("INFO", "entry 1")).await.unwrap();
+        tokio::time::sleep(options.flush_interval / 4).await;
+        assert!(requests.try_recv().is_err());
+
+        // The interval starts with the oldest buffered entry.
+        let (sent, batch) = requests.recv().await.unwrap();
+        assert_eq!(
+            (sent - started, batch),
+            (options.flush_interval, messages(0..2))
+        );
+        assert_eq!(sink.finish().await.unwrap(), ids(0..2));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sink_sends_the_rest_on_finish() {
+        let (client, mut requests) = server().await;
+        let sink = client.log_sink(SinkOptions {
+            flush_interval: Duration::from_secs(3600),
+            ..SinkOptions::default()
+        });
+
+        let handle = sink.handle();
+        for entry in entries(3) {
+            assert!(handle.try_push(entry));
+        }
+        let started = Instant::now();
+        assert_eq!(sink.finish().await.unwrap(), ids(0..3));
+        let (sent, batch) = requests.recv().await.unwrap();
+        assert_eq!((sent - started, batch), (Duration::ZERO, messages(0..3)));
+
+        assert!(!handle.try_push(LogEntry::new("INFO", "too late")));
+    }
+}
@@ -0,0 +1,488 @@
+//! Parsing log entries from lines of text.
+//!
+//! A [`LineParser`] tries a list of regular expressions on each line. The named groups `level`,
+//! `message` and `timestamp` of the first matching expression make up the entry:
+//!
+//! * `level` is mapped to one of [`LEVELS`], either by the level table of the parser or by the
+//!   common abbreviations, e.g. `W`, `wrn` or `warning`. Numbers the level table does not map are
+//!   treated as syslog priorities. A match whose level cannot be mapped is skipped.
+//! * `message` defaults to the whole line.
+//! * `timestamp` is kept as written on the device and prefixes the message of the log entry.
+//!
+//! Lines no expression matches are searched for the first upper case level name as a whole word,
+//! e.g. `Self test ERROR: sensor missing`. Any text before the level name is omitted. Lines without
+//! any level are logged as `INFO`.
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use anyhow::anyhow;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::{config::LogSettings, logs::LogEntry, Error};
+
+/// The log levels of forged.dev, most severe first.
+pub const LEVELS: [&str; 6] = ["CRITICAL", "ERROR", "WARN", "INFO", "DEBUG", "TRACE"];
+
+/// The level of lines without a recognized level.
+const DEFAULT_LEVEL: &str = "INFO";
+
+/// Common names and abbreviations of the log levels.
+const LEVEL_ALIASES: [(&str, &str); 27] = [
+    ("CRITICAL", "CRITICAL"),
+    ("CRIT", "CRITICAL"),
+    ("FATAL", "CRITICAL"),
+    ("EMERG", "CRITICAL"),
+    ("ALERT", "CRITICAL"),
+    ("C", "CRITICAL"),
+    ("F", "CRITICAL"),
+    ("ERROR", "ERROR"),
+    ("ERR", "ERROR"),
+    ("E", "ERROR"),
+    ("WARN", "WARN"),
+    ("WARNING", "WARN"),
+    ("WRN", "WARN"),
+    ("W", "WARN"),
+    ("INFO", "INFO"),
+    ("INF", "INFO"),
+    ("NOTICE", "INFO"),
+    ("I", "INFO"),
+    ("N", "INFO"),
+    ("DEBUG", "DEBUG"),
+    ("DBG", "DEBUG"),
+    ("D", "DEBUG"),
+    ("TRACE", "TRACE"),
+    ("TRC", "TRACE"),
+    ("VERBOSE", "TRACE"),
+    ("T", "TRACE"),
+    ("V", "TRACE"),
+];
+
+/// The level names that are searched for in lines no expression matches. Only upper case names are
+/// matched, so prose such as "an error occurred" is not taken for a level.
+const KEYWORDS: &str = r"\b(CRITICAL|FATAL|ERROR|WARNING|WARN|INFO|DEBUG|TRACE)\b";
+
+/// Built-in line formats.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Preset {
+    /// A leading `[W]` or `<3>`, or the first level name in the line.
+    #[default]
+    Default,
+    /// `[2024-01-01T12:00:00Z INFO app] message` of `env_logger` and ` INFO app > message` of
+    /// `pretty_env_logger`.
+    EnvLogger,
+    /// `<34>Oct 11 22:14:15 host app: message` of syslog and `<3>[ 1.234] message` of the kernel.
+    Syslog,
+    /// `0.000123 INFO message` of `defmt-print` and `probe-rs`.
+    DefmtPrint,
+    /// Test outcomes such as `test_x.py::test_y PASSED` and the live logs of pytest.
+    Pytest,
+    /// `[00:00:01.234,567] <wrn> module: message` of the Zephyr logging subsystem.
+    Zephyr,
+}
+
+impl Preset {
+    /// All presets, in the order they are listed to users.
+    pub const ALL: [Preset; 6] = [
+        Preset::Default,
+        Preset::EnvLogger,
+        Preset::Syslog,
+        Preset::DefmtPrint,
+        Preset::Pytest,
+        Preset::Zephyr,
+    ];
+
+    /// The name of the preset in the configuration file and on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Preset::Default => "default",
+            Preset::EnvLogger => "env-logger",
+            Preset::Syslog => "syslog",
+            Preset::DefmtPrint => "defmt-print",
+            Preset::Pytest => "pytest",
+            Preset::Zephyr => "zephyr",
+        }
+    }
+
+    /// The regular expressions of the preset, tried in order.
+    fn patterns(self) -> &'static [&'static str] {
+        match self {
+            Preset::Default => &[
+                r"^\s*<(?P<level>\d{1,3})>\s*(?P<message>.*)$",
+                r"^\s*\[(?P<level>[A-Za-z]{1,8})\]:?\s*(?P<message>.*)$",
+            ],
+            Preset::EnvLogger => &[
+                r"^\[(?:(?P<timestamp>\S+)\s+)?(?P<level>ERROR|WARN|INFO|DEBUG|TRACE)\s*(?:\s[^\]]*)?\]\s?(?P<message>.*)$",
+                r"^\s*(?P<level>ERROR|WARN|INFO|DEBUG|TRACE)\s+\S+\s+>\s(?P<message>.*)$",
+            ],
+            Preset::Syslog => &[
+                r"^<(?P<level>\d{1,3})>\[\s*(?P<timestamp>\d+\.\d+)\]\s*(?P<message>.*)$",
+                r"^<(?P<level>\d{1,3})>(?:1 )?(?P<timestamp>[A-Z][a-z]{2} [ \d]\d \d{2}:\d{2}:\d{2}|\d{4}-\d{2}-\d{2}T\S+)?\s*(?P<message>.*)$",
+            ],
+            Preset::DefmtPrint => &[
+                r"^(?:(?P<timestamp>\d+(?:\.\d+)?)\s+)?(?P<level>TRACE|DEBUG|INFO|WARN|ERROR)\s+(?P<message>.*)$",
+                r"^(?:(?P<timestamp>\d+(?:\.\d+)?)\s+)?\[(?P<level>TRACE|DEBUG|INFO|WARN|ERROR)\s*\]\s+(?P<message>.*)$",
+            ],
+            Preset::Pytest => &[
+                r"^\S+::\S+.*\s(?P<level>PASSED|FAILED|ERROR|SKIPPED|XFAIL|XPASS)(?:\s+\[\s*\d+%\])?$",
+                r"^(?P<level>PASSED|FAILED|ERROR|SKIPPED|XFAIL|XPASS)\s+(?P<message>.*)$",
+                r"^(?P<timestamp>\d{4}-\d{2}-\d{2} \d{2}:\d{2}:\d{2}(?:[.,]\d+)?) \[\s*(?P<level>[A-Z]+)\s*\] (?P<message>.*)$",
+                r"^(?P<level>CRITICAL|ERROR|WARNING|INFO|DEBUG)\s+(?P<message>\S+:\S*:\d+\s.*)$",
+            ],
+            Preset::Zephyr => &[
+                r"^(?:\[(?P<timestamp>[\d:.,]+)\]\s*)?<(?P<level>err|wrn|inf|dbg)>\s*(?P<message>.*)$",
+            ],
+        }
+    }
+
+    /// The level names of the preset that are no common level names.
+    fn levels(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Preset::Pytest => &[
+                ("PASSED", "INFO"),
+                ("XFAIL", "INFO"),
+                ("FAILED", "ERROR"),
+                ("SKIPPED", "WARN"),
+                ("XPASS", "WARN"),
+            ],
+            _ => &[],
+        }
+    }
+}
+
+impl fmt::Display for Preset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Preset {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.to_ascii_lowercase().replace('_', "-");
+        Preset::ALL
+            .into_iter()
+            .find(|preset| preset.name() == value)
+            .ok_or_else(|| {
+                let names: Vec<_> = Preset::ALL.iter().map(|preset| preset.name()).collect();
+                format!(
+                    "`{value}` is not a valid log preset. Choose from [{}].",
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// A log entry parsed from a line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsedLine {
+    /// One of [`LEVELS`].
+    pub level: String,
+    pub message: String,
+    /// The timestamp the device wrote, if any.
+    pub timestamp: Option<String>,
+}
+
+impl From<ParsedLine> for LogEntry {
+    /// Convert a parsed line into a log entry. Logs have no separate timestamp, so the device
+    /// timestamp prefixes the message.
+    fn from(line: ParsedLine) -> Self {
+        let message = match line.timestamp {
+            Some(timestamp) => format!("{timestamp} {}", line.message),
+            None => line.message,
+        };
+        LogEntry::new(line.level, message)
+    }
+}
+
+/// Parses log entries from lines of text.
+#[derive(Debug, Clone)]
+pub struct LineParser {
+    patterns: Vec<Regex>,
+    levels: BTreeMap<String, String>,
+    keywords: Regex,
+}
+
+impl Default for LineParser {
+    fn default() -> Self {
+        Self::new(Preset::Default)
+    }
+}
+
+impl LineParser {
+    /// Create a parser for a built-in line format.
+    pub fn new(preset: Preset) -> Self {
+        let patterns = preset
+            .patterns()
+            .iter()
+            .map(|pattern| Regex::new(pattern).expect("Built-in patterns are valid"))
+            .collect();
+        let levels = LEVEL_ALIASES
+            .iter()
+            .chain(preset.levels())
+            .map(|(name, level)| (name.to_string(), level.to_string()))
+            .collect();
+
+        Self {
+            patterns,
+            levels,
+            keywords: Regex::new(KEYWORDS).expect("The keyword pattern is valid"),
+        }
+    }
+
+    /// Create a parser from the `log` section of a configuration profile.
+    ///
+    /// # Note
+    /// The patterns of the settings are tried before the ones of the preset, and their level table
+    /// takes precedence as well.
+    ///
+    /// # Returns
+    /// An error if a pattern is invalid or has none of the named groups, or if the level table
+    /// maps to an unknown level.
+    pub fn from_settings(settings: &LogSettings) -> Result<Self, Error> {
+        let mut parser = Self::new(settings.preset.unwrap_or_default());
+        parser.add_levels(&settings.levels)?;
+
+        let patterns = settings
+            .patterns
+            .iter()
+            .map(|pattern| parse_pattern(pattern))
+            .collect::<Result<Vec<_>, _>>()?;
+        parser.patterns.splice(0..0, patterns);
+
+        Ok(parser)
+    }
+
+    /// Map additional level names, e.g. `{ "FATAL" = "ERROR" }`.
+    ///
+    /// # Args
+    /// * `levels` - The level names, matched case-insensitively, and the [`LEVELS`] they map to.
+    pub fn add_levels(&mut self, levels: &BTreeMap<String, String>) -> Result<(), Error> {
+        for (name, level) in levels {
+            let level = level.to_ascii_uppercase();
+            if !LEVELS.contains(&level.as_str()) {
+                return Err(anyhow!(
+                    "`{level}` is not a log level. Choose from [{}].",
+                    LEVELS.join(", ")
+                )
+                .into());
+            }
+            self.levels.insert(name.to_ascii_uppercase(), level);
+        }
+        Ok(())
+    }
+
+    /// Map a level name to one of [`LEVELS`].
+    ///
+    /// # Returns
+    /// The level, or `None` if the name is unknown. Numbers the level table does not map are
+    /// syslog priorities, whose lowest three bits are the severity.
+    pub fn level(&self, name: &str) -> Option<&str> {
+        let name = name.trim();
+        if let Some(level) = self.levels.get(&name.to_ascii_uppercase()) {
+            return Some(level);
+        }
+
+        let priority = name.parse::<u16>().ok()?;
+        Some(match priority % 8 {
+            0..=2 => "CRITICAL",
+            3 => "ERROR",
+            4 => "WARN",
+            5 | 6 => "INFO",
+            _ => "DEBUG",
+        })
+    }
+
+    /// Parse a line into a log entry.
+    pub fn parse(&self, line: &str) -> ParsedLine {
+        for pattern in &self.patterns {
+            let Some(captures) = pattern.captures(line) else {
+                continue;
+            };
+            let level = match captures.name("level") {
+                Some(level) => match self.level(level.as_str()) {
+                    Some(level) => level,
+                    None => continue,
+                },
+                None => DEFAULT_LEVEL,
+            };
+            let message = captures.name("message").map_or(line, |m| m.as_str());
+
+            return ParsedLine {
+                level: level.to_string(),
+                message: message.trim().to_string(),
+                timestamp: captures
+                    .name("timestamp")
+                    .map(|timestamp| timestamp.as_str().trim().to_string())
+                    .filter(|timestamp| !timestamp.is_empty()),
+            };
+        }
+
+        if let Some(keyword) = self.keywords.find(line) {
+            if let Some(level) = self.level(keyword.as_str()) {
+                return ParsedLine {
+                    level: level.to_string(),
+                    message: line[keyword.end()..]
+                        .trim_start_matches(|c: char| c.is_whitespace() || ":]>|-".contains(c))
+                        .trim_end()
+                        .to_string(),
+                    timestamp: None,
+                };
+            }
+        }
+
+        ParsedLine {
+            level: DEFAULT_LEVEL.to_string(),
+            message: line.trim().to_string(),
+            timestamp: None,
+        }
+    }
+}
+
+/// Compile a user pattern, which needs at least one of the named groups.
+fn parse_pattern(pattern: &str) -> Result<Regex, Error> {
+    let regex = Regex::new(pattern).map_err(|e| anyhow!("Invalid log pattern `{pattern}`: {e}"))?;
+    let has_group = regex
+        .capture_names()
+        .flatten()
+        .any(|name| matches!(name, "level" | "message" | "timestamp"));
+    if !has_group {
+        return Err(anyhow!(
+            "The log pattern `{pattern}` needs a `level`, `message` or `timestamp` group"
+        )
+        .into());
+    }
+    Ok(regex)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(level: &str, message: &str, timestamp: Option<&str>) -> ParsedLine {
+        ParsedLine {
+            level: level.to_string(),
+            message: message.to_string(),
+            timestamp: timestamp.map(str::to_string),
+        }
+    }
+
+    fn levels(entries: &[(&str, &str)]) -> BTreeMap<String, String> {
+        entries
+            .iter()
+            .map(|(name, level)| (name.to_string(), level.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn level_names_are_matched_as_whole_words() {
+        let parser = LineParser::default();
+        assert_eq!(
+            parser.parse("Self test: no ERRORS found"),
+            parsed("INFO", "Self test: no ERRORS found", None)
+        );
+        assert_eq!(
+            parser.parse("Self test ERROR: sensor missing"),
+            parsed("ERROR", "sensor missing", None)
+        );
+    }
+
+    #[test]
+    fn default_preset() {
+        let parser = LineParser::default();
+        assert_eq!(
+            parser.parse("[W] battery low"),
+            parsed("WARN", "battery low", None)
+        );
+        assert_eq!(
+            parser.parse("<3>flash write failed"),
+            parsed("ERROR", "flash write failed", None)
+        );
+        assert_eq!(parser.parse("booting"), parsed("INFO", "booting", None));
+    }
+
+    #[test]
+    fn presets() {
+        let cases = [
+            (
+                Preset::EnvLogger,
+                "[2024-01-01T12:00:00Z WARN app] voltage low",
+                parsed("WARN", "voltage low", Some("2024-01-01T12:00:00Z")),
+            ),
+            (
+                Preset::Syslog,
+                "<34>Oct 11 22:14:15 host app: login failed",
+                parsed(
+                    "CRITICAL",
+                    "host app: login failed",
+                    Some("Oct 11 22:14:15"),
+                ),
+            ),
+            (
+                Preset::DefmtPrint,
+                "0.000123 DEBUG adc ready",
+                parsed("DEBUG", "adc ready", Some("0.000123")),
+            ),
+            (
+                Preset::Pytest,
+                "test_radio.py::test_tx FAILED",
+                parsed("ERROR", "test_radio.py::test_tx FAILED", None),
+            ),
+            (
+                Preset::Zephyr,
+                "[00:00:01.234,567] <wrn> adc: out of range",
+                parsed("WARN", "adc: out of range", Some("00:00:01.234,567")),
+            ),
+        ];
+
+        for (preset, line, expected) in cases {
+            assert_eq!(LineParser::new(preset).parse(line), expected, "{preset}");
+        }
+    }
+
+    #[test]
+    fn user_pattern() {
+        let settings = LogSettings {
+            preset: None,
+            patterns: vec![r"^(?P<timestamp>\d+ms) (?P<level>\w+): (?P<message>.*)$".to_string()],
+            levels: levels(&[("FAIL", "ERROR")]),
+        };
+        let parser = LineParser::from_settings(&settings).unwrap();
+        assert_eq!(
+            parser.parse("1200ms FAIL: no ack"),
+            parsed("ERROR", "no ack", Some("1200ms"))
+        );
+        // Matches with an unknown level fall through to the preset.
+        assert_eq!(
+            parser.parse("1200ms BOOT: [W] cold start"),
+            parsed("INFO", "1200ms BOOT: [W] cold start", None)
+        );
+    }
+
+    #[test]
+    fn level_table_overrides() {
+        let mut parser = LineParser::default();
+        parser
+            .add_levels(&levels(&[("w", "error"), ("3", "DEBUG")]))
+            .unwrap();
+        assert_eq!(parser.parse("[W] battery low").level, "ERROR");
+        assert_eq!(parser.parse("<3>retrying").level, "DEBUG");
+        // Other numbers are still syslog priorities.
+        assert_eq!(parser.parse("<4>retrying").level, "WARN");
+    }
+
+    #[test]
+    fn pattern_needs_a_group() {
+        assert!(parse_pattern(r"^(?P<level>\w+)").is_ok());
+        assert!(parse_pattern(r"^(\w+) (?P<other>.*)$").is_err());
+        assert!(parse_pattern(r"^(?P<level>").is_err());
+    }
+
+    #[test]
+    fn unknown_levels_are_rejected() {
+        let mut parser = LineParser::default();
+        assert!(parser.add_levels(&levels(&[("FAIL", "SEVERE")])).is_err());
+        assert_eq!(parser.level("FAIL"), None);
+    }
+}
@@ -0,0 +1,166 @@
+//! Cursor-based pagination of GraphQL connections.
+use std::future::Future;
+
+use futures_util::{stream, Stream, TryStreamExt};
+use serde::Deserialize;
+
+use crate::Error;
+
+/// Where a page of a connection ends, selected as `pageInfo` of a connection fragment.
+#[derive(cynic::QueryFragment, Debug, Clone)]
+#[cynic(schema_path = "schema.graphql", schema_module = "crate::schema")]
+pub struct PageInfo {
+    /// Whether there are more nodes after this page.
+    pub has_next_page: bool,
+    /// The cursor to fetch the next page with.
+    pub end_cursor: Option<String>,
+}
+
+/// A page of a connection.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Page<T> {
+    pub nodes: Vec<T>,
+    pub page_info: PageInfo,
+}
+
+/// Turn a connection into a stream of its nodes.
+///
+/// Pages are fetched lazily: the next page is only requested once all nodes of the previous page
+/// were consumed. The stream ends after the first error.
+///
+/// # Args
+/// * `fetch` - Fetches the page after a cursor, or the first page for `None`. The page may be any
+///   connection fragment that converts into a [`Page`].
+pub fn paginate<T, P, F, Fut>(fetch: F) -> impl Stream<Item = Result<T, Error>>
+where
+    P: Into<Page<T>>,
+    F: FnMut(Option<String>) -> Fut,
+    Fut: Future<Output = Result<P, Error>>,
+{
+    // The state holds the cursor of the next page, or `None` after the last page.
+    stream::try_unfold((fetch, Some(None)), |(mut fetch, cursor)| async move {
+        let Some(cursor) = cursor else {
+            return Ok::<_, Error>(None);
+        };
+
+        let page: Page<T> = fetch(cursor).await?.into();
+        let next = match page.page_info {
+            PageInfo {
+                has_next_page: true,
+                end_cursor: Some(cursor),
+            } => Some(Some(cursor)),
+            _ => None,
+        };
+        Ok(Some((
+            stream::iter(page.nodes.into_iter().map(Ok)),
+            (fetch, next),
+        )))
+    })
+    .try_flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::VecDeque};
+
+    use anyhow::anyhow;
+    use futures_util::StreamExt;
+
+    use super::*;
+
+    fn page(nodes: &[u32], has_next_page: bool, end_cursor: Option<&str>) -> Page<u32> {
+        Page {
+            nodes: nodes.to_vec(),
+            page_info: PageInfo {
+                has_next_page,
+                end_cursor: end_cursor.map(str::to_string),
+            },
+        }
+    }
+
+    /// Paginate over fixed pages, recording the cursors they were requested with.
+    async fn collect(
+        pages: Vec<Result<Page<u32>, Error>>,
+    ) -> (Vec<Result<u32, Error>>, Vec<Option<String>>) {
+        let pages = RefCell::new(VecDeque::from(pages));
+        let cursors = RefCell::new(Vec::new());
+        let nodes = paginate(|cursor| {
+            cursors.borrow_mut().push(cursor);
+            let page = pages.borrow_mut().pop_front().expect("No more pages");
+            async move { page }
+        })
+        .collect()
+        .await;
+        (nodes, cursors.into_inner())
+    }
+
+    fn values(nodes: Vec<Result<u32, Error>>) -> Vec<u32> {
+        nodes.into_iter().map(Result::unwrap).collect()
+    }
+
+    #[tokio::test]
+    async fn follows_the_cursors() {
+        let (nodes, cursors) = collect(vec![
+            Ok(page(&[1, 2], true, Some("a"))),
+            Ok(page(&[], true, Some("b"))),
+            Ok(page(&[3], false, Some("c"))),
+        ])
+        .await;
+
+        assert_eq!(values(nodes), [1, 2, 3]);
+        assert_eq!(
+            cursors,
+            [None, Some("a".to_string()), Some("b".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn stops_without_a_next_page() {
+        let (nodes, cursors) = collect(vec![Ok(page(&[1], false, Some("a")))]).await;
+        assert_eq!(values(nodes), [1]);
+        assert_eq!(cursors, [None]);
+    }
+
+    #[tokio::test]
+    async fn stops_without_an_end_cursor() {
+        let (nodes, cursors) = collect(vec![Ok(page(&[1, 2], true, None))]).await;
+        assert_eq!(values(nodes), [1, 2]);
+        assert_eq!(cursors, [None]);
+    }
+
+    #[tokio::test]
+    async fn stops_after_an_error() {
+        let (nodes, cursors) = collect(vec![
+            Ok(page(&[1], true, Some("a"))),
+            Err(anyhow!("The server is down").into()),
+            Ok(page(&[2], false, None)),
+        ])
+        .await;
+
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(nodes[0].as_ref().unwrap(), &1);
+        assert!(nodes[1].is_err());
+        assert_eq!(cursors.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn fetches_lazily() {
+        let pages = RefCell::new(VecDeque::from(vec![
+            page(&[1, 2], true, Some("a")),
+            page(&[3], false, None),
+        ]));
+        let requests = RefCell::new(0);
+        let nodes: Vec<_> = paginate(|_| {
+            *requests.borrow_mut() += 1;
+            let page = pages.borrow_mut().pop_front().expect("No more pages");
+            async move { Ok(page) }
+        })
+        .take(2)
+        .collect()
+        .await;
+
+        assert_eq!(values(nodes), [1, 2]);
+        assert_eq!(requests.into_inner(), 1);
+    }
+}
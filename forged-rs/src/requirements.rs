@@ -0,0 +1,263 @@
+use std::fmt;
+
+use cynic::QueryBuilder;
+
+use crate::{Client, Error};
+
+impl Client {
+    /// Get all of the requirements that apply to the current run.
+    ///
+    /// # Returns
+    /// The active requirements of the run. If there is no current run, no requirements are returned.
+    pub async fn requirements(&self) -> Result<Vec<Requirement>, Error> {
+        let result = self.run_query(QueryRequirements::build(())).await?;
+
+        let Some(run) = result.current_provisioner.current_run else {
+            return Ok(Vec::new());
+        };
+
+        Ok(run
+            .requirements
+            .into_iter()
+            .filter(|requirement| requirement.active)
+            .collect())
+    }
+}
+
+impl Requirement {
+    /// Check if a value lies within the limits of the requirement.
+    pub fn check(&self, value: f64) -> bool {
+        let lower = match self.lower_limit {
+            Some(limit) if self.inclusive_lower => value >= limit,
+            Some(limit) => value > limit,
+            None => true,
+        };
+        let upper = match self.upper_limit {
+            Some(limit) if self.inclusive_upper => value <= limit,
+            Some(limit) => value < limit,
+            None => true,
+        };
+
+        lower && upper
+    }
+}
+
+impl fmt::Display for Requirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (", self.name)?;
+        if let Some(limit) = self.lower_limit {
+            write!(
+                f,
+                "{limit} {} ",
+                if self.inclusive_lower { "<=" } else { "<" }
+            )?;
+        }
+        write!(f, "{}", self.schema.name)?;
+        if let Some(limit) = self.upper_limit {
+            write!(
+                f,
+                " {} {limit}",
+                if self.inclusive_upper { "<=" } else { "<" }
+            )?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// The result of checking a single block value against a requirement.
+#[derive(Debug, Clone)]
+pub struct Evaluation<'a> {
+    /// The requirement that was checked.
+    pub requirement: &'a Requirement,
+    /// The value that was checked.
+    pub value: f64,
+    /// Whether the value lies within the limits of the requirement.
+    pub passed: bool,
+}
+
+/// Evaluate candidate block data against a set of requirements.
+///
+/// # Args
+/// * `requirements` - The requirements to check, e.g. from [`Client::requirements`].
+/// * `schema_name` - The name of the block schema the data is created for.
+/// * `data` - The block data. Plain numbers, arrays of numbers and objects with a `value` or
+///   `values` key are supported.
+///
+/// # Returns
+/// One evaluation per numeric value and requirement bound to the schema. Data without numeric
+/// values cannot be evaluated and yields no evaluations.
+pub fn evaluate<'a>(
+    requirements: &'a [Requirement],
+    schema_name: &str,
+    data: &serde_json::Value,
+) -> Vec<Evaluation<'a>> {
+    let values = numeric_values(data);
+
+    requirements
+        .iter()
+        .filter(|requirement| requirement.schema.name == schema_name)
+        .flat_map(|requirement| {
+            values.iter().map(move |&value| Evaluation {
+                requirement,
+                value,
+                passed: requirement.check(value),
+            })
+        })
+        .collect()
+}
+
+/// Extract the numeric values from block data.
+fn numeric_values(data: &serde_json::Value) -> Vec<f64> {
+    match data {
+        serde_json::Value::Number(number) => number.as_f64().into_iter().collect(),
+        serde_json::Value::Array(values) => values.iter().flat_map(numeric_values).collect(),
+        serde_json::Value::Object(object) => object
+            .get("value")
+            .or(object.get("values"))
+            .map(numeric_values)
+            .unwrap_or_default(),
+        _ => Vec::new(),
+    }
+}
+
+pub use queries::*;
+
+#[cynic::schema_for_derives(file = "schema.graphql", module = "schema")]
+pub mod queries {
+    use crate::schema;
+    use serde::Serialize;
+    use uuid::Uuid;
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "QueryRoot")]
+    pub struct QueryRequirements {
+        pub current_provisioner: Provisioner,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "Provisioner")]
+    pub struct Provisioner {
+        pub current_run: Option<Run>,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "Run")]
+    pub struct Run {
+        pub requirements: Vec<Requirement>,
+    }
+
+    #[derive(cynic::QueryFragment, Serialize, Debug, Clone)]
+    #[cynic(graphql_type = "Requirement")]
+    #[serde(rename_all = "camelCase")]
+    pub struct Requirement {
+        pub id: Uuid,
+        pub name: String,
+        pub description: Option<String>,
+        pub active: bool,
+        pub lower_limit: Option<f64>,
+        pub upper_limit: Option<f64>,
+        pub inclusive_lower: bool,
+        pub inclusive_upper: bool,
+        pub schema: BlockSchema,
+    }
+
+    #[derive(cynic::QueryFragment, Serialize, Debug, Clone)]
+    #[cynic(graphql_type = "BlockSchema")]
+    pub struct BlockSchema {
+        pub name: String,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn requirement(schema: &str, lower: Option<f64>, upper: Option<f64>) -> Requirement {
+        Requirement {
+            id: Uuid::nil(),
+            name: format!("{schema} in range"),
+            description: None,
+            active: true,
+            lower_limit: lower,
+            upper_limit: upper,
+            inclusive_lower: true,
+            inclusive_upper: true,
+            schema: BlockSchema {
+                name: schema.to_string(),
+            },
+        }
+    }
+
+    #[test]
+    fn inclusive_bounds() {
+        let requirement = requirement("vdd", Some(3.0), Some(3.6));
+        assert!(requirement.check(3.0));
+        assert!(requirement.check(3.6));
+        assert!(!requirement.check(2.99));
+        assert!(!requirement.check(3.61));
+    }
+
+    #[test]
+    fn exclusive_bounds() {
+        let requirement = Requirement {
+            inclusive_lower: false,
+            inclusive_upper: false,
+            ..requirement("vdd", Some(3.0), Some(3.6))
+        };
+        assert!(!requirement.check(3.0));
+        assert!(!requirement.check(3.6));
+        assert!(requirement.check(3.3));
+    }
+
+    #[test]
+    fn open_ended_limits() {
+        let at_least = requirement("vdd", Some(3.0), None);
+        assert!(at_least.check(f64::MAX));
+        assert!(!at_least.check(2.0));
+
+        let at_most = requirement("current", None, Some(0.5));
+        assert!(at_most.check(-10.0));
+        assert!(!at_most.check(0.6));
+
+        assert!(requirement("any", None, None).check(0.0));
+    }
+
+    #[test]
+    fn evaluate_values() {
+        let requirements = [requirement("vdd", Some(3.0), Some(3.6))];
+
+        let evaluations = evaluate(&requirements, "vdd", &json!({ "values": [3.3, 4.0] }));
+        let results: Vec<_> = evaluations
+            .iter()
+            .map(|evaluation| (evaluation.value, evaluation.passed))
+            .collect();
+        assert_eq!(results, [(3.3, true), (4.0, false)]);
+        assert_eq!(evaluate(&requirements, "vdd", &json!(3.1)).len(), 1);
+        assert_eq!(
+            evaluate(&requirements, "vdd", &json!({ "value": 3.1 })).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn evaluate_skips_other_schemas() {
+        let requirements = [requirement("vdd", Some(3.0), Some(3.6))];
+        assert!(evaluate(&requirements, "current", &json!(3.3)).is_empty());
+    }
+
+    #[test]
+    fn evaluate_skips_non_numeric_data() {
+        let requirements = [requirement("vdd", Some(3.0), Some(3.6))];
+        for data in [
+            json!("3.3"),
+            json!(null),
+            json!({ "voltage": 3.3 }),
+            json!(["high"]),
+        ] {
+            assert!(evaluate(&requirements, "vdd", &data).is_empty(), "{data}");
+        }
+    }
+}
@@ -0,0 +1,368 @@
+//! Fetching the records of a run, e.g. to analyze a failed device after the fact.
+use anyhow::anyhow;
+use cynic::QueryBuilder;
+use uuid::Uuid;
+
+use crate::{Client, Error};
+
+pub use queries::{
+    AttachmentInfo, BlockSchemaName, RequirementCheck, RunBlock, RunDetails, RunLog, RunStep, Step,
+    StepStatus,
+};
+use queries::{DeviceRunArguments, RunArguments, RunAttachments};
+
+/// The run to fetch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RunRef {
+    /// The current run of the provisioner.
+    #[default]
+    Current,
+    /// A run of the current device of the provisioner.
+    Id(Uuid),
+    /// A run of any device of the project. Without a run ID, the most recent run of the device.
+    Device {
+        device_id: Uuid,
+        run_id: Option<Uuid>,
+    },
+}
+
+impl From<Uuid> for RunRef {
+    fn from(run_id: Uuid) -> Self {
+        RunRef::Id(run_id)
+    }
+}
+
+impl RunRef {
+    /// The error if the run does not exist.
+    fn missing(&self) -> Error {
+        match self {
+            RunRef::Current => anyhow!("The provisioner has no current run"),
+            RunRef::Id(_) => anyhow!("The provisioner has no current device"),
+            RunRef::Device { device_id, .. } => anyhow!("Device {device_id} has no runs"),
+        }
+        .into()
+    }
+}
+
+/// An attachment of a run.
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    pub id: Uuid,
+    pub name: String,
+    pub mime_type: String,
+    pub data: Vec<u8>,
+}
+
+impl Client {
+    /// Get the steps, logs, blocks, requirement checks and errors of a run.
+    ///
+    /// # Args
+    /// * `run` - The run to fetch, e.g. a run ID of the current device.
+    pub async fn run_details(&self, run: impl Into<RunRef>) -> Result<RunDetails, Error> {
+        let run = run.into();
+        let details = match run {
+            RunRef::Current => {
+                self.run_query(details::Current::build(()))
+                    .await?
+                    .current_provisioner
+                    .current_run
+            }
+            RunRef::Id(run_id) => self
+                .run_query(details::OfCurrentDevice::build(RunArguments {
+                    run: Some(run_id),
+                }))
+                .await?
+                .current_provisioner
+                .current_device
+                .map(|device| device.run),
+            RunRef::Device { device_id, run_id } => Some(
+                self.run_query(details::OfDevice::build(DeviceRunArguments {
+                    device: device_id,
+                    run: run_id,
+                }))
+                .await?
+                .current_provisioner
+                .project
+                .device
+                .run,
+            ),
+        };
+
+        details.ok_or_else(|| run.missing())
+    }
+
+    /// Get the attachments of a run, including their contents.
+    ///
+    /// # Args
+    /// * `run` - The run to fetch, e.g. a run ID of the current device.
+    pub async fn attachments(&self, run: impl Into<RunRef>) -> Result<Vec<Attachment>, Error> {
+        let run = run.into();
+        let attachments = match run {
+            RunRef::Current => {
+                self.run_query(attachments::Current::build(()))
+                    .await?
+                    .current_provisioner
+                    .current_run
+            }
+            RunRef::Id(run_id) => self
+                .run_query(attachments::OfCurrentDevice::build(RunArguments {
+                    run: Some(run_id),
+                }))
+                .await?
+                .current_provisioner
+                .current_device
+                .map(|device| device.run),
+            RunRef::Device { device_id, run_id } => Some(
+                self.run_query(attachments::OfDevice::build(DeviceRunArguments {
+                    device: device_id,
+                    run: run_id,
+                }))
+                .await?
+                .current_provisioner
+                .project
+                .device
+                .run,
+            ),
+        };
+
+        let RunAttachments { attachments } = attachments.ok_or_else(|| run.missing())?;
+        Ok(attachments.into_iter().map(Attachment::from).collect())
+    }
+}
+
+impl From<queries::AttachmentData> for Attachment {
+    fn from(attachment: queries::AttachmentData) -> Self {
+        Self {
+            id: attachment.id,
+            name: attachment.name,
+            mime_type: attachment.mime_type,
+            // The API encodes bytes as a list of integers, which may be signed.
+            data: attachment.data.into_iter().map(|byte| byte as u8).collect(),
+        }
+    }
+}
+
+#[cynic::schema_for_derives(file = "schema.graphql", module = "schema")]
+pub mod queries {
+    use serde::Serialize;
+    use uuid::Uuid;
+
+    use crate::{requirements::Requirement, schema, DateTime};
+
+    #[derive(cynic::QueryVariables, Debug)]
+    pub struct RunArguments {
+        pub run: Option<Uuid>,
+    }
+
+    #[derive(cynic::QueryVariables, Debug)]
+    pub struct DeviceRunArguments {
+        pub device: Uuid,
+        pub run: Option<Uuid>,
+    }
+
+    /// The status of a run or one of its steps.
+    #[derive(cynic::Enum, Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum StepStatus {
+        Pending,
+        Pass,
+        Fail,
+    }
+
+    /// Everything recorded on a run, except for the contents of its attachments.
+    #[derive(cynic::QueryFragment, Serialize, Debug, Clone)]
+    #[cynic(graphql_type = "Run")]
+    #[serde(rename_all = "camelCase")]
+    pub struct RunDetails {
+        pub id: Uuid,
+        pub device_id: Uuid,
+        pub station: String,
+        pub creation_date: DateTime,
+        pub finished: bool,
+        pub run_status: StepStatus,
+        /// The reasons the run failed.
+        pub errors: Vec<String>,
+        pub steps: Vec<RunStep>,
+        pub logs: Vec<RunLog>,
+        pub blocks: Vec<RunBlock>,
+        pub requirement_checks: Vec<RequirementCheck>,
+        pub attachments: Vec<AttachmentInfo>,
+    }
+
+    /// A step of the playbook, as executed on a run.
+    #[derive(cynic::QueryFragment, Serialize, Debug, Clone)]
+    #[serde(rename_all = "camelCase")]
+    pub struct RunStep {
+        pub status: StepStatus,
+        pub station: String,
+        pub creation_date: DateTime,
+        pub change_date: DateTime,
+        pub step: Step,
+    }
+
+    /// A step of the playbook.
+    #[derive(cynic::QueryFragment, Serialize, Debug, Clone)]
+    pub struct Step {
+        pub label: Option<String>,
+        pub position: i32,
+    }
+
+    /// A log entry of a run.
+    #[derive(cynic::QueryFragment, Serialize, Debug, Clone)]
+    #[cynic(graphql_type = "Log")]
+    #[serde(rename_all = "camelCase")]
+    pub struct RunLog {
+        pub id: Uuid,
+        pub level: String,
+        pub message: String,
+        pub creation_date: DateTime,
+    }
+
+    /// A block created on a run.
+    #[derive(cynic::QueryFragment, Serialize, Debug, Clone)]
+    #[cynic(graphql_type = "Block")]
+    #[serde(rename_all = "camelCase")]
+    pub struct RunBlock {
+        pub id: Uuid,
+        pub schema: BlockSchemaName,
+        pub data_decoded: serde_json::Value,
+    }
+
+    /// The name of a block schema.
+    #[derive(cynic::QueryFragment, Serialize, Debug, Clone)]
+    #[cynic(graphql_type = "BlockSchema")]
+    pub struct BlockSchemaName {
+        pub name: String,
+    }
+
+    /// The result of checking a block of a run against a requirement.
+    #[derive(cynic::QueryFragment, Serialize, Debug, Clone)]
+    #[serde(rename_all = "camelCase")]
+    pub struct RequirementCheck {
+        pub passed: bool,
+        pub check_date: DateTime,
+        pub block_id: Option<Uuid>,
+        pub requirement: Requirement,
+    }
+
+    /// An attachment of a run, without its contents.
+    #[derive(cynic::QueryFragment, Serialize, Debug, Clone)]
+    #[cynic(graphql_type = "Attachment")]
+    #[serde(rename_all = "camelCase")]
+    pub struct AttachmentInfo {
+        pub id: Uuid,
+        pub name: String,
+        pub mime_type: String,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "Run")]
+    pub struct RunAttachments {
+        pub attachments: Vec<AttachmentData>,
+    }
+
+    #[derive(cynic::QueryFragment, Debug)]
+    #[cynic(graphql_type = "Attachment")]
+    pub struct AttachmentData {
+        pub id: Uuid,
+        pub name: String,
+        pub mime_type: String,
+        pub data: Vec<i32>,
+    }
+}
+
+/// Queries selecting a run fragment on the current run, a run of the current device or a run of
+/// any device, see [`RunRef`].
+///
+/// # Note
+/// The `$` token is passed in as `$d`, since GraphQL variables would be taken for macro variables.
+macro_rules! run_queries {
+    ($module:ident, $run:ident, $d:tt) => {
+        #[cynic::schema_for_derives(file = "schema.graphql", module = "schema")]
+        mod $module {
+            use super::queries::{DeviceRunArguments, RunArguments};
+            use crate::schema;
+
+            type Run = super::queries::$run;
+
+            #[derive(cynic::QueryFragment, Debug)]
+            #[cynic(graphql_type = "QueryRoot")]
+            pub struct Current {
+                pub current_provisioner: CurrentProvisioner,
+            }
+
+            #[derive(cynic::QueryFragment, Debug)]
+            #[cynic(graphql_type = "Provisioner")]
+            pub struct CurrentProvisioner {
+                pub current_run: Option<Run>,
+            }
+
+            #[derive(cynic::QueryFragment, Debug)]
+            #[cynic(graphql_type = "QueryRoot", variables = "RunArguments")]
+            pub struct OfCurrentDevice {
+                pub current_provisioner: CurrentDeviceProvisioner,
+            }
+
+            #[derive(cynic::QueryFragment, Debug)]
+            #[cynic(graphql_type = "Provisioner", variables = "RunArguments")]
+            pub struct CurrentDeviceProvisioner {
+                pub current_device: Option<CurrentDevice>,
+            }
+
+            #[derive(cynic::QueryFragment, Debug)]
+            #[cynic(graphql_type = "Device", variables = "RunArguments")]
+            pub struct CurrentDevice {
+                #[arguments(id: $d run)]
+                pub run: Run,
+            }
+
+            #[derive(cynic::QueryFragment, Debug)]
+            #[cynic(graphql_type = "QueryRoot", variables = "DeviceRunArguments")]
+            pub struct OfDevice {
+                pub current_provisioner: ProjectProvisioner,
+            }
+
+            #[derive(cynic::QueryFragment, Debug)]
+            #[cynic(graphql_type = "Provisioner", variables = "DeviceRunArguments")]
+            pub struct ProjectProvisioner {
+                pub project: Project,
+            }
+
+            #[derive(cynic::QueryFragment, Debug)]
+            #[cynic(variables = "DeviceRunArguments")]
+            pub struct Project {
+                #[arguments(id: $d device)]
+                pub device: Device,
+            }
+
+            #[derive(cynic::QueryFragment, Debug)]
+            #[cynic(variables = "DeviceRunArguments")]
+            pub struct Device {
+                #[arguments(id: $d run)]
+                pub run: Run,
+            }
+        }
+    };
+}
+
+run_queries!(details, RunDetails, $);
+run_queries!(attachments, RunAttachments, $);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attachment_bytes_may_be_signed() {
+        let attachment: queries::AttachmentData = serde_json::from_value(serde_json::json!({
+            "id": "11111111-1111-1111-1111-111111111111",
+            "name": "trace.bin",
+            "mimeType": "application/octet-stream",
+            "data": [0, 127, 128, 255, -1, -128],
+        }))
+        .unwrap();
+
+        let attachment = Attachment::from(attachment);
+        assert_eq!(attachment.data, [0, 127, 128, 255, 255, 128]);
+        assert_eq!(attachment.mime_type, "application/octet-stream");
+    }
+}